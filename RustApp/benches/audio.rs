@@ -53,7 +53,7 @@ fn bench_process(c: &mut Criterion) {
 
     let audio_params = AudioProcessParams {
         target_format: AudioPacketFormat {
-            sample_rate: SampleRate::S48000,
+            sample_rate: SampleRate::from_number(48000).unwrap(),
             audio_format: AudioFormat::I16,
             channel_count: ChannelCount::Mono,
         },
@@ -129,7 +129,7 @@ fn bench_speexdsp(c: &mut Criterion) {
 
     let audio_params = AudioProcessParams {
         target_format: AudioPacketFormat {
-            sample_rate: SampleRate::S48000,
+            sample_rate: SampleRate::from_number(48000).unwrap(),
             audio_format: AudioFormat::I16,
             channel_count: ChannelCount::Mono,
         },
@@ -1,13 +1,40 @@
 audio_device = Audio Device
 settings = Settings
+output_format = Output: { $format }
+output_device_lost = Output device lost, waiting for a device to come back
+output_device_fallback = Playing on a fallback device
 network_adapter = Network adapter
+bind_all_interfaces = Listen on all interfaces
+reachable_addresses = The phone can connect to
+extra_adapters = Also listen on
+usb_device = USB device
+usb_no_device = No Android device plugged
+usb_device_missing = The selected device is not plugged
+usb_choose_device = Several Android devices are plugged, choose one
+adb_device = ADB device
+adb_no_device = No device attached, plug the phone and enable usb debugging
+adb_unauthorized = Unlock the phone and accept the usb debugging prompt
+adb_device_missing = The selected device is not attached
+adb_wireless = Wireless debugging
+adb_wireless_connect = Connect
+adb_wireless_forget = Forget
+adb_pair_address = Pairing address (ip:port)
+adb_pair_code = Pairing code
+adb_pair = Pair
+adb_discover = Discover
+adb_no_pairing_service = No phone is waiting to be paired, open "Pair device with pairing code" in the wireless debugging settings of the phone
+replay_file = Audio file
+replay_file_placeholder = Path of a wav, flac or capture file
+replay_loop = Loop
 port = Port
 save = Save
+delete = Delete
 connection = Connection
 connection_tcp = WIFI / LAN (TCP)
 connection_udp = WIFI / LAN (UDP)
 connection_usb = USB Serial
 connection_adb = USB Adb
+connection_file = File replay
 none = None
 
 tray_show_window = Show Window
@@ -15,15 +42,38 @@ tray_connect = Connect
 tray_disconnect = Disconnect
 tray_exit = Exit
 minimized_to_tray = Application is minimized to system tray
+notification_connected = Connected on { $address }
+notification_disconnected = The phone disconnected
+notification_buffer_overrun = Audio was dropped, the output device doesn't keep up
 
 state_disconnected = Disconnected
 state_listening = Listening
 state_connected = Connected
+state_reconnecting = Reconnecting
 
 connect = Connect
 listening = Listening...
 disconnect = Disconnect
 waiting = Waiting...
+connected_peer = Phone: { $peer }
+rtt = Round trip: { $rtt } ms
+rejected_datagrams = Ignored { $count } datagrams, last from { $sender }
+
+phone = Phone
+phone_mute = Mute microphone
+phone_mic_source = Microphone
+phone_stop_streaming = Stop streaming
+phone_summary = { $name }, battery { $level }%
+phone_summary_charging = { $name }, battery { $level }% (charging)
+phone_app_version = App version { $version }
+phone_battery_low = Phone battery is low ({ $level }%) after { $minutes } minutes of streaming
+send_format_to_phone = Apply to the phone
+mic_source_default = Default
+mic_source_mic = Microphone
+mic_source_camcorder = Camcorder
+mic_source_voice_recognition = Voice recognition
+mic_source_voice_communication = Voice communication
+mic_source_unprocessed = Unprocessed
 
 title_audio_format = Audio format
 sample_rate = Sample rate
@@ -31,7 +81,17 @@ channel_count = Channel count
 audio_format = Audio format
 use_recommended_audio_format = Use Recommended Audio Format
 
+title_output_device = Output device
+preferred_devices = Preferred devices, in order (names or patterns with *, comma separated)
+preferred_devices_placeholder = Headphones*, Speakers
+
+allowed_subnets = Allowed networks (comma separated, empty allows all)
+allowed_subnets_placeholder = 192.168.1.0/24, fd00::/8
+
 title_connection = Connection
+keep_listening = Keep listening after disconnect
+reconnect_max_retries = Retries on errors
+capture_traffic = Capture the received traffic, to replay it in the file mode
 
 denoise = Noise reduction
 denoise_enabled = Enabled
@@ -48,6 +108,25 @@ dereverberation_enabled = Enabled
 
 reset_denoise_settings = Reset Denoise Settings
 
+title_profiles = Profiles
+profile = Profile
+profile_name = Save the current audio, processing and connection settings as
+profile_name_placeholder = meeting
+profile_file = Share a profile as a toml file
+profile_file_placeholder = Path of the profile file
+profile_import = Import
+profile_export = Export
+
+title_notifications = Notifications
+notify_only_when_hidden = Only when the window is hidden
+notify_connected = Phone connected
+notify_disconnected = Phone disconnected
+notify_error = Errors
+notify_device_lost = Output device lost
+notify_buffer_overrun = Audio dropped
+notify_low_battery = Low phone battery
+notify_minimized_to_tray = Minimized to the tray
+
 title_app = App
 
 start_at_login = Start at login
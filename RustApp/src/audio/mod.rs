@@ -1,4 +1,6 @@
 #![allow(clippy::needless_range_loop)]
+use std::fmt::Display;
+
 use byteorder::{ByteOrder, NativeEndian, WriteBytesExt};
 use cpal::traits::StreamTrait;
 use rtrb::Consumer;
@@ -83,8 +85,12 @@ impl AppState {
             channel_count: config.channel_count,
         };
 
-        let (stream, final_audio_config) =
-            player::create_audio_stream(device, wanted_audio_config, consumer)?;
+        let (stream, final_audio_config) = player::create_audio_stream(
+            device,
+            wanted_audio_config,
+            consumer,
+            self.output_stream_sender.clone(),
+        )?;
 
         if auto_play {
             if let Err(e) = stream.play() {
@@ -284,9 +290,19 @@ impl AudioBytes for u32 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AudioPacketFormat {
     pub sample_rate: SampleRate,
     pub audio_format: AudioFormat,
     pub channel_count: ChannelCount,
 }
+
+impl Display for AudioPacketFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} Hz, {}, {}",
+            self.sample_rate, self.audio_format, self.channel_count
+        )
+    }
+}
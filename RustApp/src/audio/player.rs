@@ -1,72 +1,138 @@
+use std::sync::Arc;
+
 use anyhow::bail;
+use cosmic::iced::{futures::Stream, stream};
 use cpal::traits::DeviceTrait;
+use futures::SinkExt;
 use rtrb::{Consumer, chunks::ChunkError};
+use tokio::sync::{Mutex, mpsc};
 
 use crate::config::{AudioFormat, ChannelCount, SampleRate};
 
 use super::{AudioBytes, AudioPacketFormat};
 
+/// Rank the configs supported by the device against the wanted one, and return the best match.
+///
+/// Preference order: same sample rate, then higher bit depth, then same channel count,
+/// then same audio format.
+pub fn negotiate_output_format(
+    device: &cpal::Device,
+    wanted: &AudioPacketFormat,
+) -> anyhow::Result<AudioPacketFormat> {
+    let sample_rate = wanted.sample_rate.to_number();
+
+    let supported = device
+        .supported_output_configs()?
+        .filter_map(|supported_config| {
+            let audio_format = AudioFormat::from_cpal_format(supported_config.sample_format())?;
+            let channel_count = ChannelCount::from_number(supported_config.channels())?;
+
+            let min = supported_config.min_sample_rate();
+            let max = supported_config.max_sample_rate();
+
+            let format = AudioPacketFormat {
+                sample_rate: SampleRate::from_number(sample_rate.clamp(min, max))?,
+                audio_format,
+                channel_count,
+            };
+            Some(format)
+        });
+
+    match best_output_format(supported, wanted) {
+        Some(format) => Ok(format),
+        None => bail!("The output device doesn't support any usable audio format."),
+    }
+}
+
+fn best_output_format(
+    supported: impl IntoIterator<Item = AudioPacketFormat>,
+    wanted: &AudioPacketFormat,
+) -> Option<AudioPacketFormat> {
+    let sample_rate = wanted.sample_rate.to_number();
+    let channel_count = wanted.channel_count.to_number();
+
+    supported.into_iter().max_by_key(|format| {
+        (
+            format.sample_rate == wanted.sample_rate,
+            // closest rate when the wanted one is not supported
+            std::cmp::Reverse(format.sample_rate.to_number().abs_diff(sample_rate)),
+            format.audio_format.sample_size(),
+            format.channel_count.to_number() == channel_count,
+            format.audio_format == wanted.audio_format,
+        )
+    })
+}
+
+/// Events reported by the error callback of an output stream
+#[derive(Debug, Clone)]
+pub enum OutputStreamEvent {
+    DeviceLost,
+    Error(String),
+}
+
+#[derive(Clone)]
+pub struct OutputStreamEvents {
+    receiver: Arc<Mutex<mpsc::UnboundedReceiver<OutputStreamEvent>>>,
+}
+
+impl OutputStreamEvents {
+    pub fn new() -> (mpsc::UnboundedSender<OutputStreamEvent>, Self) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        (
+            sender,
+            Self {
+                receiver: Arc::new(Mutex::new(receiver)),
+            },
+        )
+    }
+
+    pub fn sub(self) -> impl Stream<Item = OutputStreamEvent> {
+        let receiver_arc = self.receiver.clone();
+
+        stream::channel(1, |mut sender| async move {
+            loop {
+                let mut receiver = receiver_arc.lock().await;
+                if let Some(event) = receiver.recv().await {
+                    if sender.send(event).await.is_err() {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+        })
+    }
+}
+
 pub fn create_audio_stream(
     device: &cpal::Device,
     config: AudioPacketFormat,
     consumer: Consumer<u8>,
+    events: mpsc::UnboundedSender<OutputStreamEvent>,
 ) -> anyhow::Result<(cpal::Stream, AudioPacketFormat)> {
-    let audio_format = config.audio_format.clone();
-    let sample_rate = config.sample_rate.to_number();
-    let mut channel_count = config.channel_count.to_number();
-
-    // check if config is supported by device
-    let supported_configs = device.supported_output_configs()?;
-
-    let mut supported = false;
-    for supported_config in supported_configs {
-        if audio_format == supported_config.sample_format()
-            && supported_config.max_sample_rate() >= sample_rate
-            && supported_config.min_sample_rate() <= sample_rate
-        {
-            // use recommended channel count
-            if supported_config.channels() != channel_count {
-                warn!(
-                    "Using channel count {} instead of {}",
-                    supported_config.channels(),
-                    channel_count
-                );
-                channel_count = supported_config.channels();
-            }
-            supported = true;
-            break;
-        }
-    }
+    let negotiated = negotiate_output_format(device, &config)?;
 
-    if !supported {
-        bail!(
-            "Unsupported output audio format or sample rate. Please apply recommended format from settings page."
-        );
+    if negotiated != config {
+        warn!("Using output format {negotiated} instead of {config}");
     }
 
-    let config = cpal::StreamConfig {
-        channels: channel_count,
-        sample_rate,
+    let stream_config = cpal::StreamConfig {
+        channels: negotiated.channel_count.to_number(),
+        sample_rate: negotiated.sample_rate.to_number(),
         buffer_size: cpal::BufferSize::Default,
     };
 
     // create stream config
-    let stream: cpal::Stream = match audio_format {
-        AudioFormat::I16 => build_output_stream::<i16>(device, config, consumer),
-        AudioFormat::I24 => build_output_stream::<f32>(device, config, consumer),
-        AudioFormat::I32 => build_output_stream::<i32>(device, config, consumer),
-        AudioFormat::U8 => build_output_stream::<u8>(device, config, consumer),
-        AudioFormat::F32 => build_output_stream::<f32>(device, config, consumer),
+    let stream: cpal::Stream = match negotiated.audio_format {
+        AudioFormat::I16 => build_output_stream::<i16>(device, stream_config, consumer, events),
+        AudioFormat::I24 => build_output_stream::<f32>(device, stream_config, consumer, events),
+        AudioFormat::I32 => build_output_stream::<i32>(device, stream_config, consumer, events),
+        AudioFormat::U8 => build_output_stream::<u8>(device, stream_config, consumer, events),
+        AudioFormat::F32 => build_output_stream::<f32>(device, stream_config, consumer, events),
     }?;
 
-    // convert stream config to AudioPacketFormat
-    let config = AudioPacketFormat {
-        sample_rate: SampleRate::from_number(sample_rate).unwrap(),
-        audio_format,
-        channel_count: ChannelCount::from_number(channel_count).unwrap(),
-    };
-
-    Ok((stream, config))
+    Ok((stream, negotiated))
 }
 
 pub fn process_audio<F>(data: &mut [F], consumer: &mut Consumer<u8>, frame_bytes: usize)
@@ -128,6 +194,7 @@ fn build_output_stream<F>(
     device: &cpal::Device,
     config: cpal::StreamConfig,
     mut consumer: Consumer<u8>,
+    events: mpsc::UnboundedSender<OutputStreamEvent>,
 ) -> anyhow::Result<cpal::Stream, cpal::Error>
 where
     F: cpal::SizedSample + AudioBytes + 'static,
@@ -141,7 +208,16 @@ where
         move |data: &mut [F], _| {
             process_audio(data, &mut consumer, frame_bytes);
         },
-        |err| error!("an error occurred on audio stream: {err}"),
+        move |err| {
+            error!("an error occurred on audio stream: {err}");
+
+            let event = if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                OutputStreamEvent::DeviceLost
+            } else {
+                OutputStreamEvent::Error(err.to_string())
+            };
+            let _ = events.send(event);
+        },
         None,
     )
 }
@@ -179,4 +255,56 @@ mod tests {
 
         assert_eq!(output, [1000, -2000, 0, 0]);
     }
+
+    fn format(sample_rate: u32, audio_format: AudioFormat, channels: u16) -> AudioPacketFormat {
+        AudioPacketFormat {
+            sample_rate: SampleRate::from_number(sample_rate).unwrap(),
+            audio_format,
+            channel_count: ChannelCount::from_number(channels).unwrap(),
+        }
+    }
+
+    #[test]
+    fn best_output_format_prefers_rate_then_bit_depth_then_channels() {
+        let wanted = format(48000, AudioFormat::I16, 1);
+        let supported = [
+            format(44100, AudioFormat::F32, 1),
+            format(48000, AudioFormat::I16, 1),
+            format(48000, AudioFormat::F32, 2),
+            format(48000, AudioFormat::F32, 1),
+            format(48000, AudioFormat::U8, 1),
+        ];
+
+        assert_eq!(
+            best_output_format(supported, &wanted),
+            Some(format(48000, AudioFormat::F32, 1))
+        );
+    }
+
+    #[test]
+    fn best_output_format_keeps_wanted_format_on_ties() {
+        let wanted = format(48000, AudioFormat::I32, 2);
+        let supported = [
+            format(48000, AudioFormat::F32, 2),
+            format(48000, AudioFormat::I32, 2),
+            format(48000, AudioFormat::I16, 2),
+        ];
+
+        assert_eq!(best_output_format(supported, &wanted), Some(wanted));
+    }
+
+    #[test]
+    fn best_output_format_falls_back_to_closest_rate() {
+        let wanted = format(48000, AudioFormat::I16, 1);
+        let supported = [
+            format(16000, AudioFormat::F32, 1),
+            format(44100, AudioFormat::I16, 2),
+        ];
+
+        assert_eq!(
+            best_output_format(supported, &wanted),
+            Some(format(44100, AudioFormat::I16, 2))
+        );
+        assert_eq!(best_output_format([], &wanted), None);
+    }
 }
@@ -1,5 +1,7 @@
 use std::borrow::Cow;
 
+use anyhow::bail;
+
 use crate::{
     audio::{
         denoise_rnnoise::{DENOISE_RNNOISE_SAMPLE_RATE, DenoiseCache},
@@ -10,7 +12,7 @@ use crate::{
         resampler::{ResamplerCache, resample_f32_stream_owned},
         speexdsp::{SPEEXDSP_SAMPLE_RATE, SpeexdspCache, process_speex_f32_stream},
     },
-    config::{AudioEffect, AudioFormat, DenoiseKind},
+    config::{AudioEffect, AudioFormat, DenoiseKind, SampleRate},
     streamer::{AudioPacketMessage, AudioStream},
 };
 
@@ -71,6 +73,11 @@ impl AudioStream {
         F: cpal::SizedSample + AudioBytes + std::fmt::Debug + 'static,
     {
         let config = &self.audio_params;
+
+        // the phone can use any rate, as long as it is sensible
+        if SampleRate::from_number(packet.sample_rate).is_none() {
+            bail!("invalid sample rate received: {}", packet.sample_rate);
+        }
         let mut current_sample_rate = packet.sample_rate;
 
         // first convert audio packet to f32 vector
@@ -216,11 +223,12 @@ impl AudioStream {
                     warn!("dropped audio samples {e}");
                 }
             };
+        }
 
-            // warn about dropped samples
-            if num_bytes < total_bytes {
-                warn!("dropped {} audio bytes", total_bytes - num_bytes);
-            }
+        // warn about dropped samples
+        if num_bytes < total_bytes {
+            warn!("dropped {} audio bytes", total_bytes - num_bytes);
+            self.overrun = Some((num_bytes, total_bytes - num_bytes));
         }
 
         let buffer_mono = if self.is_window_visible {
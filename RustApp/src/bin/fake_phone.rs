@@ -0,0 +1,534 @@
+//! Client side of the AndroidMic protocol, sending audio like the phone app does.
+//! Used as a mic bridge between two computers, and as a load generator.
+
+use std::{
+    f32::consts::TAU,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use android_mic::{
+    audio::AudioBytes,
+    config::{AudioFormat, ChannelCount, SampleRate},
+    streamer::{
+        AudioPacketMessage, CHECK_1, CHECK_2, DEFAULT_PC_PORT, PROTOCOL_VERSION,
+        message::{
+            AudioPacketMessageOrdered, ConnectMessage, MessageWrapper, message_wrapper::Payload,
+        },
+    },
+};
+use anyhow::{Context, bail};
+use byteorder::{ByteOrder, NativeEndian};
+use clap::{Parser, ValueEnum};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use futures::{SinkExt, StreamExt};
+use prost::Message;
+use rtrb::{Consumer, Producer, RingBuffer};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::{Instant, MissedTickBehavior},
+};
+use tokio_util::{
+    codec::{Framed, LengthDelimitedCodec},
+    udp::UdpFramed,
+};
+
+#[macro_use]
+extern crate log;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Transport {
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Source {
+    Sine,
+    Noise,
+    Wav,
+    /// Default input device of this computer
+    Mic,
+}
+
+#[derive(Parser, Debug)]
+#[clap(
+    version,
+    about = "Send audio to AndroidMic like the phone app does",
+    long_about = None
+)]
+struct Args {
+    #[arg(short, long, default_value = "127.0.0.1", help = "address of the pc")]
+    ip: IpAddr,
+
+    #[arg(short, long, default_value_t = DEFAULT_PC_PORT)]
+    port: u16,
+
+    #[arg(short = 'm', long = "mode", value_enum, default_value_t = Transport::Tcp)]
+    transport: Transport,
+
+    #[arg(long, value_enum, default_value_t = Source::Sine)]
+    source: Source,
+
+    #[arg(long, help = "wav file sent with --source wav, in a loop")]
+    file: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 440.0, help = "frequency of the sine, in Hz")]
+    frequency: f32,
+
+    #[arg(
+        short = 'f',
+        long = "format",
+        default_value = "i16",
+        help = "i16, f32, ..."
+    )]
+    audio_format: AudioFormat,
+
+    #[arg(
+        short = 'c',
+        long = "channel",
+        default_value = "1",
+        help = "1 or 2, the wav file and the mic use their own"
+    )]
+    channel_count: ChannelCount,
+
+    #[arg(
+        short = 's',
+        long = "sample",
+        default_value = "48000",
+        help = "16000, 44100, ..., the wav file and the mic use their own"
+    )]
+    sample_rate: SampleRate,
+
+    #[arg(long, default_value_t = 20, help = "audio in each packet, in ms")]
+    packet_ms: u64,
+
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "fraction of the packets dropped, between 0 and 1"
+    )]
+    loss: f64,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "maximum random delay of each packet, in ms. Packets are reordered over udp"
+    )]
+    jitter_ms: u64,
+
+    #[arg(long, help = "stop after this many seconds")]
+    duration: Option<u64>,
+}
+
+/// Small pseudo random generator, good enough for noise and simulated network issues
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self(seed | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// In [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+enum Generator {
+    Sine {
+        frequency: f32,
+        phase: f32,
+    },
+    Noise(XorShift),
+    Wav {
+        /// Interleaved samples
+        samples: Vec<f32>,
+        position: usize,
+    },
+    Mic {
+        consumer: Consumer<f32>,
+        _stream: cpal::Stream,
+    },
+}
+
+impl Generator {
+    /// Interleaved samples for the next packet
+    fn next_samples(&mut self, frames: usize, channels: usize, sample_rate: u32) -> Vec<f32> {
+        match self {
+            Generator::Sine { frequency, phase } => {
+                let step = TAU * *frequency / sample_rate as f32;
+                let mut samples = Vec::with_capacity(frames * channels);
+                for _ in 0..frames {
+                    let sample = phase.sin() * 0.5;
+                    samples.extend(std::iter::repeat_n(sample, channels));
+                    *phase = (*phase + step) % TAU;
+                }
+                samples
+            }
+            Generator::Noise(rng) => (0..frames * channels)
+                .map(|_| (rng.next_f64() as f32 * 2.0 - 1.0) * 0.3)
+                .collect(),
+            Generator::Wav { samples, position } => {
+                let mut packet = Vec::with_capacity(frames * channels);
+                while packet.len() < frames * channels {
+                    let end = (*position + frames * channels - packet.len()).min(samples.len());
+                    packet.extend_from_slice(&samples[*position..end]);
+                    *position = if end == samples.len() { 0 } else { end };
+                }
+                packet
+            }
+            Generator::Mic { consumer, .. } => {
+                // the device clock drives the pace, send what was recorded
+                let available = consumer.slots().min(2 * frames * channels);
+                let available = available - available % channels;
+                match consumer.read_chunk(available) {
+                    Ok(chunk) => chunk.into_iter().collect(),
+                    Err(_) => Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+/// Interleaved samples of a wav file, with its rate and channel count
+fn read_wav(path: &Path) -> anyhow::Result<(Vec<f32>, SampleRate, ChannelCount)> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let sample_rate = SampleRate::from_number(spec.sample_rate)
+        .with_context(|| format!("unsupported sample rate {}", spec.sample_rate))?;
+    let channel_count = ChannelCount::from_number(spec.channels)
+        .with_context(|| format!("unsupported channel count {}", spec.channels))?;
+
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    if samples.is_empty() {
+        bail!("the file has no audio");
+    }
+
+    Ok((samples, sample_rate, channel_count))
+}
+
+fn build_input_stream<F>(
+    device: &cpal::Device,
+    config: cpal::StreamConfig,
+    mut producer: Producer<f32>,
+) -> Result<cpal::Stream, cpal::Error>
+where
+    F: cpal::SizedSample + AudioBytes + 'static,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[F], _| {
+            for sample in data {
+                // the sender is late, drop the samples
+                let _ = producer.push(sample.to_f32());
+            }
+        },
+        move |err| error!("an error occurred on input stream: {err}"),
+        None,
+    )
+}
+
+/// Record the default input device, with its own format
+fn open_mic() -> anyhow::Result<(Generator, SampleRate, ChannelCount)> {
+    let host = cpal::default_host();
+    let device = host
+        .default_input_device()
+        .context("no default input device")?;
+    let supported = device.default_input_config()?;
+
+    let sample_rate = SampleRate::from_number(supported.sample_rate())
+        .with_context(|| format!("unsupported sample rate {}", supported.sample_rate()))?;
+    let channel_count = ChannelCount::from_number(supported.channels())
+        .with_context(|| format!("unsupported channel count {}", supported.channels()))?;
+
+    // one second of audio
+    let (producer, consumer) =
+        RingBuffer::new(sample_rate.to_number() as usize * channel_count.to_number() as usize);
+
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, config, producer),
+        cpal::SampleFormat::I32 => build_input_stream::<i32>(&device, config, producer),
+        cpal::SampleFormat::U8 => build_input_stream::<u8>(&device, config, producer),
+        cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, config, producer),
+        format => bail!("unsupported input format {format}"),
+    }?;
+    stream.play()?;
+
+    Ok((
+        Generator::Mic {
+            consumer,
+            _stream: stream,
+        },
+        sample_rate,
+        channel_count,
+    ))
+}
+
+fn encode(samples: &[f32], audio_format: &AudioFormat) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(samples.len() * audio_format.sample_size());
+
+    for &sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        match audio_format {
+            AudioFormat::U8 => buffer.extend(u8::from_f32(sample).to_bytes()),
+            AudioFormat::I16 => buffer.extend(i16::from_f32(sample).to_bytes()),
+            AudioFormat::I24 => {
+                let mut bytes = [0; 3];
+                NativeEndian::write_i24(&mut bytes, (sample * ((1 << 23) - 1) as f32) as i32);
+                buffer.extend_from_slice(&bytes);
+            }
+            AudioFormat::I32 => buffer.extend(i32::from_f32(sample).to_bytes()),
+            AudioFormat::F32 => buffer.extend(sample.to_bytes()),
+        }
+    }
+
+    buffer
+}
+
+enum Link {
+    /// Audio packets are sent without wrapper
+    Tcp(Framed<TcpStream, LengthDelimitedCodec>),
+    /// Shared with the tasks sending delayed packets
+    Udp {
+        socket: Arc<UdpSocket>,
+        addr: SocketAddr,
+    },
+}
+
+async fn connect_tcp(addr: SocketAddr) -> anyhow::Result<Link> {
+    let mut stream = TcpStream::connect(addr).await?;
+
+    stream.write_all(CHECK_1.as_bytes()).await?;
+
+    let mut check = [0u8; CHECK_2.len()];
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, stream.read_exact(&mut check))
+        .await
+        .context("no answer from the pc")??;
+    if check != CHECK_2.as_bytes() {
+        bail!(
+            "unexpected answer from the pc: {}",
+            String::from_utf8_lossy(&check)
+        );
+    }
+
+    Ok(Link::Tcp(Framed::new(stream, LengthDelimitedCodec::new())))
+}
+
+async fn connect_udp(
+    addr: SocketAddr,
+    sample_rate: &SampleRate,
+    channel_count: &ChannelCount,
+    audio_format: &AudioFormat,
+) -> anyhow::Result<Link> {
+    let local: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+    let mut framed = UdpFramed::new(UdpSocket::bind(local).await?, LengthDelimitedCodec::new());
+
+    let connect = MessageWrapper {
+        payload: Some(Payload::Connect(ConnectMessage {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: "fake-phone".into(),
+            device_model: std::env::consts::OS.into(),
+            sample_rates: vec![sample_rate.to_number()],
+            channel_counts: vec![channel_count.to_number() as u32],
+            audio_formats: vec![audio_format.to_android_format()],
+            codecs: Vec::new(),
+            features: Vec::new(),
+        })),
+    };
+    framed.send((connect.encode_to_vec().into(), addr)).await?;
+
+    let (frame, _) = tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next())
+        .await
+        .context("no answer from the pc")?
+        .context("socket closed")??;
+
+    match MessageWrapper::decode(frame)?.payload {
+        Some(Payload::ConnectReply(reply)) if reply.error.is_empty() => {
+            info!(
+                "accepted by the pc, which plays at {} Hz",
+                reply.output_sample_rate
+            );
+        }
+        Some(Payload::ConnectReply(reply)) => bail!("refused by the pc: {}", reply.error),
+        _ => bail!("unexpected answer from the pc"),
+    }
+
+    Ok(Link::Udp {
+        socket: Arc::new(framed.into_inner()),
+        addr,
+    })
+}
+
+impl Link {
+    async fn send(
+        &mut self,
+        packet: AudioPacketMessage,
+        sequence_number: u32,
+        delay: Duration,
+    ) -> anyhow::Result<()> {
+        match self {
+            Link::Tcp(framed) => {
+                tokio::time::sleep(delay).await;
+                framed.send(packet.encode_to_vec().into()).await?;
+            }
+            Link::Udp { socket, addr } => {
+                let message = MessageWrapper {
+                    payload: Some(Payload::AudioPacket(AudioPacketMessageOrdered {
+                        sequence_number,
+                        audio_packet: Some(packet),
+                    })),
+                }
+                .encode_to_vec();
+
+                // same framing as LengthDelimitedCodec
+                let mut datagram = (message.len() as u32).to_be_bytes().to_vec();
+                datagram.extend(message);
+
+                let socket = socket.clone();
+                let addr = *addr;
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    if let Err(e) = socket.send_to(&datagram, addr).await {
+                        warn!("can't send packet {sequence_number}: {e}");
+                    }
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args = Args::parse();
+
+    let (mut generator, sample_rate, channel_count) = match args.source {
+        Source::Sine => (
+            Generator::Sine {
+                frequency: args.frequency,
+                phase: 0.0,
+            },
+            args.sample_rate,
+            args.channel_count,
+        ),
+        Source::Noise => (
+            Generator::Noise(XorShift::new()),
+            args.sample_rate,
+            args.channel_count,
+        ),
+        Source::Wav => {
+            let path = args.file.as_ref().context("--source wav needs --file")?;
+            let (samples, sample_rate, channel_count) =
+                read_wav(path).with_context(|| format!("can't read {}", path.display()))?;
+            (
+                Generator::Wav {
+                    samples,
+                    position: 0,
+                },
+                sample_rate,
+                channel_count,
+            )
+        }
+        Source::Mic => open_mic()?,
+    };
+
+    let addr = SocketAddr::new(args.ip, args.port);
+    let mut link = match args.transport {
+        Transport::Tcp => connect_tcp(addr).await,
+        Transport::Udp => connect_udp(addr, &sample_rate, &channel_count, &args.audio_format).await,
+    }
+    .with_context(|| format!("can't connect to {addr}"))?;
+
+    info!(
+        "sending {:?} to {addr} over {:?}: {sample_rate} Hz, {}, {channel_count}",
+        args.source, args.transport, args.audio_format
+    );
+
+    let packet_duration = Duration::from_millis(args.packet_ms.max(1));
+    let frames =
+        (sample_rate.to_number() as u64 * packet_duration.as_millis() as u64 / 1000) as usize;
+    let channels = channel_count.to_number() as usize;
+
+    let mut ticker = tokio::time::interval(packet_duration);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let deadline = args
+        .duration
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    let mut rng = XorShift::new();
+    let mut sequence_number: u32 = 0;
+    let mut dropped: u64 = 0;
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = ticker.tick() => {}
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+
+        let samples = generator.next_samples(frames, channels, sample_rate.to_number());
+        if samples.is_empty() {
+            continue;
+        }
+
+        let packet = AudioPacketMessage {
+            buffer: encode(&samples, &args.audio_format),
+            sample_rate: sample_rate.to_number(),
+            channel_count: channels as u32,
+            audio_format: args.audio_format.to_android_format(),
+        };
+
+        sequence_number = sequence_number.wrapping_add(1);
+
+        if rng.next_f64() < args.loss {
+            dropped += 1;
+            continue;
+        }
+
+        let delay = Duration::from_millis(rng.next_u64() % (args.jitter_ms + 1));
+        link.send(packet, sequence_number, delay)
+            .await
+            .context("connection lost")?;
+    }
+
+    info!("sent {} packets, {dropped} dropped", sequence_number);
+    Ok(())
+}
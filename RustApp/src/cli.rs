@@ -0,0 +1,89 @@
+//! Listings printed by the command line, for scripts choosing a device or an adapter
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde_json::{Value, json};
+
+use crate::{
+    config::SampleRate,
+    ui::app::{get_audio_devices, get_network_adapters},
+};
+
+/// Output devices of the default host, with the sample rates they support
+pub fn list_devices() -> Value {
+    let host = cpal::default_host();
+    let default_id = host
+        .default_output_device()
+        .and_then(|device| device.id().ok())
+        .map(|id| id.to_string());
+
+    let devices: Vec<Value> = get_audio_devices(&host)
+        .iter()
+        .map(|device| {
+            json!({
+                "id": device.id,
+                "name": device.name,
+                "default": Some(&device.id) == default_id.as_ref(),
+                "sample_rates": SampleRate::supported_by(&device.device)
+                    .iter()
+                    .map(SampleRate::to_number)
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    json!({
+        "host": host.id().name(),
+        "devices": devices,
+    })
+}
+
+/// Network adapters the listener can bind to
+pub fn list_adapters() -> Value {
+    let adapters: Vec<Value> = get_network_adapters()
+        .iter()
+        .map(|adapter| {
+            json!({
+                "name": adapter.name,
+                "ip": adapter.ip.to_string(),
+            })
+        })
+        .collect();
+
+    Value::Array(adapters)
+}
+
+/// What `--list-devices` and `--list-adapters` print, a single object when both are asked
+pub fn listing(devices: bool, adapters: bool) -> Value {
+    match (devices, adapters) {
+        (true, true) => json!({
+            "devices": list_devices(),
+            "adapters": list_adapters(),
+        }),
+        (true, false) => list_devices(),
+        (false, _) => list_adapters(),
+    }
+}
+
+/// Release builds on Windows have no console, print to the one of the terminal
+/// that started the app. A redirected output is already usable and kept.
+#[cfg(target_os = "windows")]
+pub fn attach_console() {
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut std::ffi::c_void;
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // SAFETY: both functions only take plain values
+    unsafe {
+        if GetStdHandle(STD_OUTPUT_HANDLE).is_null() {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn attach_console() {}
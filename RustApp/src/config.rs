@@ -1,25 +1,60 @@
-use std::{fmt::Display, net::IpAddr};
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv6Addr},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use clap::Parser;
 use light_enum::Values;
 use local_ip_address::local_ip;
 use serde::{Deserialize, Serialize};
 
-use crate::{fl, streamer::DEFAULT_PC_PORT};
+use crate::{config_migration::CONFIG_VERSION, fl, profile::Profile, streamer::DEFAULT_PC_PORT};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
+    /// Schema of the file, see [`crate::config_migration`]
+    pub version: u32,
     pub connection_mode: ConnectionMode,
     pub ip: Option<IpAddr>,
+    /// Listen on every interface (`::`, dual stack) instead of the address of `ip`
+    pub bind_all_interfaces: bool,
+    /// Addresses of other adapters listened on along with `ip`
+    pub extra_adapters: Vec<IpAddr>,
+    /// Only phones in these subnets can connect, empty to allow any
+    pub allowed_subnets: Vec<Subnet>,
     pub port: u16,
+    /// Serial of the adb device to use, the only ready one when unset
+    pub adb_device: Option<String>,
+    /// Serial of the usb device to use, the only Android device plugged when unset
+    pub usb_device: Option<String>,
+    /// Devices paired for wireless debugging
+    pub wireless_adb_devices: Vec<WirelessAdbDevice>,
+    /// Wav, flac or capture file streamed in the file replay mode
+    pub replay_file: Option<PathBuf>,
+    pub replay_loop: bool,
     pub audio_format: AudioFormat,
     pub channel_count: ChannelCount,
     pub sample_rate: SampleRate,
     pub device_id: Option<String>,
+    /// Ordered list of output device names or patterns (`*` match anything),
+    /// used when the selected device disappears.
+    pub preferred_devices: Vec<String>,
     pub start_at_login: bool,
     pub start_minimized: bool,
     pub auto_connect: bool,
+    /// Events shown as desktop notifications
+    pub notify_events: Vec<NotificationEvent>,
+    /// Don't notify while the main window is open, the logs already show the events
+    pub notify_only_when_hidden: bool,
+    /// Go back to listening when the phone disconnects
+    pub keep_listening: bool,
+    /// Number of retries on transient connection errors
+    pub reconnect_max_retries: u32,
+    /// Dump the frames received from the phone next to the logs, to replay them later
+    pub capture_traffic: bool,
     pub denoise: bool,
     pub denoise_kind: DenoiseKind,
     /// range: [-100, 0]
@@ -37,9 +72,12 @@ pub struct Config {
     pub amplify: bool,
     pub amplify_value: f32,
     pub post_effect: AudioEffect,
+    pub profiles: Vec<Profile>,
+    /// Last profile applied, the settings may have changed since
+    pub active_profile: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Values, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Values, PartialEq, Eq, clap::ValueEnum)]
 pub enum AudioEffect {
     NoEffect,
     // Environment effects:
@@ -78,7 +116,7 @@ impl Display for AudioEffect {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default, Values, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Values, PartialEq, clap::ValueEnum)]
 pub enum DenoiseKind {
     #[default]
     Rnnoise,
@@ -96,18 +134,66 @@ impl Display for DenoiseKind {
     }
 }
 
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, Values, PartialEq, Eq, Hash, clap::ValueEnum,
+)]
+pub enum NotificationEvent {
+    Connected,
+    Disconnected,
+    Error,
+    /// The output device disappeared
+    DeviceLost,
+    /// Audio was dropped because the output device is too slow
+    BufferOverrun,
+    /// The phone battery is low during a long session
+    LowBattery,
+    /// The window was closed, the app keeps running in the tray
+    MinimizedToTray,
+}
+
+impl Display for NotificationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            NotificationEvent::Connected => fl!("notify_connected"),
+            NotificationEvent::Disconnected => fl!("notify_disconnected"),
+            NotificationEvent::Error => fl!("notify_error"),
+            NotificationEvent::DeviceLost => fl!("notify_device_lost"),
+            NotificationEvent::BufferOverrun => fl!("notify_buffer_overrun"),
+            NotificationEvent::LowBattery => fl!("notify_low_battery"),
+            NotificationEvent::MinimizedToTray => fl!("notify_minimized_to_tray"),
+        };
+
+        write!(f, "{}", str)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             connection_mode: Default::default(),
             ip: None,
+            bind_all_interfaces: false,
+            extra_adapters: Vec::new(),
+            allowed_subnets: Vec::new(),
             port: DEFAULT_PC_PORT,
+            adb_device: None,
+            usb_device: None,
+            wireless_adb_devices: Vec::new(),
+            replay_file: None,
+            replay_loop: false,
             audio_format: Default::default(),
             channel_count: Default::default(),
             sample_rate: Default::default(),
             device_id: None,
+            preferred_devices: Vec::new(),
             start_at_login: false,
             auto_connect: false,
+            notify_events: NotificationEvent::VALUES.to_vec(),
+            notify_only_when_hidden: true,
+            keep_listening: true,
+            reconnect_max_retries: 5,
+            capture_traffic: false,
             denoise: false,
             denoise_kind: Default::default(),
             theme: Default::default(),
@@ -122,6 +208,8 @@ impl Default for Config {
             speex_dereverb_level: 0.5,
             post_effect: AudioEffect::NoEffect,
             start_minimized: false,
+            profiles: Vec::new(),
+            active_profile: None,
         }
     }
 }
@@ -137,12 +225,91 @@ impl Config {
         self.speex_dereverb_level = 0.5;
     }
 
+    /// Clamp the values to their documented ranges, returning a warning for each fix
+    pub fn validate(&mut self) -> Vec<String> {
+        fn clamp<T: PartialOrd + Copy + Display>(
+            warnings: &mut Vec<String>,
+            name: &str,
+            value: &mut T,
+            min: T,
+            max: T,
+        ) {
+            if !(min..=max).contains(value) {
+                let clamped = if *value > max { max } else { min };
+                warnings.push(format!(
+                    "`{name}` = {value} is outside [{min}, {max}], using {clamped}"
+                ));
+                *value = clamped;
+            }
+        }
+
+        let mut warnings = Vec::new();
+        clamp(
+            &mut warnings,
+            "speex_noise_suppress",
+            &mut self.speex_noise_suppress,
+            -100,
+            0,
+        );
+        clamp(
+            &mut warnings,
+            "speex_vad_threshold",
+            &mut self.speex_vad_threshold,
+            0,
+            100,
+        );
+        clamp(
+            &mut warnings,
+            "speex_agc_target",
+            &mut self.speex_agc_target,
+            8000,
+            65535,
+        );
+        clamp(
+            &mut warnings,
+            "speex_dereverb_level",
+            &mut self.speex_dereverb_level,
+            0.0,
+            1.0,
+        );
+        clamp(
+            &mut warnings,
+            "amplify_value",
+            &mut self.amplify_value,
+            0.0,
+            10.0,
+        );
+        clamp(
+            &mut warnings,
+            "reconnect_max_retries",
+            &mut self.reconnect_max_retries,
+            0,
+            20,
+        );
+        warnings
+    }
+
     pub fn ip_or_default(&self) -> Option<IpAddr> {
         self.ip.or(local_ip().ok())
     }
+
+    /// Addresses the tcp and udp listeners bind to, the first one is the main adapter
+    pub fn bind_addresses(&self) -> Vec<IpAddr> {
+        if self.bind_all_interfaces {
+            return vec![IpAddr::V6(Ipv6Addr::UNSPECIFIED)];
+        }
+
+        let mut addresses: Vec<IpAddr> = self.ip_or_default().into_iter().collect();
+        for ip in &self.extra_adapters {
+            if !addresses.contains(ip) {
+                addresses.push(*ip);
+            }
+        }
+        addresses
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Values)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Values, clap::ValueEnum)]
 pub enum AppTheme {
     System,
     Light,
@@ -188,9 +355,55 @@ pub struct Args {
     )]
     pub connection_mode: Option<ConnectionMode>,
 
+    #[arg(short = 'p', long = "port")]
+    pub port: Option<u16>,
+
+    #[arg(
+        long = "bind-all",
+        value_name = "BOOL",
+        help = "listen on every network interface"
+    )]
+    pub bind_all_interfaces: Option<bool>,
+
+    #[arg(
+        long = "extra-adapter",
+        value_name = "IP",
+        help = "also listen on the adapter with this address, can be repeated"
+    )]
+    pub extra_adapters: Vec<IpAddr>,
+
+    #[arg(
+        long = "allowed-subnet",
+        value_name = "SUBNET",
+        help = "only accept phones from this network, can be repeated. example: 192.168.1.0/24"
+    )]
+    pub allowed_subnets: Vec<Subnet>,
+
+    #[arg(long = "adb-device", value_name = "SERIAL")]
+    pub adb_device: Option<String>,
+
+    #[arg(long = "usb-device", value_name = "SERIAL")]
+    pub usb_device: Option<String>,
+
+    #[arg(long = "keep-listening", value_name = "BOOL")]
+    pub keep_listening: Option<bool>,
+
+    #[arg(long = "retries", help = "retries on transient connection errors")]
+    pub reconnect_max_retries: Option<u32>,
+
+    #[arg(long = "capture", value_name = "BOOL")]
+    pub capture_traffic: Option<bool>,
+
     #[arg(short = 'd', long = "device", id = "output device")]
     pub output_device: Option<String>,
 
+    #[arg(
+        long = "preferred-device",
+        value_name = "PATTERN",
+        help = "output device used when the selected one disappears, can be repeated"
+    )]
+    pub preferred_devices: Vec<String>,
+
     #[arg(
         short = 'f',
         long = "format",
@@ -210,6 +423,72 @@ pub struct Args {
     )]
     pub sample_rate: Option<SampleRate>,
 
+    #[arg(long = "denoise", value_name = "BOOL")]
+    pub denoise: Option<bool>,
+
+    #[arg(long = "denoise-kind", value_enum)]
+    pub denoise_kind: Option<DenoiseKind>,
+
+    #[arg(
+        long = "noise-suppress",
+        allow_hyphen_values = true,
+        help = "speexdsp noise suppression in dB, in [-100, 0]"
+    )]
+    pub speex_noise_suppress: Option<i32>,
+
+    #[arg(long = "vad", value_name = "BOOL")]
+    pub speex_vad_enabled: Option<bool>,
+
+    #[arg(long = "vad-threshold", help = "in [0, 100]")]
+    pub speex_vad_threshold: Option<u32>,
+
+    #[arg(long = "agc", value_name = "BOOL")]
+    pub speex_agc_enabled: Option<bool>,
+
+    #[arg(long = "agc-target", help = "in [8000, 65535]")]
+    pub speex_agc_target: Option<u32>,
+
+    #[arg(long = "dereverb", value_name = "BOOL")]
+    pub speex_dereverb_enabled: Option<bool>,
+
+    #[arg(long = "dereverb-level", help = "in [0, 1]")]
+    pub speex_dereverb_level: Option<f32>,
+
+    #[arg(long = "amplify", value_name = "BOOL")]
+    pub amplify: Option<bool>,
+
+    #[arg(long = "amplify-value", help = "in [0, 10]")]
+    pub amplify_value: Option<f32>,
+
+    #[arg(long = "effect", value_enum)]
+    pub post_effect: Option<AudioEffect>,
+
+    #[arg(long = "theme", value_enum)]
+    pub theme: Option<AppTheme>,
+
+    #[arg(long = "auto-connect", value_name = "BOOL")]
+    pub auto_connect: Option<bool>,
+
+    #[arg(long = "start-minimized", value_name = "BOOL")]
+    pub start_minimized: Option<bool>,
+
+    #[arg(
+        long = "start-at-login",
+        value_name = "BOOL",
+        conflicts_with = "no_save"
+    )]
+    pub start_at_login: Option<bool>,
+
+    #[arg(
+        long = "notify",
+        value_enum,
+        help = "event shown as a desktop notification, can be repeated"
+    )]
+    pub notify_events: Vec<NotificationEvent>,
+
+    #[arg(long = "notify-only-when-hidden", value_name = "BOOL")]
+    pub notify_only_when_hidden: Option<bool>,
+
     #[arg(
         long = "info",
         id = "supported audio config",
@@ -218,6 +497,50 @@ pub struct Args {
     )]
     pub show_supported_audio_config: bool,
 
+    #[arg(
+        long = "replay",
+        id = "audio file",
+        help = "stream a wav or flac file instead of a phone"
+    )]
+    pub replay_file: Option<PathBuf>,
+
+    #[arg(
+        long = "loop",
+        value_name = "BOOL",
+        help = "replay the audio file in a loop",
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub replay_loop: Option<bool>,
+
+    #[arg(
+        long = "profile",
+        id = "profile name",
+        help = "apply a saved profile, other options override it"
+    )]
+    pub profile: Option<String>,
+
+    #[arg(
+        long = "list-devices",
+        help = "print the output devices as json and exit",
+        default_value_t = false
+    )]
+    pub list_devices: bool,
+
+    #[arg(
+        long = "list-adapters",
+        help = "print the network adapters as json and exit",
+        default_value_t = false
+    )]
+    pub list_adapters: bool,
+
+    #[arg(
+        long = "no-save",
+        help = "don't write the settings of this session to the config file",
+        default_value_t = false
+    )]
+    pub no_save: bool,
+
     #[arg(long, hide = true, default_value_t = false)]
     pub launched_automatically: bool,
 }
@@ -243,6 +566,7 @@ pub enum ConnectionMode {
     Adb,
     #[cfg(feature = "usb")]
     Usb,
+    File,
 }
 
 #[derive(
@@ -338,6 +662,16 @@ impl AudioFormat {
         }
     }
 
+    pub fn to_android_format(&self) -> u32 {
+        match self {
+            AudioFormat::U8 => 3,
+            AudioFormat::I16 => 2,
+            AudioFormat::I24 => 21,
+            AudioFormat::I32 => 22,
+            AudioFormat::F32 => 4,
+        }
+    }
+
     pub fn from_cpal_format(format: cpal::SampleFormat) -> Option<Self> {
         // no i24 in cpal ?
         match format {
@@ -350,6 +684,57 @@ impl AudioFormat {
     }
 }
 
+/// Microphone used by the phone, see android `MediaRecorder.AudioSource`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Values)]
+pub enum MicSource {
+    #[default]
+    Default,
+    Mic,
+    Camcorder,
+    VoiceRecognition,
+    VoiceCommunication,
+    Unprocessed,
+}
+
+impl MicSource {
+    pub fn to_android_source(&self) -> u32 {
+        match self {
+            MicSource::Default => 0,
+            MicSource::Mic => 1,
+            MicSource::Camcorder => 5,
+            MicSource::VoiceRecognition => 6,
+            MicSource::VoiceCommunication => 7,
+            MicSource::Unprocessed => 9,
+        }
+    }
+
+    pub fn from_android_source(source: u32) -> Option<Self> {
+        match source {
+            0 => Some(MicSource::Default),
+            1 => Some(MicSource::Mic),
+            5 => Some(MicSource::Camcorder),
+            6 => Some(MicSource::VoiceRecognition),
+            7 => Some(MicSource::VoiceCommunication),
+            9 => Some(MicSource::Unprocessed),
+            _ => None,
+        }
+    }
+}
+
+impl Display for MicSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            MicSource::Default => fl!("mic_source_default"),
+            MicSource::Mic => fl!("mic_source_mic"),
+            MicSource::Camcorder => fl!("mic_source_camcorder"),
+            MicSource::VoiceRecognition => fl!("mic_source_voice_recognition"),
+            MicSource::VoiceCommunication => fl!("mic_source_voice_communication"),
+            MicSource::Unprocessed => fl!("mic_source_unprocessed"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
 impl PartialEq<cpal::SampleFormat> for AudioFormat {
     fn eq(&self, other: &cpal::SampleFormat) -> bool {
         match self {
@@ -362,65 +747,118 @@ impl PartialEq<cpal::SampleFormat> for AudioFormat {
     }
 }
 
+/// Sample rate in Hz, validated to be in [`SampleRate::MIN`, `SampleRate::MAX`]
 #[derive(
     Debug,
     Clone,
+    Copy,
     PartialEq,
-    Default,
-    Values,
-    strum::Display,
-    strum::EnumString,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
     serde_with::SerializeDisplay,
     serde_with::DeserializeFromStr,
 )]
-pub enum SampleRate {
-    #[strum(serialize = "8000")]
-    S8000,
-    #[strum(serialize = "11025")]
-    S11025,
-    #[strum(serialize = "16000")]
-    S16000,
-    #[strum(serialize = "22050")]
-    S22050,
-    #[default]
-    #[strum(serialize = "44100")]
-    S44100,
-    #[strum(serialize = "48000")]
-    S48000,
-    #[strum(serialize = "88200")]
-    S88200,
-    #[strum(serialize = "96600")]
-    S96600,
-    #[strum(serialize = "176400")]
-    S176400,
-    #[strum(serialize = "192000")]
-    S192000,
-    #[strum(serialize = "352800")]
-    S352800,
-    #[strum(serialize = "384000")]
-    S384000,
-}
+pub struct SampleRate(u32);
 
 impl SampleRate {
+    pub const MIN: u32 = 1000;
+    pub const MAX: u32 = 768000;
+
+    /// Rates proposed in the UI when the device support them
+    pub const COMMON: [u32; 12] = [
+        8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 384000,
+    ];
+
     pub fn to_number(&self) -> u32 {
-        self.to_string().parse().unwrap()
+        self.0
     }
 
     pub fn from_number(value: u32) -> Option<Self> {
+        (Self::MIN..=Self::MAX)
+            .contains(&value)
+            .then_some(SampleRate(value))
+    }
+
+    /// List the common rates that fit in the ranges supported by the device.
+    /// The bounds of each range are also included, to not hide unusual devices.
+    pub fn supported_by(device: &cpal::Device) -> Vec<Self> {
+        use cpal::traits::DeviceTrait;
+
+        let mut rates = Vec::new();
+
+        match device.supported_output_configs() {
+            Ok(configs) => {
+                for config in configs {
+                    let range = config.min_sample_rate()..=config.max_sample_rate();
+
+                    rates.extend(
+                        Self::COMMON
+                            .iter()
+                            .chain([range.start(), range.end()])
+                            .filter(|rate| range.contains(*rate))
+                            .filter_map(|rate| Self::from_number(*rate)),
+                    );
+                }
+            }
+            Err(e) => {
+                warn!("can't list supported output configs: {e}");
+            }
+        }
+
+        rates.sort();
+        rates.dedup();
+        rates
+    }
+}
+
+impl Default for SampleRate {
+    fn default() -> Self {
+        SampleRate(44100)
+    }
+}
+
+impl Display for SampleRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: u32 = s.trim().parse().map_err(|e| format!("{s}: {e}"))?;
+
         match value {
-            8000 => Some(SampleRate::S8000),
-            11025 => Some(SampleRate::S11025),
-            16000 => Some(SampleRate::S16000),
-            22050 => Some(SampleRate::S22050),
-            44100 => Some(SampleRate::S44100),
-            48000 => Some(SampleRate::S48000),
-            88200 => Some(SampleRate::S88200),
-            96600 => Some(SampleRate::S96600),
-            176400 => Some(SampleRate::S176400),
-            192000 => Some(SampleRate::S192000),
-            352800 => Some(SampleRate::S352800),
-            384000 => Some(SampleRate::S384000),
-            _ => None,
+            // this value was wrongly offered by older versions
+            96600 => Ok(SampleRate(96000)),
+            value => Self::from_number(value).ok_or_else(|| {
+                format!(
+                    "sample rate {value} is outside [{}, {}]",
+                    Self::MIN,
+                    Self::MAX
+                )
+            }),
+        }
+    }
+}
+
+/// Phone paired with `adb pair`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WirelessAdbDevice {
+    /// Name advertised over mDNS, like `adb-<serial>-<id>`
+    pub name: String,
+    /// Last address used to connect, the port changes each time wireless debugging is enabled
+    pub address: Option<String>,
+}
+
+impl Display for WirelessAdbDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.address {
+            Some(address) => write!(f, "{} ({address})", self.name),
+            None => write!(f, "{}", self.name),
         }
     }
 }
@@ -436,3 +874,172 @@ impl Display for NetworkAdapter {
         write!(f, "{} ({})", self.name, self.ip)
     }
 }
+
+/// IP network in CIDR notation, like `192.168.1.0/24`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde_with::SerializeDisplay, serde_with::DeserializeFromStr,
+)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // dual stack sockets report IPv4 peers as IPv4-mapped IPv6 addresses
+        match (self.addr.to_canonical(), ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s.trim(), None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid address {addr}: {e}"))?
+            .to_canonical();
+
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| format!("invalid prefix length {prefix_len}"))?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn subnet_parse() {
+        assert_eq!(
+            "192.168.1.0/24".parse::<Subnet>().unwrap().to_string(),
+            "192.168.1.0/24"
+        );
+        // a bare address is a single host
+        assert_eq!(
+            "10.0.0.1".parse::<Subnet>().unwrap().to_string(),
+            "10.0.0.1/32"
+        );
+        assert_eq!(
+            "fe80::1".parse::<Subnet>().unwrap().to_string(),
+            "fe80::1/128"
+        );
+        // IPv4-mapped IPv6 addresses are stored as IPv4
+        assert_eq!(
+            "::ffff:192.168.1.0/24"
+                .parse::<Subnet>()
+                .unwrap()
+                .to_string(),
+            "192.168.1.0/24"
+        );
+
+        assert!("192.168.1.0/33".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/abc".parse::<Subnet>().is_err());
+        assert!("192.168.1/24".parse::<Subnet>().is_err());
+        assert!("fe80::/129".parse::<Subnet>().is_err());
+    }
+
+    #[test]
+    fn subnet_contains() {
+        let subnet: Subnet = "192.168.1.0/24".parse().unwrap();
+        assert!(subnet.contains(ip("192.168.1.42")));
+        assert!(!subnet.contains(ip("192.168.2.42")));
+        // dual stack sockets report IPv4 peers as IPv4-mapped IPv6 addresses
+        assert!(subnet.contains(ip("::ffff:192.168.1.42")));
+        assert!(!subnet.contains(ip("::ffff:192.168.2.42")));
+        assert!(!subnet.contains(ip("fe80::1")));
+
+        let subnet: Subnet = "fe80::/64".parse().unwrap();
+        assert!(subnet.contains(ip("fe80::1234")));
+        assert!(!subnet.contains(ip("fe81::1")));
+        assert!(!subnet.contains(ip("192.168.1.42")));
+
+        let any: Subnet = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("8.8.8.8")));
+        assert!(any.contains(ip("::ffff:8.8.8.8")));
+
+        let host: Subnet = "10.0.0.1".parse().unwrap();
+        assert!(host.contains(ip("10.0.0.1")));
+        assert!(!host.contains(ip("10.0.0.2")));
+    }
+
+    #[test]
+    fn bind_addresses() {
+        let mut config = Config {
+            ip: Some(ip("192.168.1.2")),
+            extra_adapters: vec![ip("10.0.0.2"), ip("192.168.1.2")],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.bind_addresses(),
+            vec![ip("192.168.1.2"), ip("10.0.0.2")]
+        );
+
+        config.bind_all_interfaces = true;
+        assert_eq!(config.bind_addresses(), vec![ip("::")]);
+    }
+
+    #[test]
+    fn validate_clamps_out_of_range_values() {
+        let mut config = Config {
+            speex_noise_suppress: 10,
+            speex_vad_threshold: 500,
+            speex_dereverb_level: f32::NAN,
+            amplify_value: 50.0,
+            ..Default::default()
+        };
+        let defaults = Config::default();
+
+        let warnings = config.validate();
+
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(config.speex_noise_suppress, 0);
+        assert_eq!(config.speex_vad_threshold, 100);
+        // NaN is in no range, the minimum is used
+        assert_eq!(config.speex_dereverb_level, 0.0);
+        assert_eq!(config.amplify_value, 10.0);
+        // values in range are kept
+        assert_eq!(config.speex_agc_target, defaults.speex_agc_target);
+        assert_eq!(config.reconnect_max_retries, defaults.reconnect_max_retries);
+        assert!(Config::default().validate().is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+//! Bring the config file to the current schema before it is loaded.
+//!
+//! Each migration upgrades the raw toml from one version to the next, so old files keep
+//! their settings when a field or a value is renamed. Values that still can't be read
+//! are dropped one by one, instead of resetting the whole config to its defaults.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::de::DeserializeOwned;
+use toml::{Table, Value};
+
+use crate::{config::Config, profile::Profile};
+
+pub const CONFIG_VERSION: u32 = 2;
+
+/// `MIGRATIONS[n]` upgrades a config from version `n` to `n + 1`
+const MIGRATIONS: [fn(&mut Table); CONFIG_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// Files written before the version field existed
+fn v0_to_v1(table: &mut Table) {
+    // the sample rate was an enum, and 96600 was offered instead of 96000
+    if let Some(sample_rate) = table.get_mut("sample_rate")
+        && sample_rate.as_str() == Some("96600")
+    {
+        *sample_rate = Value::String("96000".into());
+    }
+}
+
+/// The low battery and minimized notifications were always shown, they became events
+fn v1_to_v2(table: &mut Table) {
+    if let Some(Value::Array(events)) = table.get_mut("notify_events") {
+        for event in ["LowBattery", "MinimizedToTray"] {
+            events.push(Value::String(event.into()));
+        }
+    }
+}
+
+/// Copy of the file kept before it is changed, like `AndroidMic.toml.v0-2024-01-31_12-00-00.bak`
+fn backup_path(path: &Path, reason: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(
+        ".{reason}-{}.bak",
+        Local::now().format("%Y-%m-%d_%H-%M-%S")
+    ));
+    path.with_file_name(file_name)
+}
+
+/// Keys of `table` that can't be read as a field of `T`. Each field has a default,
+/// so they can be checked alone.
+fn invalid_keys<T: DeserializeOwned>(table: &Table) -> Vec<String> {
+    table
+        .iter()
+        .filter(|(key, value)| {
+            let field = Table::from_iter([((*key).clone(), (*value).clone())]);
+            Value::Table(field).try_into::<T>().is_err()
+        })
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// Drop the invalid fields of each profile, and the entries that are not profiles at all,
+/// so one bad entry doesn't lose the others
+fn drop_invalid_profile_fields(table: &mut Table) -> Vec<String> {
+    let Some(Value::Array(profiles)) = table.get_mut("profiles") else {
+        return Vec::new();
+    };
+
+    let mut invalid = Vec::new();
+    let mut index = 0;
+    profiles.retain_mut(|profile| {
+        let key = format!("profiles[{index}]");
+        index += 1;
+
+        let Value::Table(profile) = profile else {
+            warn!("invalid value for {key} in config: {profile:?}");
+            invalid.push(key);
+            return false;
+        };
+
+        for field in invalid_keys::<Profile>(profile) {
+            let value = profile.remove(&field);
+            warn!("invalid value for {key}.{field} in config: {value:?}");
+            invalid.push(format!("{key}.{field}"));
+        }
+        true
+    });
+
+    invalid
+}
+
+/// Migrate the config file in place, and return the warnings to show to the user
+pub fn migrate_config_file(path: &Path) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return warnings,
+        Err(e) => {
+            error!("can't read config {}: {e}", path.display());
+            warnings.push(format!("Can't read the config: {e}"));
+            return warnings;
+        }
+    };
+
+    let mut table = match content.parse::<Table>() {
+        Ok(table) => table,
+        Err(e) => {
+            // moved away, so the default config doesn't overwrite it
+            let backup = backup_path(path, "corrupt");
+            error!("corrupt config, moved to {}: {e}", backup.display());
+            match std::fs::rename(path, &backup) {
+                Ok(()) => warnings.push(format!(
+                    "The config file is corrupt and was moved to `{}`, using the default settings",
+                    backup.display()
+                )),
+                Err(e) => warnings.push(format!("The config file is corrupt: {e}")),
+            }
+            return warnings;
+        }
+    };
+
+    let version = table
+        .get("version")
+        .and_then(Value::as_integer)
+        .unwrap_or_default()
+        .max(0) as u32;
+
+    let mut changed = version != CONFIG_VERSION;
+    // the file is copied before any rewrite
+    let backup = backup_path(path, &format!("v{version}"));
+
+    if changed {
+        if version > CONFIG_VERSION {
+            warn!("config version {version} is newer than {CONFIG_VERSION}");
+            warnings.push(format!(
+                "The config was written by a newer version of the app, unknown settings are ignored. A copy was kept in `{}`",
+                backup.display()
+            ));
+        } else {
+            info!("migrating config from version {version} to {CONFIG_VERSION}");
+            for migration in &MIGRATIONS[version as usize..] {
+                migration(&mut table);
+            }
+        }
+
+        table.insert("version".into(), Value::Integer(CONFIG_VERSION.into()));
+    }
+
+    let mut invalid = drop_invalid_profile_fields(&mut table);
+    for key in invalid_keys::<Config>(&table) {
+        let value = table.remove(&key);
+        warn!("invalid value for {key} in config: {value:?}");
+        invalid.push(key);
+    }
+
+    for key in &invalid {
+        warnings.push(format!(
+            "Invalid value for `{key}` in the config, using the default"
+        ));
+    }
+    if !invalid.is_empty() {
+        warnings.push(format!(
+            "A copy of the previous config was kept in `{}`",
+            backup.display()
+        ));
+        changed = true;
+    }
+
+    if changed {
+        if let Err(e) = std::fs::copy(path, &backup) {
+            error!("can't back up config to {}: {e}", backup.display());
+        }
+
+        let res = toml::to_string_pretty(&table)
+            .map_err(|e| e.to_string())
+            .and_then(|content| std::fs::write(path, content).map_err(|e| e.to_string()));
+        if let Err(e) = res {
+            error!("can't write migrated config: {e}");
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_values_are_dropped_and_others_kept() {
+        let dir = std::env::temp_dir().join(format!("android-mic-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("AndroidMic.toml");
+        std::fs::write(
+            &path,
+            "sample_rate = \"96600\"\ndenoise = true\ndenoise_kind = \"DeepFilter\"\n",
+        )
+        .unwrap();
+
+        let warnings = migrate_config_file(&path);
+        let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let backups = std::fs::read_dir(&dir).unwrap().count() - 1;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("denoise_kind"));
+        assert_eq!(config.sample_rate.to_number(), 96000);
+        assert!(config.denoise);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(backups, 1);
+    }
+
+    #[test]
+    fn invalid_profile_fields_are_dropped_and_other_profiles_kept() {
+        let dir = std::env::temp_dir().join(format!(
+            "android-mic-config-profiles-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("AndroidMic.toml");
+        std::fs::write(
+            &path,
+            format!(
+                "version = {CONFIG_VERSION}\nprofiles = [\n  {{ name = \"bad\", denoise = true, denoise_kind = \"DeepFilter\" }},\n  \"not a profile\",\n  {{ name = \"good\", port = 1234 }},\n]\n"
+            ),
+        )
+        .unwrap();
+
+        let warnings = migrate_config_file(&path);
+        let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let backups = std::fs::read_dir(&dir).unwrap().count() - 1;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("profiles[0].denoise_kind"));
+        assert!(warnings[1].contains("profiles[1]"));
+        // the current version is backed up too before the rewrite
+        assert_eq!(backups, 1);
+
+        let names: Vec<&str> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["bad", "good"]);
+        assert!(config.profiles[0].denoise);
+        assert_eq!(config.profiles[1].port, 1234);
+    }
+}
@@ -2,7 +2,10 @@
 extern crate log;
 
 pub mod audio;
+pub mod cli;
 pub mod config;
+pub mod config_migration;
+pub mod profile;
 pub mod single_instance;
 pub mod start_at_login;
 pub mod streamer;
@@ -1,12 +1,12 @@
 // to not launch a console on Windows, only in release because it blocks all logs
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use android_mic::{localize, single_instance};
+use android_mic::{cli, config_migration, localize, single_instance, start_at_login};
 use chrono::Local;
 use std::io::Write;
 use std::{fs::File, path::Path};
 
-use android_mic::config::{Args, Config};
+use android_mic::config::{Args, Config, ConnectionMode};
 use android_mic::ui::app::run_ui;
 use android_mic::utils::{self, APP, ORG, QUALIFIER};
 use clap::Parser;
@@ -70,6 +70,14 @@ fn main() {
         .parse_default_env()
         .init();
 
+    let args = Args::parse();
+
+    if args.list_devices || args.list_adapters {
+        cli::attach_console();
+        println!("{}", cli::listing(args.list_devices, args.list_adapters));
+        return;
+    }
+
     // ensure single instance
     let instance_lock_path = if cfg!(debug_assertions) {
         std::path::PathBuf::from("log").join("app.lock")
@@ -83,8 +91,15 @@ fn main() {
             instance_lock_path
         );
 
-        if let Err(e) = single_instance::send_event(single_instance::IpcEvent::Show) {
-            error!("can't send ipc event {e}");
+        let events = args
+            .profile
+            .map(single_instance::IpcEvent::SwitchProfile)
+            .into_iter()
+            .chain([single_instance::IpcEvent::Show]);
+        for event in events {
+            if let Err(e) = single_instance::send_event(event) {
+                error!("can't send ipc event {e}");
+            }
         }
         return;
     }
@@ -109,11 +124,50 @@ fn main() {
     std::fs::create_dir_all(config_path).expect("Failed to create config directory");
     let config_file_path = config_path.join(format!("{APP}.toml"));
 
-    let mut config: ConfigManager<Config> = ConfigManager::new(config_file_path.clone());
+    // the session works on a copy, so the config file is left untouched, migration included
+    let session_config_path = if args.no_save {
+        let session_path = log_path.join(format!("{APP}.session.toml"));
+        let _ = std::fs::remove_file(&session_path);
+        if config_file_path.exists()
+            && let Err(e) = std::fs::copy(&config_file_path, &session_path)
+        {
+            error!("can't copy config: {e}");
+        }
+        session_path
+    } else {
+        config_file_path.clone()
+    };
 
-    let args = Args::parse();
+    let mut config_warnings = config_migration::migrate_config_file(&session_config_path);
+    if args.no_save {
+        config_warnings.push("Settings changed in this session are not saved".into());
+    }
+
+    let mut config: ConfigManager<Config> = ConfigManager::new(session_config_path);
+
+    let mut invalid_values = Vec::new();
+    config.update_without_write(|config| invalid_values = config.validate());
+    if !invalid_values.is_empty() {
+        for warning in &invalid_values {
+            warn!("{warning}");
+        }
+        // save the fixed values
+        config.update(|_| {});
+        config_warnings.extend(invalid_values);
+    }
+
+    if !args.no_save {
+        start_at_login::reconcile(&mut config);
+    }
 
     config.update_without_write(|config| {
+        if let Some(profile) = &args.profile {
+            match config.switch_profile(profile) {
+                Ok(warnings) => config_warnings.extend(warnings),
+                Err(e) => error!("can't apply profile: {e}"),
+            }
+        }
+
         if let Some(ip) = args.ip {
             config.ip.replace(ip);
         }
@@ -122,9 +176,42 @@ fn main() {
             config.connection_mode = connection_mode;
         }
 
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(bind_all_interfaces) = args.bind_all_interfaces {
+            config.bind_all_interfaces = bind_all_interfaces;
+        }
+        if !args.extra_adapters.is_empty() {
+            config.extra_adapters = args.extra_adapters;
+        }
+        if !args.allowed_subnets.is_empty() {
+            config.allowed_subnets = args.allowed_subnets;
+        }
+
+        if let Some(adb_device) = args.adb_device {
+            config.adb_device.replace(adb_device);
+        }
+        if let Some(usb_device) = args.usb_device {
+            config.usb_device.replace(usb_device);
+        }
+
+        if let Some(keep_listening) = args.keep_listening {
+            config.keep_listening = keep_listening;
+        }
+        if let Some(reconnect_max_retries) = args.reconnect_max_retries {
+            config.reconnect_max_retries = reconnect_max_retries;
+        }
+        if let Some(capture_traffic) = args.capture_traffic {
+            config.capture_traffic = capture_traffic;
+        }
+
         if let Some(output_device) = args.output_device {
             config.device_id.replace(output_device);
         }
+        if !args.preferred_devices.is_empty() {
+            config.preferred_devices = args.preferred_devices;
+        }
 
         if let Some(audio_format) = args.audio_format {
             config.audio_format = audio_format;
@@ -136,14 +223,85 @@ fn main() {
         if let Some(sample_rate) = args.sample_rate {
             config.sample_rate = sample_rate;
         }
+
+        if let Some(replay_file) = args.replay_file {
+            config.connection_mode = ConnectionMode::File;
+            config.replay_file.replace(replay_file);
+        }
+        if let Some(replay_loop) = args.replay_loop {
+            config.replay_loop = replay_loop;
+        }
+
+        if let Some(denoise) = args.denoise {
+            config.denoise = denoise;
+        }
+        if let Some(denoise_kind) = args.denoise_kind {
+            config.denoise_kind = denoise_kind;
+        }
+        if let Some(speex_noise_suppress) = args.speex_noise_suppress {
+            config.speex_noise_suppress = speex_noise_suppress;
+        }
+        if let Some(speex_vad_enabled) = args.speex_vad_enabled {
+            config.speex_vad_enabled = speex_vad_enabled;
+        }
+        if let Some(speex_vad_threshold) = args.speex_vad_threshold {
+            config.speex_vad_threshold = speex_vad_threshold;
+        }
+        if let Some(speex_agc_enabled) = args.speex_agc_enabled {
+            config.speex_agc_enabled = speex_agc_enabled;
+        }
+        if let Some(speex_agc_target) = args.speex_agc_target {
+            config.speex_agc_target = speex_agc_target;
+        }
+        if let Some(speex_dereverb_enabled) = args.speex_dereverb_enabled {
+            config.speex_dereverb_enabled = speex_dereverb_enabled;
+        }
+        if let Some(speex_dereverb_level) = args.speex_dereverb_level {
+            config.speex_dereverb_level = speex_dereverb_level;
+        }
+        if let Some(amplify) = args.amplify {
+            config.amplify = amplify;
+        }
+        if let Some(amplify_value) = args.amplify_value {
+            config.amplify_value = amplify_value;
+        }
+        if let Some(post_effect) = args.post_effect {
+            config.post_effect = post_effect;
+        }
+
+        if let Some(theme) = args.theme {
+            config.theme = theme;
+        }
+        if let Some(auto_connect) = args.auto_connect {
+            config.auto_connect = auto_connect;
+        }
+        if let Some(start_minimized) = args.start_minimized {
+            config.start_minimized = start_minimized;
+        }
+        if !args.notify_events.is_empty() {
+            config.notify_events = args.notify_events;
+        }
+        if let Some(notify_only_when_hidden) = args.notify_only_when_hidden {
+            config.notify_only_when_hidden = notify_only_when_hidden;
+        }
+
+        for warning in config.validate() {
+            warn!("{warning}");
+            config_warnings.push(warning);
+        }
     });
 
+    if let Some(start_at_login) = args.start_at_login {
+        start_at_login::start_at_login(start_at_login, &mut config);
+    }
+
     localize::localize();
 
     let flags = Flags {
         config,
         config_path: config_file_path.to_string_lossy().to_string(),
         log_path: log_file_path.to_string_lossy().to_string(),
+        config_warnings,
         launched_automatically: args.launched_automatically,
     };
 
@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    AudioEffect, AudioFormat, ChannelCount, Config, ConnectionMode, DenoiseKind, SampleRate, Subnet,
+};
+
+/// Named set of audio, processing and connection settings.
+///
+/// Settings tied to this computer (network adapter, output device, phone serials)
+/// are left out, so a profile can be shared with other people.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub connection_mode: ConnectionMode,
+    pub bind_all_interfaces: bool,
+    pub allowed_subnets: Vec<Subnet>,
+    pub port: u16,
+    pub keep_listening: bool,
+    pub reconnect_max_retries: u32,
+    pub audio_format: AudioFormat,
+    pub channel_count: ChannelCount,
+    pub sample_rate: SampleRate,
+    pub denoise: bool,
+    pub denoise_kind: DenoiseKind,
+    pub speex_noise_suppress: i32,
+    pub speex_vad_enabled: bool,
+    pub speex_vad_threshold: u32,
+    pub speex_agc_enabled: bool,
+    pub speex_agc_target: u32,
+    pub speex_dereverb_enabled: bool,
+    pub speex_dereverb_level: f32,
+    pub amplify: bool,
+    pub amplify_value: f32,
+    pub post_effect: AudioEffect,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::from_config(String::new(), &Config::default())
+    }
+}
+
+impl Profile {
+    /// Snapshot of the current settings
+    pub fn from_config(name: String, config: &Config) -> Self {
+        Self {
+            name,
+            connection_mode: config.connection_mode,
+            bind_all_interfaces: config.bind_all_interfaces,
+            allowed_subnets: config.allowed_subnets.clone(),
+            port: config.port,
+            keep_listening: config.keep_listening,
+            reconnect_max_retries: config.reconnect_max_retries,
+            audio_format: config.audio_format.clone(),
+            channel_count: config.channel_count.clone(),
+            sample_rate: config.sample_rate,
+            denoise: config.denoise,
+            denoise_kind: config.denoise_kind.clone(),
+            speex_noise_suppress: config.speex_noise_suppress,
+            speex_vad_enabled: config.speex_vad_enabled,
+            speex_vad_threshold: config.speex_vad_threshold,
+            speex_agc_enabled: config.speex_agc_enabled,
+            speex_agc_target: config.speex_agc_target,
+            speex_dereverb_enabled: config.speex_dereverb_enabled,
+            speex_dereverb_level: config.speex_dereverb_level,
+            amplify: config.amplify,
+            amplify_value: config.amplify_value,
+            post_effect: config.post_effect.clone(),
+        }
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        config.connection_mode = self.connection_mode;
+        config.bind_all_interfaces = self.bind_all_interfaces;
+        config.allowed_subnets = self.allowed_subnets.clone();
+        config.port = self.port;
+        config.keep_listening = self.keep_listening;
+        config.reconnect_max_retries = self.reconnect_max_retries;
+        config.audio_format = self.audio_format.clone();
+        config.channel_count = self.channel_count.clone();
+        config.sample_rate = self.sample_rate;
+        config.denoise = self.denoise;
+        config.denoise_kind = self.denoise_kind.clone();
+        config.speex_noise_suppress = self.speex_noise_suppress;
+        config.speex_vad_enabled = self.speex_vad_enabled;
+        config.speex_vad_threshold = self.speex_vad_threshold;
+        config.speex_agc_enabled = self.speex_agc_enabled;
+        config.speex_agc_target = self.speex_agc_target;
+        config.speex_dereverb_enabled = self.speex_dereverb_enabled;
+        config.speex_dereverb_level = self.speex_dereverb_level;
+        config.amplify = self.amplify;
+        config.amplify_value = self.amplify_value;
+        config.post_effect = self.post_effect.clone();
+        config.active_profile = Some(self.name.clone());
+    }
+
+    /// Write the profile as a standalone toml file
+    pub fn export(&self, path: &Path) -> anyhow::Result<()> {
+        let content = toml::to_string_pretty(self)?;
+        std::fs::write(path, content).with_context(|| format!("can't write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Read a profile exported with [`Profile::export`].
+    /// Without a name, the profile is named after the file.
+    pub fn import(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        let mut profile: Profile = toml::from_str(&content)
+            .with_context(|| format!("{} is not a valid profile", path.display()))?;
+
+        if profile.name.trim().is_empty() {
+            profile.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .context("the profile has no name")?;
+        }
+
+        Ok(profile)
+    }
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Add the profile, replacing the one with the same name
+    pub fn insert_profile(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn remove_profile(&mut self, name: &str) {
+        self.profiles.retain(|profile| profile.name != name);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+    }
+
+    /// Apply the settings of a saved profile, returning the validation warnings
+    pub fn switch_profile(&mut self, name: &str) -> Result<Vec<String>, String> {
+        let profile = self
+            .profile(name)
+            .cloned()
+            .ok_or_else(|| format!("no profile named {name}"))?;
+        profile.apply(self);
+        Ok(self.validate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_profile_is_imported_back() {
+        let mut config = Config {
+            sample_rate: SampleRate::from_number(48000).unwrap(),
+            audio_format: AudioFormat::F32,
+            denoise: true,
+            post_effect: AudioEffect::Robot,
+            ..Default::default()
+        };
+        let path =
+            std::env::temp_dir().join(format!("android-mic-profile-{}.toml", std::process::id()));
+
+        Profile::from_config("recording".into(), &config)
+            .export(&path)
+            .unwrap();
+        let profile = Profile::import(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        config = Config {
+            device_id: Some("speakers".into()),
+            ..Default::default()
+        };
+        config.insert_profile(profile);
+        config.switch_profile("recording").unwrap();
+
+        assert_eq!(config.sample_rate.to_number(), 48000);
+        assert_eq!(config.audio_format, AudioFormat::F32);
+        assert!(config.denoise);
+        assert_eq!(config.post_effect, AudioEffect::Robot);
+        assert_eq!(config.device_id.as_deref(), Some("speakers"));
+        assert_eq!(config.active_profile.as_deref(), Some("recording"));
+    }
+}
@@ -1,34 +1,48 @@
-use byteorder::WriteBytesExt;
+use std::io::Write;
+
+use byteorder::{BigEndian, WriteBytesExt};
 use futures::stream::Stream;
 use interprocess::local_socket::traits::Stream as InterprocessStreamTrait;
 use interprocess::local_socket::traits::tokio::Listener as TokioListener;
 use interprocess::local_socket::{GenericNamespaced, ListenerOptions, ToNsName};
 use interprocess::local_socket::{Name, Stream as InterprocessStream};
 
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use async_stream::stream;
 
 #[derive(Debug, Clone)]
 pub enum IpcEvent {
     Show,
+    SwitchProfile(String),
 }
 
-impl TryFrom<u8> for IpcEvent {
-    type Error = ();
+const SHOW: u8 = 0;
+const SWITCH_PROFILE: u8 = 1;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(IpcEvent::Show),
-            _ => Err(()),
+impl IpcEvent {
+    /// A tag byte, followed by a length prefixed string for `SwitchProfile`
+    fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            IpcEvent::Show => writer.write_u8(SHOW),
+            IpcEvent::SwitchProfile(name) => {
+                writer.write_u8(SWITCH_PROFILE)?;
+                writer.write_u16::<BigEndian>(name.len() as u16)?;
+                writer.write_all(name.as_bytes())
+            }
         }
     }
-}
 
-impl From<IpcEvent> for u8 {
-    fn from(value: IpcEvent) -> Self {
-        match value {
-            IpcEvent::Show => 0,
+    async fn read(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Option<Self>> {
+        match reader.read_u8().await? {
+            SHOW => Ok(Some(IpcEvent::Show)),
+            SWITCH_PROFILE => {
+                let len = reader.read_u16().await?;
+                let mut name = vec![0; len as usize];
+                reader.read_exact(&mut name).await?;
+                Ok(String::from_utf8(name).ok().map(IpcEvent::SwitchProfile))
+            }
+            _ => Ok(None),
         }
     }
 }
@@ -50,13 +64,11 @@ pub fn stream() -> anyhow::Result<impl Stream<Item = IpcEvent>> {
             match listener.accept().await {
                 Ok(mut client) => {
                     loop {
-                        match client.read_u8().await {
-                            Ok(byte) => match byte.try_into() {
-                                Ok(event) => yield event,
-                                Err(_) => {
-                                    error!("can't parse ipc event");
-                                }
-                            },
+                        match IpcEvent::read(&mut client).await {
+                            Ok(Some(event)) => yield event,
+                            Ok(None) => {
+                                error!("can't parse ipc event");
+                            }
                             Err(e) => {
                                 if e.kind() == std::io::ErrorKind::UnexpectedEof {
                                 } else {
@@ -83,7 +95,7 @@ pub fn send_event(event: IpcEvent) -> anyhow::Result<()> {
 
     let mut stream = InterprocessStream::connect(name)?;
 
-    stream.write_u8(event.into())?;
+    event.write(&mut stream)?;
 
     Ok(())
 }
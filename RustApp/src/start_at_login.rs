@@ -1,15 +1,41 @@
+use zconf::ConfigManager;
+
+use crate::config::Config;
+
 #[cfg(target_os = "windows")]
-pub use windows::start_at_login;
+pub use windows::{reconcile, set_start_at_login};
 
 #[cfg(target_os = "linux")]
-pub use linux::start_at_login;
+pub use linux::{reconcile, set_start_at_login};
 
 #[cfg(target_os = "macos")]
-pub use macos::start_at_login;
+pub use macos::{reconcile, set_start_at_login};
+
+/// Run a request from the command line, before the ui and its runtime are started
+fn block_on<F: Future>(future: F) -> anyhow::Result<F::Output> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future))
+}
+
+/// Blocking version of [`set_start_at_login`], for the command line
+pub fn start_at_login(start_at_login: bool, config: &mut ConfigManager<Config>) {
+    match block_on(set_start_at_login(start_at_login)).and_then(|res| res) {
+        Ok(enabled) => config.update(|s| s.start_at_login = enabled),
+        Err(e) => {
+            error!("can't change start at login: {e}");
+            config.update(|s| s.start_at_login = false);
+        }
+    }
+}
 
 #[cfg(target_os = "windows")]
 mod windows {
-    use std::{env, fs, path::Path};
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
 
     use directories::BaseDirs;
     use zconf::ConfigManager;
@@ -32,35 +58,153 @@ mod windows {
         Ok(())
     }
 
-    pub fn start_at_login(start_at_login: bool, config: &mut ConfigManager<Config>) {
+    fn shortcut_path() -> PathBuf {
         let dirs = BaseDirs::new().unwrap();
-        let file_path = dirs
-            .data_dir()
-            .join("Microsoft/Windows/Start Menu/Programs/Startup/AndroidMic.lnk");
+        dirs.data_dir()
+            .join("Microsoft/Windows/Start Menu/Programs/Startup/AndroidMic.lnk")
+    }
+
+    /// Create or remove the startup shortcut, return whether the app starts at login
+    pub async fn set_start_at_login(start_at_login: bool) -> anyhow::Result<bool> {
+        let file_path = shortcut_path();
 
         if start_at_login {
-            if let Err(e) = create_shortcut(&file_path) {
-                error!("can't create shortcut: {e}");
-                config.update(|s| s.start_at_login = false);
-            } else {
-                config.update(|s| s.start_at_login = true);
-            }
-        } else {
-            if let Err(e) = remove_shortcut(&file_path) {
-                error!("can't remove shortcut: {e}");
-            }
-            config.update(|s| s.start_at_login = false);
+            create_shortcut(&file_path)?;
+        } else if let Err(e) = remove_shortcut(&file_path) {
+            error!("can't remove shortcut: {e}");
+        }
+        Ok(start_at_login)
+    }
+
+    /// The shortcut can be removed from the startup apps settings
+    pub fn reconcile(config: &mut ConfigManager<Config>) {
+        let exists = shortcut_path().exists();
+        if config.data().start_at_login != exists {
+            config.update(|s| s.start_at_login = exists);
         }
     }
 }
 
 #[cfg(target_os = "linux")]
 mod linux {
-    use crate::config::Config;
+    use std::{fs, path::PathBuf};
+
+    use directories::BaseDirs;
     use zconf::ConfigManager;
 
-    pub fn start_at_login(_start_at_login: bool, _config: &mut ConfigManager<Config>) {
-        todo!()
+    use crate::{config::Config, utils::APP_ID};
+
+    /// XDG autostart entry, like `~/.config/autostart/io.github.teamclouday.AndroidMic.desktop`
+    fn desktop_file_path() -> anyhow::Result<PathBuf> {
+        let dirs = BaseDirs::new().ok_or_else(|| anyhow::anyhow!("no home directory"))?;
+        Ok(dirs
+            .config_dir()
+            .join("autostart")
+            .join(format!("{APP_ID}.desktop")))
+    }
+
+    fn exec_path() -> anyhow::Result<PathBuf> {
+        // the executable of an AppImage lives in a temporary mount
+        match std::env::var_os("APPIMAGE") {
+            Some(appimage) => Ok(PathBuf::from(appimage)),
+            None => Ok(std::env::current_exe()?),
+        }
+    }
+
+    fn create_desktop_file() -> anyhow::Result<()> {
+        let path = desktop_file_path()?;
+        let exec = exec_path()?;
+
+        let content = format!(
+            "[Desktop Entry]
+Type=Application
+Name=AndroidMic
+Comment=Use your Android phone as a mic for your PC
+Exec=\"{}\" --launched-automatically
+Icon={APP_ID}
+Terminal=false
+X-GNOME-Autostart-enabled=true
+",
+            exec.display()
+        );
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    fn remove_desktop_file() -> anyhow::Result<()> {
+        match fs::remove_file(desktop_file_path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The sandbox can't write in the autostart directory, the portal does it for us.
+    /// The user may be asked first, return whether autostart is enabled.
+    #[cfg(ANDROID_MIC_FORMAT = "flatpak")]
+    pub async fn set_start_at_login(start_at_login: bool) -> anyhow::Result<bool> {
+        use ashpd::desktop::background::Background;
+
+        let response = Background::request()
+            .reason("Start AndroidMic at login")
+            .auto_start(start_at_login)
+            .command(&["android-mic", "--launched-automatically"])
+            .dbus_activatable(false)
+            .send()
+            .await?
+            .response()?;
+        Ok(response.auto_start())
+    }
+
+    /// Create or remove the autostart entry, return whether the app starts at login
+    #[cfg(not(ANDROID_MIC_FORMAT = "flatpak"))]
+    pub async fn set_start_at_login(start_at_login: bool) -> anyhow::Result<bool> {
+        if start_at_login {
+            create_desktop_file()?;
+        } else if let Err(e) = remove_desktop_file() {
+            error!("can't remove autostart entry: {e}");
+        }
+        Ok(start_at_login)
+    }
+
+    /// The portal has no query, the request is sent again to learn the current state:
+    /// autostart can be revoked from the desktop settings
+    #[cfg(ANDROID_MIC_FORMAT = "flatpak")]
+    pub fn reconcile(config: &mut ConfigManager<Config>) {
+        if !config.data().start_at_login {
+            return;
+        }
+
+        match super::block_on(set_start_at_login(true)).and_then(|res| res) {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("start at login was disabled according to the background portal");
+                config.update(|s| s.start_at_login = false);
+            }
+            // the portal may not be running yet, keep the setting
+            Err(e) => warn!("can't check start at login: {e}"),
+        }
+    }
+
+    /// The entry can be removed from the desktop settings, or point to an old executable
+    #[cfg(not(ANDROID_MIC_FORMAT = "flatpak"))]
+    pub fn reconcile(config: &mut ConfigManager<Config>) {
+        let exists = desktop_file_path().is_ok_and(|path| path.exists());
+
+        if exists {
+            // refresh the executable path, it changes with updates
+            if let Err(e) = create_desktop_file() {
+                error!("can't update autostart entry: {e}");
+            }
+        }
+
+        if config.data().start_at_login != exists {
+            info!("start at login is {exists} according to the autostart entry");
+            config.update(|s| s.start_at_login = exists);
+        }
     }
 }
 
@@ -69,7 +213,9 @@ mod macos {
     use crate::config::Config;
     use zconf::ConfigManager;
 
-    pub fn start_at_login(_start_at_login: bool, _config: &mut ConfigManager<Config>) {
+    pub async fn set_start_at_login(_start_at_login: bool) -> anyhow::Result<bool> {
         todo!()
     }
+
+    pub fn reconcile(_config: &mut ConfigManager<Config>) {}
 }
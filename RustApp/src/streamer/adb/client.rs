@@ -0,0 +1,516 @@
+//! Client for the adb host protocol, spoken by the adb server on `localhost:5037`.
+//!
+//! A request is an ascii payload prefixed by its length as 4 hex digits.
+//! The server answers `OKAY`, or `FAIL` followed by a length prefixed message.
+//! See `protocol.txt` and `SERVICES.TXT` in the adb sources.
+
+use std::{
+    fmt::Display,
+    future::Future,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    process::Command,
+    time::timeout,
+};
+
+pub const DEFAULT_SERVER_PORT: u16 = 5037;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Pairing and connecting over Wi-Fi wait for the device to answer
+const WIRELESS_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const MDNS_PAIRING_SERVICE: &str = "_adb-tls-pairing._tcp";
+pub const MDNS_CONNECT_SERVICE: &str = "_adb-tls-connect._tcp";
+
+#[derive(Debug, Error)]
+pub enum AdbError {
+    #[error("can't reach the adb server: {0}")]
+    ServerUnreachable(io::Error),
+    #[error("can't start the adb server: {0}, make sure adb is installed and in your PATH")]
+    CantStartServer(io::Error),
+    #[error("adb start-server failed: {code:?}:{stderr}")]
+    StartServerFailed { code: Option<i32>, stderr: String },
+    #[error("adb server connection error: {0}")]
+    Io(#[from] io::Error),
+    #[error("the adb server didn't answer in {0:?}")]
+    Timeout(Duration),
+    #[error("adb: {0}")]
+    Failed(String),
+    #[error("unexpected answer from the adb server: {0:?}")]
+    Protocol(String),
+}
+
+impl AdbError {
+    /// The device or the server may come back by itself
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            AdbError::ServerUnreachable(..)
+                | AdbError::Io(..)
+                | AdbError::Timeout(..)
+                | AdbError::Failed(..)
+        )
+    }
+
+    /// The request targeted a forward that doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, AdbError::Failed(message) if message.contains("not found"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    Device,
+    Offline,
+    Unauthorized,
+    Other(String),
+}
+
+impl From<&str> for DeviceState {
+    fn from(state: &str) -> Self {
+        match state {
+            "device" => DeviceState::Device,
+            "offline" => DeviceState::Offline,
+            "unauthorized" => DeviceState::Unauthorized,
+            other => DeviceState::Other(other.to_string()),
+        }
+    }
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceState::Device => write!(f, "device"),
+            DeviceState::Offline => write!(f, "offline"),
+            DeviceState::Unauthorized => write!(f, "unauthorized"),
+            DeviceState::Other(state) => write!(f, "{state}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdbDevice {
+    pub serial: String,
+    pub state: DeviceState,
+    /// Only known once the device is authorized
+    pub model: Option<String>,
+}
+
+impl AdbDevice {
+    /// Only authorized and online devices accept services
+    pub fn is_ready(&self) -> bool {
+        self.state == DeviceState::Device
+    }
+}
+
+impl Display for AdbDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.model {
+            Some(model) => write!(f, "{model} ({})", self.serial)?,
+            None => write!(f, "{}", self.serial)?,
+        }
+
+        if !self.is_ready() {
+            write!(f, " - {}", self.state)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Device advertised on the local network by the adb server mDNS discovery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsService {
+    /// Like `adb-<serial>-<id>`, the same for pairing and connect services of a device
+    pub name: String,
+    pub service_type: String,
+    pub address: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdbClient {
+    addr: SocketAddr,
+    /// Run `adb start-server` when nothing listens on `addr`
+    start_server: bool,
+}
+
+impl Default for AdbClient {
+    fn default() -> Self {
+        // same variable as the adb binary
+        let port = std::env::var("ANDROID_ADB_SERVER_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_SERVER_PORT);
+
+        Self {
+            addr: SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port),
+            start_server: true,
+        }
+    }
+}
+
+impl AdbClient {
+    /// Client for a server that is already running, like a stand-in server
+    #[cfg(test)]
+    pub fn with_addr(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            start_server: false,
+        }
+    }
+
+    pub async fn devices(&self) -> Result<Vec<AdbDevice>, AdbError> {
+        let mut stream = self.connect().await?;
+        request(&mut stream, "host:devices-l").await?;
+        let list = read_string(&mut stream).await?;
+
+        Ok(parse_devices(&list))
+    }
+
+    /// Forward connections to `remote` on the device to `local` on the pc,
+    /// like `adb -s <serial> reverse <remote> <local>`
+    pub async fn reverse(&self, serial: &str, remote: &str, local: &str) -> Result<(), AdbError> {
+        self.device_request(serial, &format!("reverse:forward:{remote};{local}"))
+            .await
+    }
+
+    /// Like `adb -s <serial> reverse --remove <remote>`
+    pub async fn remove_reverse(&self, serial: &str, remote: &str) -> Result<(), AdbError> {
+        self.device_request(serial, &format!("reverse:killforward:{remote}"))
+            .await
+    }
+
+    /// Pair with a device showing a pairing code, like `adb pair <address> <code>`.
+    /// Return the name of the device, used by its mDNS services.
+    pub async fn pair(&self, address: &str, code: &str) -> Result<String, AdbError> {
+        let mut stream = self.connect().await?;
+        request(&mut stream, &format!("host:pair:{code}:{address}")).await?;
+        let answer = read_string_within(&mut stream, WIRELESS_TIMEOUT).await?;
+
+        // Successfully paired to 192.168.1.20:37099 [guid=adb-R58M123-a1b2c3]
+        if !answer.starts_with("Successfully paired") {
+            return Err(AdbError::Failed(answer));
+        }
+
+        let name = answer
+            .split_once("[guid=")
+            .and_then(|(_, guid)| guid.strip_suffix(']'))
+            .unwrap_or(address);
+
+        Ok(name.to_string())
+    }
+
+    /// Like `adb connect <address>`, the device is then attached with `address` as serial
+    pub async fn connect_device(&self, address: &str) -> Result<(), AdbError> {
+        let mut stream = self.connect().await?;
+        request(&mut stream, &format!("host:connect:{address}")).await?;
+        let answer = read_string_within(&mut stream, WIRELESS_TIMEOUT).await?;
+
+        if answer.starts_with("connected to") || answer.starts_with("already connected to") {
+            Ok(())
+        } else {
+            Err(AdbError::Failed(answer))
+        }
+    }
+
+    /// Services found on the local network, like `adb mdns services`
+    pub async fn mdns_services(&self) -> Result<Vec<MdnsService>, AdbError> {
+        let mut stream = self.connect().await?;
+        request(&mut stream, "host:mdns:services").await?;
+        let list = read_string(&mut stream).await?;
+
+        Ok(parse_mdns_services(&list))
+    }
+
+    /// Reverse forwards are handled by the device: the connection is first switched
+    /// to the device transport, then the device answers with a second status.
+    async fn device_request(&self, serial: &str, service: &str) -> Result<(), AdbError> {
+        let mut stream = self.connect().await?;
+        request(&mut stream, &format!("host:transport:{serial}")).await?;
+        request(&mut stream, service).await?;
+        read_status(&mut stream).await
+    }
+
+    async fn connect(&self) -> Result<TcpStream, AdbError> {
+        match with_timeout(TcpStream::connect(self.addr)).await {
+            Ok(stream) => Ok(stream),
+            Err(AdbError::Io(e))
+                if self.start_server && e.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                info!("adb server not running, starting it");
+                start_server(self.addr.port()).await?;
+
+                with_timeout(TcpStream::connect(self.addr))
+                    .await
+                    .map_err(|e| match e {
+                        AdbError::Io(e) => AdbError::ServerUnreachable(e),
+                        e => e,
+                    })
+            }
+            Err(AdbError::Io(e)) => Err(AdbError::ServerUnreachable(e)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The adb binary is only used to start the server, which then keeps running
+async fn start_server(port: u16) -> Result<(), AdbError> {
+    let mut cmd = Command::new("adb");
+    cmd.arg("-P").arg(port.to_string()).arg("start-server");
+
+    // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+
+    let output = cmd.output().await.map_err(AdbError::CantStartServer)?;
+
+    if !output.status.success() {
+        return Err(AdbError::StartServerFailed {
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+async fn with_timeout<T>(future: impl Future<Output = io::Result<T>>) -> Result<T, AdbError> {
+    with_timeout_of(IO_TIMEOUT, future).await
+}
+
+async fn with_timeout_of<T>(
+    duration: Duration,
+    future: impl Future<Output = io::Result<T>>,
+) -> Result<T, AdbError> {
+    timeout(duration, future)
+        .await
+        .map_err(|_| AdbError::Timeout(duration))?
+        .map_err(AdbError::Io)
+}
+
+async fn request(stream: &mut TcpStream, payload: &str) -> Result<(), AdbError> {
+    if payload.len() > 0xFFFF {
+        return Err(AdbError::Protocol(format!("request too long: {payload}")));
+    }
+
+    let message = format!("{:04x}{payload}", payload.len());
+    with_timeout(stream.write_all(message.as_bytes())).await?;
+
+    read_status(stream).await
+}
+
+async fn read_status(stream: &mut TcpStream) -> Result<(), AdbError> {
+    let mut status = [0u8; 4];
+    with_timeout(stream.read_exact(&mut status)).await?;
+
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => Err(AdbError::Failed(read_string(stream).await?)),
+        _ => Err(AdbError::Protocol(
+            String::from_utf8_lossy(&status).to_string(),
+        )),
+    }
+}
+
+async fn read_string(stream: &mut TcpStream) -> Result<String, AdbError> {
+    read_string_within(stream, IO_TIMEOUT).await
+}
+
+async fn read_string_within(
+    stream: &mut TcpStream,
+    duration: Duration,
+) -> Result<String, AdbError> {
+    let mut len = [0u8; 4];
+    with_timeout_of(duration, stream.read_exact(&mut len)).await?;
+
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| AdbError::Protocol(String::from_utf8_lossy(&len).to_string()))?;
+
+    let mut data = vec![0u8; len];
+    with_timeout(stream.read_exact(&mut data)).await?;
+
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+/// Parse the answer of `host:devices-l`, one `<serial> <state> [key:value]...` per line
+fn parse_devices(list: &str) -> Vec<AdbDevice> {
+    list.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let serial = parts.next()?.to_string();
+            let state = parts.next()?.into();
+            let model = parts
+                .find_map(|part| part.strip_prefix("model:"))
+                .map(|model| model.replace('_', " "));
+
+            Some(AdbDevice {
+                serial,
+                state,
+                model,
+            })
+        })
+        .collect()
+}
+
+/// Parse the answer of `host:mdns:services`, one `<name>\t<type>\t<address>` per line
+fn parse_mdns_services(list: &str) -> Vec<MdnsService> {
+    list.lines()
+        .filter_map(|line| {
+            let mut parts = line.split('\t').map(str::trim);
+            Some(MdnsService {
+                name: parts.next()?.to_string(),
+                // some adb versions end the type with a dot
+                service_type: parts.next()?.trim_end_matches('.').to_string(),
+                address: parts.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Read one request sent to the stand-in server
+    async fn read_request(stream: &mut TcpStream) -> String {
+        read_string(stream).await.unwrap()
+    }
+
+    async fn write_string(stream: &mut TcpStream, data: &str) {
+        let message = format!("{:04x}{data}", data.len());
+        stream.write_all(message.as_bytes()).await.unwrap();
+    }
+
+    /// Start a stand-in adb server accepting one connection, handled by `serve`
+    async fn stand_in_server<F, Fut>(serve: F) -> AdbClient
+    where
+        F: FnOnce(TcpStream) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream).await;
+        });
+
+        AdbClient::with_addr(addr)
+    }
+
+    #[tokio::test]
+    async fn devices_are_listed_with_their_state() {
+        let client = stand_in_server(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:devices-l");
+            stream.write_all(b"OKAY").await.unwrap();
+            write_string(
+                &mut stream,
+                "R58M123                device usb:1-1 product:p7 model:Pixel_7 device:panther transport_id:1\n\
+                 emulator-5554          unauthorized transport_id:2\n",
+            )
+            .await;
+        })
+        .await;
+
+        let devices = client.devices().await.unwrap();
+
+        assert_eq!(
+            devices,
+            [
+                AdbDevice {
+                    serial: "R58M123".into(),
+                    state: DeviceState::Device,
+                    model: Some("Pixel 7".into()),
+                },
+                AdbDevice {
+                    serial: "emulator-5554".into(),
+                    state: DeviceState::Unauthorized,
+                    model: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reverse_switches_to_the_device_transport() {
+        let client = stand_in_server(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:transport:R58M123");
+            stream.write_all(b"OKAY").await.unwrap();
+            assert_eq!(
+                read_request(&mut stream).await,
+                "reverse:forward:tcp:55555;tcp:55555"
+            );
+            stream.write_all(b"OKAYOKAY").await.unwrap();
+        })
+        .await;
+
+        client
+            .reverse("R58M123", "tcp:55555", "tcp:55555")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_message_is_returned() {
+        let client = stand_in_server(|mut stream| async move {
+            read_request(&mut stream).await;
+            stream.write_all(b"OKAY").await.unwrap();
+            read_request(&mut stream).await;
+            stream.write_all(b"OKAYFAIL").await.unwrap();
+            write_string(&mut stream, "listener 'tcp:55555' not found").await;
+        })
+        .await;
+
+        let error = client
+            .remove_reverse("R58M123", "tcp:55555")
+            .await
+            .unwrap_err();
+
+        assert!(error.is_not_found(), "{error}");
+    }
+
+    #[tokio::test]
+    async fn unexpected_status_is_a_protocol_error() {
+        let client = stand_in_server(|mut stream| async move {
+            read_request(&mut stream).await;
+            stream.write_all(b"WHAT").await.unwrap();
+        })
+        .await;
+
+        let error = client.devices().await.unwrap_err();
+
+        assert!(matches!(error, AdbError::Protocol(status) if status == "WHAT"));
+    }
+
+    #[tokio::test]
+    async fn pairing_returns_the_device_name() {
+        let client = stand_in_server(|mut stream| async move {
+            assert_eq!(
+                read_request(&mut stream).await,
+                "host:pair:123456:192.168.1.20:37099"
+            );
+            stream.write_all(b"OKAY").await.unwrap();
+            write_string(
+                &mut stream,
+                "Successfully paired to 192.168.1.20:37099 [guid=adb-R58M123-a1b2c3]",
+            )
+            .await;
+        })
+        .await;
+
+        let name = client.pair("192.168.1.20:37099", "123456").await.unwrap();
+
+        assert_eq!(name, "adb-R58M123-a1b2c3");
+    }
+}
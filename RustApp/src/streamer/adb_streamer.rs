@@ -1,95 +1,218 @@
-use anyhow::Result;
-use tokio::process::Command;
+use std::net::SocketAddr;
 
 use crate::{
-    config::ConnectionMode,
-    streamer::{StreamerMsg, tcp_streamer},
+    config::{ConnectionMode, WirelessAdbDevice},
+    streamer::{PhoneControl, StreamerMsg, tcp_streamer},
 };
 
 use super::{
     AudioStream, ConnectError, StreamerTrait,
+    adb::client::{
+        AdbClient, AdbDevice, DeviceState, MDNS_CONNECT_SERVICE, MDNS_PAIRING_SERVICE, MdnsService,
+    },
+    capture::Capture,
     tcp_streamer::{TcpStreamer, TcpStreamerState},
 };
 
 pub struct AdbStreamer {
     tcp_streamer: TcpStreamer,
+    client: AdbClient,
+    /// Serial chosen by the user, the only ready device is used otherwise
+    requested_serial: Option<String>,
+    /// Paired phone of the requested serial, when it is a wireless device
+    wireless: Option<WirelessAdbDevice>,
+    /// Device with the reverse proxy
+    serial: String,
 }
 
-async fn get_connected_devices() -> Result<Vec<String>, ConnectError> {
-    let mut cmd = Command::new("adb");
-    cmd.arg("devices");
-
-    let output = exec_cmd(cmd).await?;
-    let mut devices = Vec::new();
+/// Devices attached to the adb server, for the user to choose from
+pub async fn list_devices() -> Result<Vec<AdbDevice>, String> {
+    AdbClient::default()
+        .devices()
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // Skip the first line which is "List of devices attached"
-    for line in output.lines().skip(1) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            devices.push(parts[0].to_string());
-        }
-    }
+/// Phones waiting to be paired, with wireless debugging pairing dialog open
+pub async fn discover_pairing_services() -> Result<Vec<MdnsService>, String> {
+    let services = AdbClient::default()
+        .mdns_services()
+        .await
+        .map_err(|e| e.to_string())?;
 
-    Ok(devices)
+    Ok(services
+        .into_iter()
+        .filter(|service| service.service_type == MDNS_PAIRING_SERVICE)
+        .collect())
 }
 
-async fn remove_adb_reverse_proxy(device_id: &str, port: u16) -> Result<(), ConnectError> {
-    let mut cmd = Command::new("adb");
-    cmd.arg("-s")
-        .arg(device_id)
-        .arg("reverse")
-        .arg("--remove")
-        .arg(format!("tcp:{}", port));
+/// Pair with the code shown by the phone, then connect to it
+pub async fn pair_wireless(
+    address: String,
+    code: String,
+) -> Result<(WirelessAdbDevice, String), String> {
+    let client = AdbClient::default();
+    let name = client
+        .pair(&address, &code)
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("paired with {name} on {address}");
 
-    exec_cmd(cmd).await?;
+    let device = WirelessAdbDevice {
+        name,
+        address: None,
+    };
+    connect_wireless(&client, device)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    Ok(())
+/// Connect to a paired phone, return the updated device and its serial
+pub async fn reconnect_wireless(
+    device: WirelessAdbDevice,
+) -> Result<(WirelessAdbDevice, String), String> {
+    connect_wireless(&AdbClient::default(), device)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-async fn exec_cmd(mut cmd: Command) -> Result<String, ConnectError> {
-    // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+async fn connect_wireless(
+    client: &AdbClient,
+    device: WirelessAdbDevice,
+) -> Result<(WirelessAdbDevice, String), ConnectError> {
+    // the connect port is random, the phone advertises it while wireless debugging is on
+    let advertised = match client.mdns_services().await {
+        Ok(services) => services
+            .into_iter()
+            .find(|service| {
+                service.service_type == MDNS_CONNECT_SERVICE && service.name == device.name
+            })
+            .map(|service| service.address),
+        Err(e) => {
+            warn!("can't discover adb devices: {e}");
+            None
+        }
+    };
 
-    let status = cmd.output().await.map_err(ConnectError::CommandFailed)?;
+    let address = advertised
+        .or(device.address)
+        .ok_or_else(|| ConnectError::AdbWirelessNotFound(device.name.clone()))?;
 
-    if !status.status.success() {
-        let stderr = String::from_utf8_lossy(&status.stderr).to_string();
+    client.connect_device(&address).await?;
+    info!("connected to {} on {address}", device.name);
 
-        return Err(ConnectError::AdbStatusCommand {
-            code: status.status.code(),
-            stderr,
-        });
-    }
-    let stdout = String::from_utf8_lossy(&status.stdout).trim().to_string();
-    Ok(stdout)
+    let device = WirelessAdbDevice {
+        name: device.name,
+        address: Some(address.clone()),
+    };
+    Ok((device, address))
 }
 
-pub async fn new(port: u16, stream_config: AudioStream) -> Result<AdbStreamer, ConnectError> {
-    let tcp_streamer = tcp_streamer::new("127.0.0.1".parse().unwrap(), port, stream_config).await?;
+/// Find the device to use. A detached wireless device is connected again, at the address
+/// it advertises when it is a paired phone: `requested_serial` and `wireless` are updated then.
+async fn select_device(
+    client: &AdbClient,
+    requested_serial: &mut Option<String>,
+    wireless: &mut Option<WirelessAdbDevice>,
+) -> Result<String, ConnectError> {
+    let mut devices = client.devices().await?;
 
-    let devices = get_connected_devices().await?;
-    if devices.is_empty() {
-        return Err(ConnectError::NoAdbDevice);
+    // a wireless device is detached when the phone sleeps or the pc restarts
+    if let Some(serial) = requested_serial.as_deref()
+        && serial.parse::<SocketAddr>().is_ok()
+        && !devices.iter().any(|device| device.serial == serial)
+    {
+        match wireless.take() {
+            // the port changes each time wireless debugging is toggled
+            Some(device) => {
+                info!("connecting to wireless adb device {}", device.name);
+                let (device, address) = connect_wireless(client, device).await?;
+                *wireless = Some(device);
+                *requested_serial = Some(address);
+            }
+            None => {
+                info!("connecting to wireless adb device {serial}");
+                client.connect_device(serial).await?;
+            }
+        }
+        devices = client.devices().await?;
     }
 
-    for device_id in &devices {
-        if let Err(e) = remove_adb_reverse_proxy(device_id, tcp_streamer.port).await
-            && !e.to_string().contains("not found")
-        {
-            warn!("cannot remove adb proxy for device {device_id}: {e}");
+    let device = match requested_serial.as_deref() {
+        Some(serial) => devices
+            .into_iter()
+            .find(|device| device.serial == serial)
+            .ok_or_else(|| ConnectError::AdbDeviceNotFound(serial.to_string()))?,
+        None => {
+            let ready = devices.iter().filter(|device| device.is_ready()).count();
+            if ready > 1 {
+                warn!("{ready} adb devices are ready, choose one to avoid using the first one");
+            }
+
+            match devices.iter().position(AdbDevice::is_ready) {
+                Some(index) => devices.swap_remove(index),
+                None => devices
+                    .into_iter()
+                    .next()
+                    .ok_or(ConnectError::NoAdbDevice)?,
+            }
         }
+    };
+
+    match device.state {
+        DeviceState::Device => Ok(device.serial),
+        DeviceState::Unauthorized => Err(ConnectError::AdbUnauthorized(device.serial)),
+        state => Err(ConnectError::AdbDeviceNotReady {
+            serial: device.serial,
+            state: state.to_string(),
+        }),
+    }
+}
 
-        let mut cmd = Command::new("adb");
-        cmd.arg("-s")
-            .arg(device_id)
-            .arg("reverse")
-            .arg(format!("tcp:{}", tcp_streamer.port))
-            .arg(format!("tcp:{}", tcp_streamer.port));
-        exec_cmd(cmd).await?;
+async fn setup_adb_reverse_proxy(
+    client: &AdbClient,
+    serial: &str,
+    port: u16,
+) -> Result<(), ConnectError> {
+    let address = format!("tcp:{port}");
+
+    if let Err(e) = client.remove_reverse(serial, &address).await
+        && !e.is_not_found()
+    {
+        warn!("cannot remove adb proxy for device {serial}: {e}");
     }
 
-    let streamer = AdbStreamer { tcp_streamer };
+    client.reverse(serial, &address, &address).await?;
+    info!("adb reverse proxy set up on {serial} for port {port}");
+
+    Ok(())
+}
+
+pub async fn new(
+    port: u16,
+    mut requested_serial: Option<String>,
+    mut wireless: Option<WirelessAdbDevice>,
+    stream_config: AudioStream,
+) -> Result<AdbStreamer, ConnectError> {
+    let tcp_streamer = tcp_streamer::new(
+        vec!["127.0.0.1".parse().unwrap()],
+        port,
+        Vec::new(),
+        stream_config,
+    )
+    .await?;
+
+    let client = AdbClient::default();
+    let serial = select_device(&client, &mut requested_serial, &mut wireless).await?;
+    setup_adb_reverse_proxy(&client, &serial, tcp_streamer.port).await?;
+
+    let streamer = AdbStreamer {
+        tcp_streamer,
+        client,
+        requested_serial,
+        wireless,
+        serial,
+    };
     Ok(streamer)
 }
 
@@ -102,15 +225,32 @@ impl StreamerTrait for AdbStreamer {
         self.tcp_streamer.reconfigure_stream(config)
     }
 
+    fn capture(&mut self, capture: Capture) {
+        self.tcp_streamer.capture(capture)
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        // the reverse proxy is lost when the device is unplugged or adb restarts
+        self.serial =
+            select_device(&self.client, &mut self.requested_serial, &mut self.wireless).await?;
+        setup_adb_reverse_proxy(&self.client, &self.serial, self.tcp_streamer.port).await?;
+        self.tcp_streamer.reconnect().await
+    }
+
+    async fn send_control(&mut self, control: &PhoneControl) -> Result<(), ConnectError> {
+        self.tcp_streamer.send_control(control).await
+    }
+
     fn status(&self) -> StreamerMsg {
         match &self.tcp_streamer.state {
-            TcpStreamerState::Listening { .. } => StreamerMsg::Listening {
+            TcpStreamerState::Listening => StreamerMsg::Listening {
                 ip: None,
                 port: None,
             },
             TcpStreamerState::Streaming { .. } => StreamerMsg::Connected {
                 ip: None,
                 port: None,
+                peer: None,
                 mode: ConnectionMode::Adb,
             },
         }
@@ -119,14 +259,14 @@ impl StreamerTrait for AdbStreamer {
 
 impl Drop for AdbStreamer {
     fn drop(&mut self) {
-        let port = self.tcp_streamer.port;
+        let client = self.client.clone();
+        let serial = self.serial.clone();
+        let address = format!("tcp:{}", self.tcp_streamer.port);
         tokio::spawn(async move {
-            let devices: Vec<String> = get_connected_devices().await.unwrap_or_default();
-
-            for device_id in devices {
-                if let Err(e) = remove_adb_reverse_proxy(&device_id, port).await {
-                    warn!("cannot remove adb proxy for device {device_id}: {e}");
-                }
+            if let Err(e) = client.remove_reverse(&serial, &address).await
+                && !e.is_not_found()
+            {
+                warn!("cannot remove adb proxy for device {serial}: {e}");
             }
         });
     }
@@ -0,0 +1,228 @@
+//! Dump of the raw frames received from the phone, with their arrival time,
+//! to reproduce network and jitter issues with the replay streamer.
+//!
+//! Format: `MAGIC`, `VERSION`, then for each frame its arrival time in microseconds
+//! since the start of the capture (u64), its length (u32) and its bytes, all big endian.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::Instant,
+};
+
+pub const CAPTURE_EXTENSION: &str = "amicdump";
+
+const MAGIC: &[u8; 8] = b"AMICDUMP";
+const VERSION: u8 = 1;
+
+/// Frames bigger than this are a corrupted dump
+const MAX_FRAME_SIZE: u32 = 8 * 1024 * 1024;
+
+/// Arrival time and length, before the bytes of each frame
+const FRAME_HEADER_SIZE: usize = size_of::<u64>() + size_of::<u32>();
+
+/// Frames waiting to be written, the newest are dropped when the disk can't keep up
+const CAPTURE_QUEUE_SIZE: usize = 1024;
+
+pub struct Capture {
+    /// Frames and their arrival time in microseconds, written by a separate task
+    sender: Option<mpsc::Sender<(u64, Vec<u8>)>>,
+    /// Ends by itself once the capture is dropped, only awaited by the tests
+    #[cfg_attr(not(test), allow(dead_code))]
+    writer_task: JoinHandle<()>,
+    start: Instant,
+}
+
+impl Capture {
+    pub async fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path).await?);
+        writer.write_all(MAGIC).await?;
+        writer.write_u8(VERSION).await?;
+        writer.flush().await?;
+
+        info!("capturing received frames to {}", path.display());
+
+        let (sender, receiver) = mpsc::channel(CAPTURE_QUEUE_SIZE);
+        let writer_task = tokio::spawn(write_frames(path.to_path_buf(), writer, receiver));
+
+        Ok(Self {
+            sender: Some(sender),
+            writer_task,
+            start: Instant::now(),
+        })
+    }
+
+    /// Append a frame without waiting for the disk, the capture stops on the first write error
+    pub fn record(&mut self, frame: &[u8]) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let at = self.start.elapsed().as_micros() as u64;
+
+        match sender.try_send((at, frame.to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!("the capture can't keep up, a frame was dropped"),
+            // the writer stopped, the error is already logged
+            Err(TrySendError::Closed(_)) => self.sender = None,
+        }
+    }
+
+    /// Wait for the recorded frames to be written
+    #[cfg(test)]
+    pub async fn close(mut self) {
+        self.sender = None;
+        let _ = (&mut self.writer_task).await;
+    }
+}
+
+async fn write_frames(
+    path: PathBuf,
+    mut writer: BufWriter<File>,
+    mut receiver: mpsc::Receiver<(u64, Vec<u8>)>,
+) {
+    let res = async {
+        while let Some((at, frame)) = receiver.recv().await {
+            writer.write_u64(at).await?;
+            writer.write_u32(frame.len() as u32).await?;
+            writer.write_all(&frame).await?;
+            // keep the dump usable if the app is killed, without a flush per frame
+            if receiver.is_empty() {
+                writer.flush().await?;
+            }
+        }
+        writer.flush().await
+    }
+    .await;
+
+    if let Err(e) = res {
+        error!("capture to {} stopped: {e}", path.display());
+    }
+}
+
+pub struct CapturedFrame {
+    /// Arrival time since the start of the capture
+    pub at: Duration,
+    pub frame: Vec<u8>,
+}
+
+pub struct CaptureReader {
+    reader: BufReader<File>,
+    /// Header and bytes of the frame being read
+    partial: Vec<u8>,
+}
+
+impl CaptureReader {
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path).await?);
+
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic).await?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an AndroidMic capture",
+            ));
+        }
+
+        let version = reader.read_u8().await?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported capture version {version}"),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Next frame, `None` at the end of the dump.
+    ///
+    /// Cancel safe: the bytes already read are kept for the next call.
+    pub async fn read_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        loop {
+            let needed = match self.partial.get(size_of::<u64>()..FRAME_HEADER_SIZE) {
+                Some(len) => {
+                    let len = u32::from_be_bytes(len.try_into().unwrap());
+                    if len > MAX_FRAME_SIZE {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("frame of {len} bytes"),
+                        ));
+                    }
+                    FRAME_HEADER_SIZE + len as usize
+                }
+                None => FRAME_HEADER_SIZE,
+            };
+
+            if self.partial.len() == needed {
+                break;
+            }
+
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                if self.partial.len() >= size_of::<u64>() {
+                    warn!("the capture ends with a truncated frame");
+                }
+                self.partial.clear();
+                return Ok(None);
+            }
+
+            let count = available.len().min(needed - self.partial.len());
+            self.partial.extend_from_slice(&available[..count]);
+            self.reader.consume(count);
+        }
+
+        let frame = self.partial.split_off(FRAME_HEADER_SIZE);
+        let at = u64::from_be_bytes(self.partial[..size_of::<u64>()].try_into().unwrap());
+        self.partial.clear();
+
+        Ok(Some(CapturedFrame {
+            at: Duration::from_micros(at),
+            frame,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_are_read_back_in_order_with_their_timing() {
+        let path = std::env::temp_dir().join(format!(
+            "android-mic-capture-{}.{CAPTURE_EXTENSION}",
+            std::process::id()
+        ));
+
+        let mut capture = Capture::create(&path).await.unwrap();
+        capture.record(b"first");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        capture.record(b"");
+        capture.record(b"third");
+        capture.close().await;
+
+        let mut reader = CaptureReader::open(&path).await.unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = reader.read_frame().await.unwrap() {
+            frames.push(frame);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let data: Vec<&[u8]> = frames.iter().map(|f| f.frame.as_slice()).collect();
+        assert_eq!(data, [&b"first"[..], b"", b"third"]);
+        assert!(frames[1].at >= frames[0].at + Duration::from_millis(20));
+        assert!(frames[2].at >= frames[1].at);
+    }
+}
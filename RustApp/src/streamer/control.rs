@@ -0,0 +1,92 @@
+use crate::{audio::AudioPacketFormat, config::MicSource};
+
+use super::message::{
+    ControlMessage, FormatChangeRequest, MessageWrapper, MicSourceRequest, MuteRequest,
+    StopStreamRequest, control_message::Command, message_wrapper::Payload,
+};
+
+/// Commands sent by the pc to the phone, over the streaming connection
+#[derive(Debug, Clone)]
+pub enum PhoneControl {
+    ChangeFormat(AudioPacketFormat),
+    Mute(bool),
+    MicSource(MicSource),
+    StopStreaming,
+}
+
+impl PhoneControl {
+    pub fn to_message(&self) -> MessageWrapper {
+        let command = match self {
+            PhoneControl::ChangeFormat(format) => Command::FormatChange(FormatChangeRequest {
+                sample_rate: format.sample_rate.to_number(),
+                channel_count: format.channel_count.to_number().into(),
+                audio_format: format.audio_format.to_android_format(),
+            }),
+            PhoneControl::Mute(muted) => Command::Mute(MuteRequest { muted: *muted }),
+            PhoneControl::MicSource(source) => Command::MicSource(MicSourceRequest {
+                audio_source: source.to_android_source(),
+            }),
+            PhoneControl::StopStreaming => Command::Stop(StopStreamRequest {}),
+        };
+
+        MessageWrapper {
+            payload: Some(Payload::Control(ControlMessage {
+                command: Some(command),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use crate::config::{AudioFormat, ChannelCount, SampleRate};
+
+    use super::*;
+
+    fn round_trip(control: &PhoneControl) -> Command {
+        let bytes = control.to_message().encode_to_vec();
+        match MessageWrapper::decode(bytes.as_slice()).unwrap().payload {
+            Some(Payload::Control(ControlMessage {
+                command: Some(command),
+            })) => command,
+            payload => panic!("not a control message: {payload:?}"),
+        }
+    }
+
+    #[test]
+    fn control_messages_survive_encoding() {
+        let format = AudioPacketFormat {
+            sample_rate: SampleRate::from_number(44100).unwrap(),
+            audio_format: AudioFormat::F32,
+            channel_count: ChannelCount::Stereo,
+        };
+        assert_eq!(
+            round_trip(&PhoneControl::ChangeFormat(format)),
+            Command::FormatChange(FormatChangeRequest {
+                sample_rate: 44100,
+                channel_count: 2,
+                audio_format: AudioFormat::F32.to_android_format(),
+            })
+        );
+
+        assert_eq!(
+            round_trip(&PhoneControl::Mute(true)),
+            Command::Mute(MuteRequest { muted: true })
+        );
+
+        let source = MicSource::default();
+        assert_eq!(
+            round_trip(&PhoneControl::MicSource(source)),
+            Command::MicSource(MicSourceRequest {
+                audio_source: source.to_android_source(),
+            })
+        );
+
+        assert_eq!(
+            round_trip(&PhoneControl::StopStreaming),
+            Command::Stop(StopStreamRequest {})
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::bail;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::{
+    audio::process::ProcessCache,
+    config::{AudioFormat, ConnectionMode, SampleRate},
+};
+
+use super::{
+    AudioPacketMessage, AudioStream, ConnectError, PhoneControl, StreamerMsg, StreamerTrait,
+    capture::Capture,
+};
+
+/// Audio sent in each packet, close to what the phone app sends
+const PACKET_DURATION: Duration = Duration::from_millis(20);
+
+/// Audio file decoded in memory
+struct AudioFile {
+    /// Interleaved samples, in `audio_format`
+    buffer: Vec<u8>,
+    sample_rate: u32,
+    channel_count: u32,
+    audio_format: AudioFormat,
+}
+
+impl AudioFile {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let file = match extension.as_deref() {
+            Some("wav") => Self::read_wav(path)?,
+            Some("flac") => Self::read_flac(path)?,
+            _ => bail!("unsupported file type, use a wav or flac file"),
+        };
+
+        if SampleRate::from_number(file.sample_rate).is_none() {
+            bail!("unsupported sample rate {}", file.sample_rate);
+        }
+        if !(1..=2).contains(&file.channel_count) {
+            bail!(
+                "only mono and stereo files are supported, this one has {} channels",
+                file.channel_count
+            );
+        }
+        if file.buffer.is_empty() {
+            bail!("the file has no audio");
+        }
+
+        Ok(file)
+    }
+
+    fn read_wav(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let (audio_format, buffer) = match spec.sample_format {
+            hound::SampleFormat::Float => (
+                AudioFormat::F32,
+                encode(reader.samples::<f32>().map(|s| s.map(f32::to_ne_bytes)))?,
+            ),
+            hound::SampleFormat::Int => {
+                encode_int(reader.samples::<i32>(), spec.bits_per_sample as u32)?
+            }
+        };
+
+        Ok(Self {
+            buffer,
+            sample_rate: spec.sample_rate,
+            channel_count: spec.channels as u32,
+            audio_format,
+        })
+    }
+
+    fn read_flac(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = claxon::FlacReader::open(path)?;
+        let info = reader.streaminfo();
+        let (audio_format, buffer) = encode_int(reader.samples(), info.bits_per_sample)?;
+
+        Ok(Self {
+            buffer,
+            sample_rate: info.sample_rate,
+            channel_count: info.channels,
+            audio_format,
+        })
+    }
+
+    fn frame_size(&self) -> usize {
+        self.audio_format.sample_size() * self.channel_count as usize
+    }
+}
+
+fn encode<const N: usize, E>(
+    samples: impl Iterator<Item = Result<[u8; N], E>>,
+) -> anyhow::Result<Vec<u8>>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut buffer = Vec::new();
+    for sample in samples {
+        buffer.extend_from_slice(&sample?);
+    }
+    Ok(buffer)
+}
+
+/// Integer samples are widened to the closest format handled by the pipeline
+fn encode_int<E>(
+    samples: impl Iterator<Item = Result<i32, E>>,
+    bits_per_sample: u32,
+) -> anyhow::Result<(AudioFormat, Vec<u8>)>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if bits_per_sample <= 16 {
+        let shift = 16 - bits_per_sample;
+        let buffer = encode(samples.map(|s| s.map(|s| ((s << shift) as i16).to_ne_bytes())))?;
+        Ok((AudioFormat::I16, buffer))
+    } else {
+        let shift = 32 - bits_per_sample;
+        let buffer = encode(samples.map(|s| s.map(|s| (s << shift).to_ne_bytes())))?;
+        Ok((AudioFormat::I32, buffer))
+    }
+}
+
+/// Replay an audio file at real-time pace, as if it came from a phone
+pub struct FileStreamer {
+    path: PathBuf,
+    /// Start again from the beginning at the end of the file
+    looping: bool,
+    stream_config: AudioStream,
+    file: AudioFile,
+    /// Offset of the next packet in the file buffer
+    position: usize,
+    ticker: Interval,
+    process_cache: ProcessCache,
+}
+
+pub async fn new(
+    path: PathBuf,
+    looping: bool,
+    stream_config: AudioStream,
+) -> Result<FileStreamer, ConnectError> {
+    let file = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || AudioFile::open(&path)).await?
+    }
+    .map_err(|e| ConnectError::CantReadFile(path.display().to_string(), e.to_string()))?;
+
+    info!(
+        "replaying {}: {} Hz, {} channels, {}",
+        path.display(),
+        file.sample_rate,
+        file.channel_count,
+        file.audio_format
+    );
+
+    let mut ticker = tokio::time::interval(PACKET_DURATION);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let streamer = FileStreamer {
+        path,
+        looping,
+        stream_config,
+        file,
+        position: 0,
+        ticker,
+        process_cache: ProcessCache::new(),
+    };
+
+    Ok(streamer)
+}
+
+impl StreamerTrait for FileStreamer {
+    fn reconfigure_stream(&mut self, stream_config: AudioStream) {
+        self.stream_config = stream_config;
+        self.process_cache.clear();
+    }
+
+    fn capture(&mut self, _capture: Capture) {}
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        self.position = 0;
+        self.ticker.reset();
+        self.process_cache.clear();
+        Ok(())
+    }
+
+    async fn send_control(&mut self, _control: &PhoneControl) -> Result<(), ConnectError> {
+        Err(ConnectError::NotConnected)
+    }
+
+    fn status(&self) -> StreamerMsg {
+        StreamerMsg::Connected {
+            ip: None,
+            port: None,
+            peer: None,
+            mode: ConnectionMode::File,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
+        if self.position >= self.file.buffer.len() {
+            if !self.looping {
+                return Err(ConnectError::ReplayFinished(
+                    self.path.display().to_string(),
+                ));
+            }
+            debug!("replaying {} again", self.path.display());
+            self.position = 0;
+        }
+
+        self.ticker.tick().await;
+
+        let packet = self.next_packet();
+        let sample_rate = packet.sample_rate;
+
+        let res = self
+            .stream_config
+            .process_audio_packet(packet, &mut self.process_cache);
+        self.stream_config.take_overrun()?;
+
+        match res {
+            Ok(Some(buffer)) => Ok(Some(StreamerMsg::UpdateAudioWave {
+                data: AudioPacketMessage::to_wave_data(&buffer, sample_rate),
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl FileStreamer {
+    fn next_packet(&mut self) -> AudioPacketMessage {
+        let frames = self.file.sample_rate as usize * PACKET_DURATION.as_millis() as usize / 1000;
+        let end = (self.position + frames * self.file.frame_size()).min(self.file.buffer.len());
+
+        let packet = AudioPacketMessage {
+            buffer: self.file.buffer[self.position..end].to_vec(),
+            sample_rate: self.file.sample_rate,
+            channel_count: self.file.channel_count,
+            audio_format: self.file.audio_format.to_android_format(),
+        };
+
+        self.position = end;
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streamer::test_support::stream_config;
+
+    /// 50 ms of stereo 16 bits audio at 48 kHz
+    fn write_wav(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("android-mic-{name}-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..2400 * 2 {
+            writer.write_sample((i % 100) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn packets_follow_the_file_then_the_replay_finishes() {
+        let path = write_wav("file-streamer-finish");
+        let mut streamer = new(path.clone(), false, stream_config()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 20 ms packets: 960 frames of 4 bytes, the last one is shorter
+        let sizes: Vec<usize> = (0..3)
+            .map(|_| {
+                let packet = streamer.next_packet();
+                assert_eq!(packet.sample_rate, 48000);
+                assert_eq!(packet.channel_count, 2);
+                assert_eq!(packet.audio_format, AudioFormat::I16.to_android_format());
+                packet.buffer.len()
+            })
+            .collect();
+        assert_eq!(sizes, [3840, 3840, 1920]);
+
+        assert!(matches!(
+            streamer.next().await,
+            Err(ConnectError::ReplayFinished(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn looping_starts_again_at_the_end() {
+        let path = write_wav("file-streamer-loop");
+        let mut streamer = new(path.clone(), true, stream_config()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        streamer.position = streamer.file.buffer.len();
+        assert!(streamer.next().await.is_ok());
+        assert_eq!(streamer.position, 3840);
+    }
+}
@@ -0,0 +1,73 @@
+//! Handling of the frames received from the phone, shared by every transport
+//! and by the replay of their captures.
+
+use prost::{DecodeError, Message};
+
+use crate::audio::process::ProcessCache;
+
+use super::{
+    AudioPacketMessage, AudioStream, ConnectError, StreamerMsg,
+    message::{MessageWrapper, message_wrapper::Payload},
+};
+
+pub enum Frame {
+    Audio(AudioPacketMessage),
+    /// Any other message, answered by the streamer
+    Message(Payload),
+}
+
+/// Decode a frame. `tracked_sequence` follows the sequence numbers of the wrapped audio packets.
+pub fn decode_frame(
+    frame: &[u8],
+    tracked_sequence: &mut u32,
+) -> Result<Option<Frame>, DecodeError> {
+    // audio packets are sent without wrapper over tcp, for compatibility with older apps.
+    // a wrapper never decodes as an audio packet with a sample rate
+    if let Ok(packet) = AudioPacketMessage::decode(frame)
+        && packet.sample_rate != 0
+    {
+        return Ok(Some(Frame::Audio(packet)));
+    }
+
+    match MessageWrapper::decode(frame)?.payload {
+        Some(Payload::AudioPacket(packet)) => {
+            if packet.sequence_number < *tracked_sequence {
+                info!(
+                    "dropped packet: old sequence number {} < {}",
+                    packet.sequence_number, tracked_sequence
+                );
+            }
+            *tracked_sequence = packet.sequence_number;
+
+            Ok(packet.audio_packet.map(Frame::Audio))
+        }
+        Some(payload) => Ok(Some(Frame::Message(payload))),
+        None => {
+            debug!("ignoring message with an unknown payload");
+            Ok(None)
+        }
+    }
+}
+
+/// Send the audio to the output, and return the wave to show
+pub fn play_audio_packet(
+    stream_config: &mut AudioStream,
+    process_cache: &mut ProcessCache,
+    packet: AudioPacketMessage,
+) -> Result<Option<StreamerMsg>, ConnectError> {
+    let buffer_size = packet.buffer.len();
+    let sample_rate = packet.sample_rate;
+
+    let res = stream_config.process_audio_packet(packet, process_cache);
+    stream_config.take_overrun()?;
+
+    match res {
+        Ok(Some(buffer)) => {
+            debug!("received {} bytes", buffer_size);
+            Ok(Some(StreamerMsg::UpdateAudioWave {
+                data: AudioPacketMessage::to_wave_data(&buffer, sample_rate),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
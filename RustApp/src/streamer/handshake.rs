@@ -0,0 +1,206 @@
+use crate::{
+    audio::AudioPacketFormat,
+    config::{AudioFormat, ChannelCount, SampleRate},
+};
+
+use super::{
+    ConnectError,
+    message::{ConnectMessage, ConnectReplyMessage, MessageWrapper, message_wrapper::Payload},
+};
+
+/// Version of the protocol spoken after the handshake.
+/// Version 0 is used by apps sending an empty `ConnectMessage`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const CODEC_PCM: &str = "pcm";
+
+/// The phone answers to ping messages
+pub const FEATURE_HEARTBEAT: &str = "heartbeat";
+
+/// The phone accepts control messages
+pub const FEATURE_CONTROL: &str = "control";
+
+const SUPPORTED_FEATURES: [&str; 2] = [FEATURE_HEARTBEAT, FEATURE_CONTROL];
+
+/// Agree on a protocol version and a stream format with the phone.
+///
+/// Return `None` for legacy apps, which expect `CHECK_2` as answer.
+/// `output` is the format of the pc output stream, the closest format supported
+/// by the phone is chosen to avoid conversions.
+pub fn negotiate(
+    connect: &ConnectMessage,
+    output: &AudioPacketFormat,
+) -> Result<Option<ConnectReplyMessage>, ConnectError> {
+    if connect.protocol_version == 0 {
+        info!("legacy handshake, the phone app predates protocol versioning");
+        return Ok(None);
+    }
+
+    if connect.protocol_version != PROTOCOL_VERSION {
+        return Err(ConnectError::IncompatibleVersion {
+            phone: connect.protocol_version,
+            pc: PROTOCOL_VERSION,
+        });
+    }
+
+    info!(
+        "handshake with {} on {}, protocol version {}",
+        connect.client_name, connect.device_model, connect.protocol_version
+    );
+
+    let sample_rate = choose(
+        connect
+            .sample_rates
+            .iter()
+            .filter_map(|rate| SampleRate::from_number(*rate)),
+        output.sample_rate,
+        |rate| std::cmp::Reverse(rate.to_number().abs_diff(output.sample_rate.to_number())),
+    );
+
+    let audio_format = choose(
+        connect
+            .audio_formats
+            .iter()
+            .filter_map(|format| AudioFormat::from_android_format(*format)),
+        output.audio_format.clone(),
+        |format| format.sample_size(),
+    );
+
+    let channel_count = choose(
+        connect
+            .channel_counts
+            .iter()
+            .filter_map(|count| ChannelCount::from_number((*count).try_into().ok()?)),
+        output.channel_count.clone(),
+        |count| count.to_number(),
+    );
+
+    if !connect.codecs.is_empty() && !connect.codecs.iter().any(|codec| codec == CODEC_PCM) {
+        return Err(ConnectError::NoCommonFormat(format!(
+            "codec ({})",
+            connect.codecs.join(", ")
+        )));
+    }
+
+    let features = connect
+        .features
+        .iter()
+        .filter(|feature| SUPPORTED_FEATURES.contains(&feature.as_str()))
+        .cloned()
+        .collect();
+
+    Ok(Some(ConnectReplyMessage {
+        protocol_version: PROTOCOL_VERSION,
+        error: String::new(),
+        sample_rate: sample_rate.to_number(),
+        channel_count: channel_count.to_number().into(),
+        audio_format: audio_format.to_android_format(),
+        codec: CODEC_PCM.into(),
+        output_sample_rate: output.sample_rate.to_number(),
+        features,
+    }))
+}
+
+/// Pick the preferred value when the phone supports it, otherwise the best one by `key`.
+/// An empty list means that the phone didn't announce any usable value, and accepts the preferred one.
+fn choose<T: PartialEq, K: Ord>(
+    supported: impl Iterator<Item = T>,
+    preferred: T,
+    key: impl Fn(&T) -> K,
+) -> T {
+    let supported = supported.collect::<Vec<_>>();
+
+    if supported.contains(&preferred) {
+        return preferred;
+    }
+
+    supported.into_iter().max_by_key(key).unwrap_or(preferred)
+}
+
+pub fn has_feature(reply: &ConnectReplyMessage, feature: &str) -> bool {
+    reply.features.iter().any(|f| f == feature)
+}
+
+pub fn reply(reply: ConnectReplyMessage) -> MessageWrapper {
+    MessageWrapper {
+        payload: Some(Payload::ConnectReply(reply)),
+    }
+}
+
+/// Tell the phone why the connection is refused, before closing the session
+pub fn refusal(error: &ConnectError) -> MessageWrapper {
+    reply(ConnectReplyMessage {
+        protocol_version: PROTOCOL_VERSION,
+        error: error.to_string(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output() -> AudioPacketFormat {
+        AudioPacketFormat {
+            sample_rate: SampleRate::from_number(48000).unwrap(),
+            audio_format: AudioFormat::I16,
+            channel_count: ChannelCount::Mono,
+        }
+    }
+
+    #[test]
+    fn legacy_phone_gets_no_reply() {
+        let connect = ConnectMessage::default();
+
+        assert!(matches!(negotiate(&connect, &output()), Ok(None)));
+    }
+
+    #[test]
+    fn other_protocol_version_is_refused() {
+        let connect = ConnectMessage {
+            protocol_version: PROTOCOL_VERSION + 1,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            negotiate(&connect, &output()),
+            Err(ConnectError::IncompatibleVersion { phone, pc })
+                if phone == PROTOCOL_VERSION + 1 && pc == PROTOCOL_VERSION
+        ));
+    }
+
+    #[test]
+    fn phone_without_pcm_is_refused() {
+        let connect = ConnectMessage {
+            protocol_version: PROTOCOL_VERSION,
+            codecs: vec!["opus".into()],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            negotiate(&connect, &output()),
+            Err(ConnectError::NoCommonFormat(..))
+        ));
+    }
+
+    #[test]
+    fn closest_format_supported_by_the_phone_is_chosen() {
+        let connect = ConnectMessage {
+            protocol_version: PROTOCOL_VERSION,
+            sample_rates: vec![16000, 44100],
+            channel_counts: vec![1, 2],
+            audio_formats: vec![AudioFormat::U8.to_android_format()],
+            codecs: vec![CODEC_PCM.into()],
+            features: vec![FEATURE_CONTROL.into(), "unknown".into()],
+            ..Default::default()
+        };
+
+        let reply = negotiate(&connect, &output()).unwrap().unwrap();
+
+        assert_eq!(reply.sample_rate, 44100);
+        assert_eq!(reply.channel_count, 1);
+        assert_eq!(reply.audio_format, AudioFormat::U8.to_android_format());
+        assert_eq!(reply.output_sample_rate, 48000);
+        assert_eq!(reply.features, vec![FEATURE_CONTROL.to_string()]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use super::message::{MessageWrapper, PingMessage, PongMessage, message_wrapper::Payload};
+
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Without any frame from the peer for this long, it is considered dead
+pub const DEAD_PEER_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Track the liveness of the peer, and measure the round trip time with ping/pong messages.
+pub struct Heartbeat {
+    epoch: Instant,
+    last_received: Instant,
+    last_ping: Option<Instant>,
+    /// Only apps announcing the heartbeat feature answer to pings
+    ping_enabled: bool,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            epoch: now,
+            last_received: now,
+            last_ping: None,
+            ping_enabled: false,
+        }
+    }
+
+    pub fn enable_ping(&mut self) {
+        self.ping_enabled = true;
+    }
+
+    /// To be called for every frame received from the peer
+    pub fn received(&mut self) {
+        self.last_received = Instant::now();
+    }
+
+    pub fn silence(&self) -> Duration {
+        self.last_received.elapsed()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.silence() > DEAD_PEER_TIMEOUT
+    }
+
+    /// Return a ping message when it is time to send one
+    pub fn next_ping(&mut self) -> Option<MessageWrapper> {
+        if !self.ping_enabled
+            || self
+                .last_ping
+                .is_some_and(|last_ping| last_ping.elapsed() < PING_INTERVAL)
+        {
+            return None;
+        }
+
+        self.last_ping = Some(Instant::now());
+
+        Some(MessageWrapper {
+            payload: Some(Payload::Ping(PingMessage {
+                timestamp: self.epoch.elapsed().as_micros() as u64,
+            })),
+        })
+    }
+
+    /// Round trip time of the ping answered by this pong
+    pub fn rtt(&self, pong: &PongMessage) -> Option<Duration> {
+        let now = self.epoch.elapsed().as_micros() as u64;
+        now.checked_sub(pong.timestamp).map(Duration::from_micros)
+    }
+
+    /// Answer to a ping sent by the peer
+    pub fn pong(ping: &PingMessage) -> MessageWrapper {
+        MessageWrapper {
+            payload: Some(Payload::Pong(PongMessage {
+                timestamp: ping.timestamp,
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_is_dead_after_timeout() {
+        let mut heartbeat = Heartbeat::new();
+        assert!(!heartbeat.is_dead());
+
+        heartbeat.last_received = Instant::now() - DEAD_PEER_TIMEOUT - Duration::from_secs(1);
+        assert!(heartbeat.is_dead());
+
+        heartbeat.received();
+        assert!(!heartbeat.is_dead());
+    }
+
+    #[test]
+    fn pong_gives_round_trip_time() {
+        let mut heartbeat = Heartbeat::new();
+        assert!(heartbeat.next_ping().is_none());
+
+        heartbeat.enable_ping();
+        let Some(Payload::Ping(ping)) = heartbeat.next_ping().and_then(|m| m.payload) else {
+            panic!("expected a ping");
+        };
+        // not before the next interval
+        assert!(heartbeat.next_ping().is_none());
+
+        let Some(Payload::Pong(pong)) = Heartbeat::pong(&ping).payload else {
+            panic!("expected a pong");
+        };
+        assert_eq!(pong.timestamp, ping.timestamp);
+        assert!(heartbeat.rtt(&pong).is_some_and(|rtt| rtt < PING_INTERVAL));
+
+        // a pong from the future is ignored
+        let pong = PongMessage {
+            timestamp: u64::MAX,
+        };
+        assert!(heartbeat.rtt(&pong).is_none());
+    }
+}
@@ -17,11 +17,132 @@ pub struct AudioPacketMessageOrdered {
     #[prost(message, optional, tag = "2")]
     pub audio_packet: ::core::option::Option<AudioPacketMessage>,
 }
+/// First message sent by the phone. Apps predating the versioned handshake send it empty,
+/// and get the raw CHECK_2 string as answer instead of a ConnectReplyMessage.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ConnectMessage {
+    #[prost(uint32, tag = "1")]
+    pub protocol_version: u32,
+    #[prost(string, tag = "2")]
+    pub client_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub device_model: ::prost::alloc::string::String,
+    /// supported formats, with the same values as in AudioPacketMessage
+    #[prost(uint32, repeated, tag = "4")]
+    pub sample_rates: ::prost::alloc::vec::Vec<u32>,
+    #[prost(uint32, repeated, tag = "5")]
+    pub channel_counts: ::prost::alloc::vec::Vec<u32>,
+    #[prost(uint32, repeated, tag = "6")]
+    pub audio_formats: ::prost::alloc::vec::Vec<u32>,
+    #[prost(string, repeated, tag = "7")]
+    pub codecs: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "8")]
+    pub features: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Answer of the pc to a versioned ConnectMessage
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ConnectReplyMessage {
+    #[prost(uint32, tag = "1")]
+    pub protocol_version: u32,
+    /// set when the connection is refused, the other fields are then meaningless
+    #[prost(string, tag = "2")]
+    pub error: ::prost::alloc::string::String,
+    /// format the phone should stream with
+    #[prost(uint32, tag = "3")]
+    pub sample_rate: u32,
+    #[prost(uint32, tag = "4")]
+    pub channel_count: u32,
+    #[prost(uint32, tag = "5")]
+    pub audio_format: u32,
+    #[prost(string, tag = "6")]
+    pub codec: ::prost::alloc::string::String,
+    /// sample rate of the pc output device
+    #[prost(uint32, tag = "7")]
+    pub output_sample_rate: u32,
+    /// features supported by both sides
+    #[prost(string, repeated, tag = "8")]
+    pub features: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Keep-alive, the receiver must answer with a PongMessage holding the same timestamp
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct PingMessage {
+    /// sender clock, in microseconds
+    #[prost(uint64, tag = "1")]
+    pub timestamp: u64,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct PongMessage {
+    /// timestamp of the PingMessage being answered
+    #[prost(uint64, tag = "1")]
+    pub timestamp: u64,
+}
+/// Restart the stream with this format, same values as in AudioPacketMessage
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct FormatChangeRequest {
+    #[prost(uint32, tag = "1")]
+    pub sample_rate: u32,
+    #[prost(uint32, tag = "2")]
+    pub channel_count: u32,
+    #[prost(uint32, tag = "3")]
+    pub audio_format: u32,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct MuteRequest {
+    #[prost(bool, tag = "1")]
+    pub muted: bool,
+}
 #[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
-pub struct ConnectMessage {}
+pub struct MicSourceRequest {
+    /// android MediaRecorder.AudioSource value
+    #[prost(uint32, tag = "1")]
+    pub audio_source: u32,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct StopStreamRequest {}
+/// Command sent by the pc over the streaming connection, only to phones
+/// announcing the "control" feature during the handshake
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ControlMessage {
+    #[prost(oneof = "control_message::Command", tags = "1, 2, 3, 4")]
+    pub command: ::core::option::Option<control_message::Command>,
+}
+/// Nested message and enum types in `ControlMessage`.
+pub mod control_message {
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum Command {
+        #[prost(message, tag = "1")]
+        FormatChange(super::FormatChangeRequest),
+        #[prost(message, tag = "2")]
+        Mute(super::MuteRequest),
+        #[prost(message, tag = "3")]
+        MicSource(super::MicSourceRequest),
+        #[prost(message, tag = "4")]
+        Stop(super::StopStreamRequest),
+    }
+}
+/// Sent by the phone when its state changes, and periodically during a session
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TelemetryMessage {
+    #[prost(string, tag = "1")]
+    pub device_name: ::prost::alloc::string::String,
+    /// percent, negative when unknown
+    #[prost(int32, tag = "2")]
+    pub battery_level: i32,
+    #[prost(bool, tag = "3")]
+    pub charging: bool,
+    /// android MediaRecorder.AudioSource value of the microphone in use
+    #[prost(uint32, tag = "4")]
+    pub audio_source: u32,
+    #[prost(string, tag = "5")]
+    pub app_version: ::prost::alloc::string::String,
+    #[prost(bool, tag = "6")]
+    pub muted: bool,
+}
+/// Over tcp, audio packets are sent unwrapped. A wrapper never decodes as a valid
+/// AudioPacketMessage, which always has a sample rate, so both can be told apart.
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct MessageWrapper {
-    #[prost(oneof = "message_wrapper::Payload", tags = "1, 2")]
+    #[prost(oneof = "message_wrapper::Payload", tags = "1, 2, 3, 4, 5, 6, 7")]
     pub payload: ::core::option::Option<message_wrapper::Payload>,
 }
 /// Nested message and enum types in `MessageWrapper`.
@@ -32,5 +153,15 @@ pub mod message_wrapper {
         AudioPacket(super::AudioPacketMessageOrdered),
         #[prost(message, tag = "2")]
         Connect(super::ConnectMessage),
+        #[prost(message, tag = "3")]
+        Ping(super::PingMessage),
+        #[prost(message, tag = "4")]
+        Pong(super::PongMessage),
+        #[prost(message, tag = "5")]
+        ConnectReply(super::ConnectReplyMessage),
+        #[prost(message, tag = "6")]
+        Control(super::ControlMessage),
+        #[prost(message, tag = "7")]
+        Telemetry(super::TelemetryMessage),
     }
 }
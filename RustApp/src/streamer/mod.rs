@@ -1,20 +1,36 @@
 use anyhow::Result;
+use capture::Capture;
 use enum_dispatch::enum_dispatch;
+use file_streamer::FileStreamer;
 use prost::DecodeError;
+use replay_streamer::ReplayStreamer;
 use rtrb::{Producer, chunks::ChunkError};
-use std::{fmt::Debug, io};
+use std::{fmt::Debug, io, time::Duration};
 use tcp_streamer::TcpStreamer;
 use thiserror::Error;
 use udp_streamer::UdpStreamer;
 
+#[cfg(feature = "adb")]
+mod adb;
 #[cfg(feature = "adb")]
 mod adb_streamer;
 #[cfg(feature = "adb")]
 use adb_streamer::AdbStreamer;
 
-mod message;
+mod capture;
+mod control;
+mod file_streamer;
+mod frame;
+mod handshake;
+mod heartbeat;
+pub mod message;
+mod replay_streamer;
+mod socket;
 mod streamer_runner;
 mod tcp_streamer;
+mod telemetry;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 mod udp_streamer;
 
 #[cfg(feature = "usb")]
@@ -24,20 +40,38 @@ mod usb_streamer;
 #[cfg(feature = "usb")]
 use crate::streamer::usb_streamer::UsbStreamer;
 
+#[cfg(feature = "adb")]
+pub use adb::client::{AdbDevice, DeviceState as AdbDeviceState, MdnsService};
+#[cfg(feature = "adb")]
+pub use adb_streamer::{
+    discover_pairing_services, list_devices as list_adb_devices, pair_wireless, reconnect_wireless,
+};
+pub use capture::CAPTURE_EXTENSION;
+pub use control::PhoneControl;
+pub use handshake::PROTOCOL_VERSION;
 pub use message::AudioPacketMessage;
-pub use streamer_runner::{ConnectOption, StreamerCommand, StreamerMsg, sub};
+pub use streamer_runner::{ConnectOption, ReconnectPolicy, StreamerCommand, StreamerMsg, sub};
+pub use telemetry::PhoneTelemetry;
+#[cfg(feature = "usb")]
+pub use usb::device::{UsbDevice, UsbHotplug};
+#[cfg(feature = "usb")]
+pub use usb_streamer::{hotplug as usb_hotplug, list_devices as list_usb_devices};
 
 use crate::{audio::AudioProcessParams, config::AudioFormat};
 
 pub const DEFAULT_PC_PORT: u16 = 54345;
 
-const CHECK_1: &str = "AndroidMic1";
-const CHECK_2: &str = "AndroidMic2";
+/// Sent by the phone over tcp, before the audio packets
+pub const CHECK_1: &str = "AndroidMic1";
+/// Answer of the pc to `CHECK_1`
+pub const CHECK_2: &str = "AndroidMic2";
 
 pub struct AudioStream {
     pub buff: Producer<u8>,
     pub audio_params: AudioProcessParams,
     pub is_window_visible: bool,
+    /// Bytes written and dropped by the last packet that didn't fit in the buffer
+    pub overrun: Option<(usize, usize)>,
 }
 
 impl AudioStream {
@@ -50,6 +84,15 @@ impl AudioStream {
             buff,
             audio_params,
             is_window_visible,
+            overrun: None,
+        }
+    }
+
+    /// The output device doesn't read fast enough, samples were dropped
+    fn take_overrun(&mut self) -> Result<(), WriteError> {
+        match self.overrun.take() {
+            Some((moved, lost)) => Err(WriteError::BufferOverfilled(moved, lost)),
+            None => Ok(()),
         }
     }
 }
@@ -74,6 +117,16 @@ trait StreamerTrait {
 
     fn reconfigure_stream(&mut self, stream_config: AudioStream);
 
+    /// Record every frame received from the phone
+    fn capture(&mut self, capture: Capture);
+
+    /// Go back to a listening state after a disconnection or an error,
+    /// re-establishing what the transport needs (adb reverse proxy, aoa session, ...).
+    async fn reconnect(&mut self) -> Result<(), ConnectError>;
+
+    /// Send a command to the connected phone
+    async fn send_control(&mut self, control: &PhoneControl) -> Result<(), ConnectError>;
+
     fn status(&self) -> StreamerMsg;
 }
 #[allow(clippy::enum_variant_names)]
@@ -85,6 +138,8 @@ enum Streamer {
     UdpStreamer,
     #[cfg(feature = "usb")]
     UsbStreamer,
+    FileStreamer,
+    ReplayStreamer,
     DummyStreamer,
 }
 
@@ -103,6 +158,20 @@ enum ConnectError {
     NoUsbDevice(io::Error),
     #[error("no adb device found")]
     NoAdbDevice,
+    #[error("adb device {0} is not attached")]
+    AdbDeviceNotFound(String),
+    #[error("adb device {0} is unauthorized: unlock the phone and accept the usb debugging prompt")]
+    AdbUnauthorized(String),
+    #[error("wireless adb device {0} not found, enable wireless debugging on the phone")]
+    AdbWirelessNotFound(String),
+    #[error("adb device {serial} is {state}")]
+    AdbDeviceNotReady { serial: String, state: String },
+    #[cfg(feature = "usb")]
+    #[error("usb device {0} is not plugged")]
+    UsbDeviceNotFound(String),
+    #[cfg(feature = "usb")]
+    #[error("{0} Android devices are plugged, choose one")]
+    UsbDeviceNotChosen(usize),
     #[cfg(feature = "usb")]
     #[error("can't open usb handle: {0}")]
     CantOpenUsbHandle(io::Error),
@@ -117,16 +186,69 @@ enum ConnectError {
     CantSwitchUsbAOAMode(io::Error),
     #[error("device disconnected")]
     Disconnected,
+    #[error("no data received from the device for {0:?}")]
+    PeerTimeout(Duration),
     #[error(transparent)]
     CantJoin(#[from] tokio::task::JoinError),
-    #[error("command failed: {code:?}:{stderr}")]
-    AdbStatusCommand { code: Option<i32>, stderr: String },
-    #[error("command failed: {0} make sure adb is installed and in your PATH")]
-    CommandFailed(io::Error),
+    #[cfg(feature = "adb")]
+    #[error(transparent)]
+    Adb(#[from] adb::client::AdbError),
     #[error("Handshake failed: {0} {1}")]
     HandShakeFailed(&'static str, io::Error),
     #[error("Handshake failed: {0}")]
     HandShakeFailed2(String),
+    #[error(
+        "the phone app uses protocol version {phone}, but this app uses version {pc}: update both apps"
+    )]
+    IncompatibleVersion { phone: u32, pc: u32 },
+    #[error("no common {0} with the phone")]
+    NoCommonFormat(String),
+    #[error("can't read audio file {0}: {1}")]
+    CantReadFile(String, String),
+    #[error("finished replaying {0}")]
+    ReplayFinished(String),
+    #[error("no phone connected")]
+    NotConnected,
+    #[error("the phone app doesn't support remote control")]
+    ControlUnsupported,
+}
+
+impl ConnectError {
+    /// The peer went away or misbehaved: the streamer can listen again right away.
+    ///
+    /// A version or format mismatch is not one, the user must be told to update an app.
+    fn is_disconnection(&self) -> bool {
+        matches!(
+            self,
+            ConnectError::Disconnected
+                | ConnectError::PeerTimeout(..)
+                | ConnectError::HandShakeFailed(..)
+                | ConnectError::HandShakeFailed2(..)
+        )
+    }
+
+    /// Errors that can go away by themselves, worth retrying with a backoff
+    fn is_transient(&self) -> bool {
+        match self {
+            ConnectError::CantBindPort(..)
+            | ConnectError::CantAccept(..)
+            | ConnectError::NoAdbDevice
+            | ConnectError::AdbDeviceNotFound(..)
+            | ConnectError::AdbUnauthorized(..)
+            | ConnectError::AdbDeviceNotReady { .. }
+            | ConnectError::AdbWirelessNotFound(..) => true,
+            #[cfg(feature = "adb")]
+            ConnectError::Adb(e) => e.is_transient(),
+            #[cfg(feature = "usb")]
+            ConnectError::NoUsbDevice(..)
+            | ConnectError::UsbDeviceNotFound(..)
+            | ConnectError::CantOpenUsbHandle(..)
+            | ConnectError::CantClaimUsbInterface(..)
+            | ConnectError::CantSwitchUsbAOAMode(..) => true,
+            ConnectError::WriteError(WriteError::Io(..)) => true,
+            _ => self.is_disconnection(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -160,6 +282,16 @@ impl StreamerTrait for DummyStreamer {
 
     fn reconfigure_stream(&mut self, _config: AudioStream) {}
 
+    fn capture(&mut self, _capture: Capture) {}
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        Ok(())
+    }
+
+    async fn send_control(&mut self, _control: &PhoneControl) -> Result<(), ConnectError> {
+        Err(ConnectError::NotConnected)
+    }
+
     fn status(&self) -> StreamerMsg {
         unreachable!()
     }
@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+use tokio::time::Instant;
+
+use crate::{
+    audio::process::ProcessCache, config::ConnectionMode,
+    streamer::message::message_wrapper::Payload,
+};
+
+use super::{
+    AudioStream, ConnectError, PhoneControl, StreamerMsg, StreamerTrait,
+    capture::{Capture, CaptureReader, CapturedFrame},
+    frame::{self, Frame},
+};
+
+/// Feed a capture back with its original timing, gaps and ordering included
+pub struct ReplayStreamer {
+    path: PathBuf,
+    reader: CaptureReader,
+    /// Instant matching the start of the capture
+    start: Instant,
+    /// Frame read but not due yet, kept when the runner cancels `next` for a command
+    pending: Option<CapturedFrame>,
+    stream_config: AudioStream,
+    tracked_sequence: u32,
+    process_cache: ProcessCache,
+}
+
+async fn open(path: &Path) -> Result<CaptureReader, ConnectError> {
+    CaptureReader::open(path)
+        .await
+        .map_err(|e| ConnectError::CantReadFile(path.display().to_string(), e.to_string()))
+}
+
+pub async fn new(
+    path: PathBuf,
+    stream_config: AudioStream,
+) -> Result<ReplayStreamer, ConnectError> {
+    let reader = open(&path).await?;
+
+    info!("replaying capture {}", path.display());
+
+    let streamer = ReplayStreamer {
+        path,
+        reader,
+        start: Instant::now(),
+        pending: None,
+        stream_config,
+        tracked_sequence: 0,
+        process_cache: ProcessCache::new(),
+    };
+
+    Ok(streamer)
+}
+
+impl StreamerTrait for ReplayStreamer {
+    fn reconfigure_stream(&mut self, stream_config: AudioStream) {
+        self.stream_config = stream_config;
+        self.process_cache.clear();
+    }
+
+    fn capture(&mut self, _capture: Capture) {}
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        self.reader = open(&self.path).await?;
+        self.start = Instant::now();
+        self.pending = None;
+        self.tracked_sequence = 0;
+        self.process_cache.clear();
+        Ok(())
+    }
+
+    async fn send_control(&mut self, _control: &PhoneControl) -> Result<(), ConnectError> {
+        Err(ConnectError::NotConnected)
+    }
+
+    fn status(&self) -> StreamerMsg {
+        StreamerMsg::Connected {
+            ip: None,
+            port: None,
+            peer: None,
+            mode: ConnectionMode::File,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
+        let at = match &self.pending {
+            Some(captured) => captured.at,
+            None => {
+                let captured = self
+                    .reader
+                    .read_frame()
+                    .await
+                    .map_err(|e| {
+                        ConnectError::CantReadFile(self.path.display().to_string(), e.to_string())
+                    })?
+                    .ok_or_else(|| ConnectError::ReplayFinished(self.path.display().to_string()))?;
+                let at = captured.at;
+                self.pending = Some(captured);
+                at
+            }
+        };
+
+        tokio::time::sleep_until(self.start + at).await;
+        let captured = self.pending.take().expect("read before the sleep");
+
+        // the frames go through the same handling as on the transport they were captured on
+        match frame::decode_frame(&captured.frame, &mut self.tracked_sequence) {
+            Ok(Some(Frame::Audio(packet))) => {
+                frame::play_audio_packet(&mut self.stream_config, &mut self.process_cache, packet)
+            }
+            Ok(Some(Frame::Message(Payload::Telemetry(telemetry)))) => {
+                Ok(Some(StreamerMsg::Telemetry(telemetry.into())))
+            }
+            // there is no phone to answer to
+            Ok(_) => Ok(None),
+            Err(e) => {
+                warn!("skipping a frame that can't be decoded: {e}");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prost::Message;
+
+    use super::*;
+    use crate::streamer::{
+        message::{MessageWrapper, TelemetryMessage},
+        test_support::stream_config,
+    };
+
+    fn telemetry(device_name: &str) -> Vec<u8> {
+        MessageWrapper {
+            payload: Some(Payload::Telemetry(TelemetryMessage {
+                device_name: device_name.into(),
+                ..Default::default()
+            })),
+        }
+        .encode_to_vec()
+    }
+
+    fn device_name(msg: Result<Option<StreamerMsg>, ConnectError>) -> String {
+        match msg {
+            Ok(Some(StreamerMsg::Telemetry(telemetry))) => telemetry.device_name,
+            other => panic!("expected telemetry, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn no_frame_is_lost_when_next_is_cancelled() {
+        let path = std::env::temp_dir().join(format!(
+            "android-mic-replay-{}.{}",
+            std::process::id(),
+            crate::streamer::CAPTURE_EXTENSION
+        ));
+
+        let mut capture = Capture::create(&path).await.unwrap();
+        capture.record(&telemetry("first"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        capture.record(&telemetry("second"));
+        capture.close().await;
+
+        let mut streamer = new(path.clone(), stream_config()).await.unwrap();
+        assert_eq!(device_name(streamer.next().await), "first");
+
+        // the runner drops `next` when it receives a command
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), streamer.next())
+                .await
+                .is_err()
+        );
+
+        assert_eq!(device_name(streamer.next().await), "second");
+        assert!(matches!(
+            streamer.next().await,
+            Err(ConnectError::ReplayFinished(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, UdpSocket};
+
+use crate::config::Subnet;
+
+/// Create a socket bound to `ip`. The IPv6 unspecified address is bound in dual stack mode,
+/// falling back to IPv4 only when IPv6 is not available on this system.
+fn bind(ip: IpAddr, port: u16, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    let addr = SocketAddr::new(ip, port);
+
+    let socket = match Socket::new(Domain::for_address(addr), ty, Some(protocol)) {
+        Ok(socket) => socket,
+        Err(e) if ip == IpAddr::V6(Ipv6Addr::UNSPECIFIED) => {
+            warn!("IPv6 is not available ({e}), listening on IPv4 only");
+            return bind(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port, ty, protocol);
+        }
+        Err(e) => return Err(e),
+    };
+
+    if ip == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        // the default differs between systems, windows only accepts IPv6 otherwise
+        socket.set_only_v6(false)?;
+    }
+
+    // same as tokio, so the port can be reused right after a disconnection
+    #[cfg(unix)]
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+pub fn bind_tcp(ip: IpAddr, port: u16) -> io::Result<TcpListener> {
+    let socket = bind(ip, port, Type::STREAM, Protocol::TCP)?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+pub fn bind_udp(ip: IpAddr, port: u16) -> io::Result<UdpSocket> {
+    let socket = bind(ip, port, Type::DGRAM, Protocol::UDP)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Bind a listener on each address. They share the port of the first one,
+/// so a random port is the same on every adapter.
+pub fn bind_tcp_all(ips: &[IpAddr], mut port: u16) -> io::Result<Vec<TcpListener>> {
+    ips.iter()
+        .map(|ip| {
+            let listener = bind_tcp(*ip, port)?;
+            port = listener.local_addr()?.port();
+            Ok(listener)
+        })
+        .collect()
+}
+
+/// Bind a socket on each address, sharing the port like [`bind_tcp_all`]
+pub fn bind_udp_all(ips: &[IpAddr], mut port: u16) -> io::Result<Vec<UdpSocket>> {
+    ips.iter()
+        .map(|ip| {
+            let socket = bind_udp(*ip, port)?;
+            port = socket.local_addr()?.port();
+            Ok(socket)
+        })
+        .collect()
+}
+
+/// An empty allowlist allows every address
+pub fn is_allowed(allowed_subnets: &[Subnet], ip: IpAddr) -> bool {
+    allowed_subnets.is_empty() || allowed_subnets.iter().any(|subnet| subnet.contains(ip))
+}
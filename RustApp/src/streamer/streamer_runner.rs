@@ -8,37 +8,86 @@ use futures::{
 };
 use rtrb::Producer;
 use std::fmt::Debug;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::sync::mpsc::{self, Sender};
+use tokio::time::Instant;
 
 use crate::audio::AudioProcessParams;
-use crate::config::ConnectionMode;
+use crate::config::{ConnectionMode, Subnet};
 use crate::streamer::{StreamerTrait, WriteError};
 
-use super::{AudioStream, ConnectError, DummyStreamer, Streamer, tcp_streamer, udp_streamer};
+use super::{
+    AudioStream, ConnectError, DummyStreamer, PhoneControl, PhoneTelemetry, Streamer,
+    capture::Capture, file_streamer, replay_streamer, tcp_streamer, udp_streamer,
+};
 
 #[derive(Debug)]
 pub enum ConnectOption {
     Tcp {
-        ip: IpAddr,
+        ips: Vec<IpAddr>,
         port: u16,
+        allowed_subnets: Vec<Subnet>,
     },
     Udp {
-        ip: IpAddr,
+        ips: Vec<IpAddr>,
         port: u16,
+        allowed_subnets: Vec<Subnet>,
     },
     #[cfg(feature = "adb")]
     Adb {
         port: u16,
+        /// Serial of the device to use
+        serial: Option<String>,
+        /// Paired phone with this serial, found by name when its port changed
+        wireless: Option<crate::config::WirelessAdbDevice>,
     },
     #[cfg(feature = "usb")]
-    Usb,
+    Usb {
+        /// Serial of the device to use
+        serial: Option<String>,
+    },
+    /// Replay an audio file instead of a phone
+    File {
+        path: PathBuf,
+        /// Start again at the end of the file
+        looping: bool,
+    },
+    /// Replay the frames of a capture, with their original timing
+    Replay { path: PathBuf },
+}
+
+/// What to do when the connection is lost
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Go back to listening when the phone disconnects, instead of stopping
+    pub keep_listening: bool,
+    /// Number of retries for transient errors, 0 to stop on the first error
+    pub max_retries: u32,
+}
+
+impl ReconnectPolicy {
+    const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    fn backoff(attempt: u32) -> Duration {
+        Self::INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(Self::MAX_BACKOFF)
+    }
 }
 
+/// Overruns come with every packet while the output is too slow
+const BUFFER_OVERRUN_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// App -> Streamer
 pub enum StreamerCommand {
     Connect {
         connect_options: ConnectOption,
+        reconnect_policy: ReconnectPolicy,
+        /// Dump the received frames to this file
+        capture: Option<PathBuf>,
         buff: Producer<u8>,
         audio_params: AudioProcessParams,
         is_window_visible: bool,
@@ -48,6 +97,8 @@ pub enum StreamerCommand {
         audio_params: AudioProcessParams,
         is_window_visible: bool,
     },
+    /// Forward a command to the phone
+    Control(PhoneControl),
     Stop,
 }
 
@@ -56,12 +107,16 @@ impl Debug for StreamerCommand {
         match self {
             Self::Connect {
                 connect_options,
+                reconnect_policy,
+                capture,
                 buff: _,
                 audio_params,
                 is_window_visible,
             } => f
                 .debug_struct("Connect")
                 .field("connect_options", connect_options)
+                .field("reconnect_policy", reconnect_policy)
+                .field("capture", capture)
                 .field("audio_params", audio_params)
                 .field("is_window_visible", is_window_visible)
                 .finish(),
@@ -74,6 +129,7 @@ impl Debug for StreamerCommand {
                 .field("audio_params", audio_params)
                 .field("is_window_visible", is_window_visible)
                 .finish(),
+            Self::Control(control) => f.debug_tuple("Control").field(control).finish(),
             Self::Stop => write!(f, "Stop"),
         }
     }
@@ -93,17 +149,42 @@ pub enum StreamerMsg {
     Connected {
         ip: Option<IpAddr>,
         port: Option<u16>,
+        /// Address of the phone, when known
+        peer: Option<SocketAddr>,
         mode: ConnectionMode,
     },
+    /// The connection was lost, and will be retried after `delay`
+    Reconnecting {
+        error: String,
+        attempt: u32,
+        max_attempts: u32,
+        delay: Duration,
+    },
+    /// Datagrams from other senders than the phone were ignored, `count` since the session started
+    Rejected {
+        from: SocketAddr,
+        count: u64,
+    },
+    /// Features agreed on during the handshake with the phone
+    PeerFeatures {
+        /// The phone accepts control messages
+        control: bool,
+    },
+    /// Round trip time measured with the heartbeat
+    RoundTrip {
+        rtt: Duration,
+    },
+    /// Audio was dropped because the output device doesn't read fast enough,
+    /// `dropped_bytes` since the last report
+    BufferOverrun {
+        dropped_bytes: usize,
+    },
+    /// A command could not be sent to the phone
+    ControlFailed(String),
+    Telemetry(PhoneTelemetry),
     Ready(Sender<StreamerCommand>),
 }
 
-impl StreamerMsg {
-    fn is_error(&self) -> bool {
-        matches!(self, StreamerMsg::Error(..))
-    }
-}
-
 async fn send(sender: &mut futures::channel::mpsc::Sender<StreamerMsg>, msg: StreamerMsg) {
     sender.send(msg).await.unwrap();
 }
@@ -113,13 +194,31 @@ pub fn sub() -> impl Stream<Item = StreamerMsg> {
         let (command_sender, mut command_receiver) = mpsc::channel(100);
 
         let mut streamer: Streamer = DummyStreamer::new();
+        let mut reconnect_policy = ReconnectPolicy {
+            keep_listening: false,
+            max_retries: 0,
+        };
+        // when set, the streamer will try to reconnect at this instant
+        let mut retry_deadline: Option<Instant> = None;
+        let mut retry_attempt: u32 = 0;
+        let mut last_overrun_report: Option<Instant> = None;
+        let mut dropped_bytes: usize = 0;
 
         send(&mut sender, StreamerMsg::Ready(command_sender)).await;
 
         loop {
             let either = {
                 let recv_future = command_receiver.recv();
-                let process_future = streamer.next();
+                let process_future = async {
+                    match retry_deadline {
+                        Some(deadline) => {
+                            tokio::time::sleep_until(deadline).await;
+                            let res = streamer.reconnect().await.map(|_| Some(streamer.status()));
+                            (true, res)
+                        }
+                        None => (false, streamer.next().await),
+                    }
+                };
 
                 pin_mut!(recv_future);
                 pin_mut!(process_future);
@@ -138,40 +237,90 @@ pub fn sub() -> impl Stream<Item = StreamerMsg> {
                         match command {
                             StreamerCommand::Connect {
                                 connect_options,
+                                reconnect_policy: new_reconnect_policy,
+                                capture,
                                 buff,
                                 audio_params,
                                 is_window_visible,
                             } => {
+                                retry_deadline = None;
+                                retry_attempt = 0;
+                                reconnect_policy = new_reconnect_policy;
+
                                 let stream_config =
                                     AudioStream::new(buff, audio_params, is_window_visible);
                                 let new_streamer: Result<Streamer, ConnectError> =
                                     match connect_options {
-                                        ConnectOption::Tcp { ip, port } => {
-                                            tcp_streamer::new(ip, port, stream_config)
-                                                .await
-                                                .map(Streamer::from)
-                                        }
+                                        ConnectOption::Tcp {
+                                            ips,
+                                            port,
+                                            allowed_subnets,
+                                        } => tcp_streamer::new(
+                                            ips,
+                                            port,
+                                            allowed_subnets,
+                                            stream_config,
+                                        )
+                                        .await
+                                        .map(Streamer::from),
                                         #[cfg(feature = "adb")]
-                                        ConnectOption::Adb { port } => {
-                                            crate::streamer::adb_streamer::new(port, stream_config)
-                                                .await
-                                                .map(Streamer::from)
+                                        ConnectOption::Adb {
+                                            port,
+                                            serial,
+                                            wireless,
+                                        } => crate::streamer::adb_streamer::new(
+                                            port,
+                                            serial,
+                                            wireless,
+                                            stream_config,
+                                        )
+                                        .await
+                                        .map(Streamer::from),
+                                        ConnectOption::Udp {
+                                            ips,
+                                            port,
+                                            allowed_subnets,
+                                        } => udp_streamer::new(
+                                            ips,
+                                            port,
+                                            allowed_subnets,
+                                            stream_config,
+                                        )
+                                        .await
+                                        .map(Streamer::from),
+                                        #[cfg(feature = "usb")]
+                                        ConnectOption::Usb { serial } => {
+                                            crate::streamer::usb_streamer::new(
+                                                serial,
+                                                stream_config,
+                                            )
+                                            .await
+                                            .map(Streamer::from)
                                         }
-                                        ConnectOption::Udp { ip, port } => {
-                                            udp_streamer::new(ip, port, stream_config)
+                                        ConnectOption::File { path, looping } => {
+                                            file_streamer::new(path, looping, stream_config)
                                                 .await
                                                 .map(Streamer::from)
                                         }
-                                        #[cfg(feature = "usb")]
-                                        ConnectOption::Usb => {
-                                            crate::streamer::usb_streamer::new(stream_config)
+                                        ConnectOption::Replay { path } => {
+                                            replay_streamer::new(path, stream_config)
                                                 .await
                                                 .map(Streamer::from)
                                         }
                                     };
 
                                 match new_streamer {
-                                    Ok(new_streamer) => {
+                                    Ok(mut new_streamer) => {
+                                        if let Some(path) = capture {
+                                            match Capture::create(&path).await {
+                                                Ok(capture) => new_streamer.capture(capture),
+                                                Err(e) => error!(
+                                                    "can't capture to {}: {e}",
+                                                    path.display()
+                                                ),
+                                            }
+                                        }
+
                                         send(&mut sender, new_streamer.status()).await;
                                         drop(streamer);
                                         streamer = new_streamer;
@@ -192,26 +341,80 @@ pub fn sub() -> impl Stream<Item = StreamerMsg> {
 
                                 streamer.reconfigure_stream(stream_config);
                             }
+                            StreamerCommand::Control(control) => {
+                                if let Err(e) = streamer.send_control(&control).await {
+                                    warn!("can't send {control:?} to the phone: {e}");
+                                    send(&mut sender, StreamerMsg::ControlFailed(e.to_string()))
+                                        .await;
+                                }
+                            }
                             StreamerCommand::Stop => {
+                                retry_deadline = None;
+                                retry_attempt = 0;
                                 drop(streamer);
                                 streamer = DummyStreamer::new();
                             }
                         }
                     }
                 }
-                Either::Right(res) => match res {
+                Either::Right((is_retry, res)) => match res {
                     Ok(status) => {
+                        if is_retry {
+                            info!("streamer reconnected");
+                            retry_deadline = None;
+                        }
+
                         if let Some(status) = status {
+                            if let StreamerMsg::Connected { .. } = status {
+                                retry_attempt = 0;
+                            }
                             send(&mut sender, status).await;
                         }
                     }
                     Err(connect_error) => {
+                        if let ConnectError::WriteError(WriteError::BufferOverfilled(_, lost)) =
+                            connect_error
+                        {
+                            dropped_bytes += lost;
+                            if last_overrun_report
+                                .is_none_or(|last| last.elapsed() >= BUFFER_OVERRUN_REPORT_INTERVAL)
+                            {
+                                last_overrun_report = Some(Instant::now());
+                                send(&mut sender, StreamerMsg::BufferOverrun { dropped_bytes })
+                                    .await;
+                                dropped_bytes = 0;
+                            }
+                            continue;
+                        }
+
                         error!("{connect_error}");
 
-                        if !matches!(
-                            connect_error,
-                            ConnectError::WriteError(WriteError::BufferOverfilled(..))
-                        ) {
+                        if reconnect_policy.keep_listening
+                            && connect_error.is_disconnection()
+                            && !is_retry
+                        {
+                            // listen again right away
+                            retry_deadline = Some(Instant::now());
+                        } else if connect_error.is_transient()
+                            && retry_attempt < reconnect_policy.max_retries
+                        {
+                            retry_attempt += 1;
+                            let delay = ReconnectPolicy::backoff(retry_attempt);
+                            retry_deadline = Some(Instant::now() + delay);
+
+                            send(
+                                &mut sender,
+                                StreamerMsg::Reconnecting {
+                                    error: connect_error.to_string(),
+                                    attempt: retry_attempt,
+                                    max_attempts: reconnect_policy.max_retries,
+                                    delay,
+                                },
+                            )
+                            .await;
+                        } else {
+                            retry_deadline = None;
+                            retry_attempt = 0;
                             send(&mut sender, StreamerMsg::Error(connect_error.to_string())).await;
                             streamer = DummyStreamer::new();
                         }
@@ -1,6 +1,6 @@
 use std::{io, net::IpAddr, time::Duration};
 
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
 use prost::Message;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -10,52 +10,74 @@ use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 use crate::{
     audio::process::ProcessCache,
-    config::ConnectionMode,
-    streamer::{CHECK_1, CHECK_2, StreamerMsg, WriteError},
+    config::{ConnectionMode, Subnet},
+    streamer::{
+        CHECK_1, CHECK_2, PhoneControl, StreamerMsg, WriteError,
+        frame::{self, Frame},
+        handshake,
+        heartbeat::Heartbeat,
+        message::{MessageWrapper, message_wrapper::Payload},
+        socket,
+    },
 };
 
-use super::{AudioPacketMessage, AudioStream, ConnectError, StreamerTrait};
+use super::{AudioStream, ConnectError, StreamerTrait, capture::Capture};
 
 const MAX_WAIT_TIME: Duration = Duration::from_millis(1500);
 
 const DISCONNECT_LOOP_DETECTER_MAX: u32 = 1000;
 
 pub struct TcpStreamer {
+    /// Address of the main adapter
     ip: IpAddr,
     pub port: u16,
+    /// One listener per adapter, all on the same port
+    listeners: Vec<TcpListener>,
+    /// Connections from other addresses are refused
+    allowed_subnets: Vec<Subnet>,
     pub state: TcpStreamerState,
     stream_config: AudioStream,
     process_cache: ProcessCache,
+    capture: Option<Capture>,
 }
 
 #[allow(clippy::large_enum_variant)]
 pub enum TcpStreamerState {
-    Listening {
-        listener: TcpListener,
-    },
+    Listening,
     Streaming {
         framed: Framed<TcpStream, LengthDelimitedCodec>,
         disconnect_loop_detecter: u32,
+        heartbeat: Heartbeat,
+        /// The phone accepts control messages
+        control_enabled: bool,
+        tracked_sequence: u32,
     },
 }
 
 pub async fn new(
-    ip: IpAddr,
+    ips: Vec<IpAddr>,
     port: u16,
+    allowed_subnets: Vec<Subnet>,
     stream_config: AudioStream,
 ) -> Result<TcpStreamer, ConnectError> {
-    let listener = TcpListener::bind((ip, port))
-        .await
-        .map_err(|e| ConnectError::CantBindPort(port, e))?;
+    let listeners =
+        socket::bind_tcp_all(&ips, port).map_err(|e| ConnectError::CantBindPort(port, e))?;
 
-    let addr = TcpListener::local_addr(&listener).map_err(ConnectError::NoLocalAddress)?;
+    let addr = listeners
+        .first()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))
+        .and_then(TcpListener::local_addr)
+        .map_err(ConnectError::NoLocalAddress)?;
 
     let streamer = TcpStreamer {
-        ip,
+        ip: addr.ip(),
         port: addr.port(),
+        allowed_subnets,
         stream_config,
-        state: TcpStreamerState::Listening { listener },
+        listeners,
+        state: TcpStreamerState::Listening,
         process_cache: ProcessCache::new(),
+        capture: None,
     };
 
     Ok(streamer)
@@ -67,15 +89,40 @@ impl StreamerTrait for TcpStreamer {
         self.process_cache.clear();
     }
 
+    fn capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        // the listeners are kept, so the phone can connect again on the same port
+        self.state = TcpStreamerState::Listening;
+        self.process_cache.clear();
+        Ok(())
+    }
+
+    async fn send_control(&mut self, control: &PhoneControl) -> Result<(), ConnectError> {
+        match &mut self.state {
+            TcpStreamerState::Listening => Err(ConnectError::NotConnected),
+            TcpStreamerState::Streaming {
+                control_enabled: false,
+                ..
+            } => Err(ConnectError::ControlUnsupported),
+            TcpStreamerState::Streaming { framed, .. } => {
+                send_message(framed, control.to_message()).await
+            }
+        }
+    }
+
     fn status(&self) -> StreamerMsg {
         match &self.state {
-            TcpStreamerState::Listening { .. } => StreamerMsg::Listening {
+            TcpStreamerState::Listening => StreamerMsg::Listening {
                 ip: Some(self.ip),
                 port: Some(self.port),
             },
-            TcpStreamerState::Streaming { .. } => StreamerMsg::Connected {
+            TcpStreamerState::Streaming { framed, .. } => StreamerMsg::Connected {
                 ip: Some(self.ip),
                 port: Some(self.port),
+                peer: framed.get_ref().peer_addr().ok(),
                 mode: ConnectionMode::Tcp,
             },
         }
@@ -83,20 +130,35 @@ impl StreamerTrait for TcpStreamer {
 
     async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
         match &mut self.state {
-            TcpStreamerState::Listening { listener } => {
-                let addr =
-                    TcpListener::local_addr(listener).map_err(ConnectError::NoLocalAddress)?;
+            TcpStreamerState::Listening => {
+                for listener in &self.listeners {
+                    let addr = listener
+                        .local_addr()
+                        .map_err(ConnectError::NoLocalAddress)?;
+
+                    info!("TCP server listening on {}", addr);
+                }
 
-                info!("TCP server listening on {}", addr);
+                let (accepted, _, _) = futures::future::select_all(
+                    self.listeners
+                        .iter()
+                        .map(|listener| Box::pin(listener.accept())),
+                )
+                .await;
+                let (mut stream, addr) = accepted.map_err(ConnectError::CantAccept)?;
 
-                let (mut stream, addr) =
-                    listener.accept().await.map_err(ConnectError::CantAccept)?;
+                if !socket::is_allowed(&self.allowed_subnets, addr.ip()) {
+                    warn!("connection refused from {addr}: not in the allowed subnets");
+                    return Ok(None);
+                }
 
                 let mut buf1 = [0u8; CHECK_1.len()];
 
-                stream
-                    .read_exact(&mut buf1)
+                tokio::time::timeout(MAX_WAIT_TIME, stream.read_exact(&mut buf1))
                     .await
+                    .map_err(|_| {
+                        ConnectError::HandShakeFailed("reading", io::ErrorKind::TimedOut.into())
+                    })?
                     .map_err(|e| ConnectError::HandShakeFailed("reading", e))?;
 
                 if buf1 != CHECK_1.as_bytes() {
@@ -118,53 +180,130 @@ impl StreamerTrait for TcpStreamer {
                 self.state = TcpStreamerState::Streaming {
                     framed: Framed::new(stream, LengthDelimitedCodec::new()),
                     disconnect_loop_detecter: 0,
+                    heartbeat: Heartbeat::new(),
+                    control_enabled: false,
+                    tracked_sequence: 0,
                 };
 
                 Ok(Some(StreamerMsg::Connected {
                     ip: Some(self.ip),
                     port: Some(self.port),
+                    peer: Some(addr),
                     mode: ConnectionMode::Tcp,
                 }))
             }
             TcpStreamerState::Streaming {
                 framed,
-                disconnect_loop_detecter: _,
+                disconnect_loop_detecter,
+                heartbeat,
+                control_enabled,
+                tracked_sequence,
             } => {
-                match framed.next().await {
-                    Some(Ok(frame)) => match AudioPacketMessage::decode(frame) {
-                        Ok(packet) => {
-                            let buffer_size = packet.buffer.len();
-                            let sample_rate = packet.sample_rate;
-
-                            match self
-                                .stream_config
-                                .process_audio_packet(packet, &mut self.process_cache)
-                            {
-                                Ok(Some(buffer)) => {
-                                    debug!("received {} bytes", buffer_size);
-                                    Ok(Some(StreamerMsg::UpdateAudioWave {
-                                        data: AudioPacketMessage::to_wave_data(
-                                            &buffer,
-                                            sample_rate,
-                                        ),
-                                    }))
+                if let Some(ping) = heartbeat.next_ping() {
+                    send_message(framed, ping).await?;
+                }
+
+                let frame = match tokio::time::timeout(MAX_WAIT_TIME, framed.next()).await {
+                    Ok(Some(Ok(frame))) => frame.freeze(),
+                    Ok(Some(Err(e))) => {
+                        return match e.kind() {
+                            // timeout use to check for input on stdin
+                            // WouldBlock trigger on Linux when there is no stream input
+                            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                                *disconnect_loop_detecter += 1;
+                                if *disconnect_loop_detecter > DISCONNECT_LOOP_DETECTER_MAX {
+                                    Err(ConnectError::Disconnected)
+                                } else {
+                                    Ok(None)
                                 }
-                                _ => Ok(None),
                             }
-                        }
-                        Err(e) => Err(ConnectError::WriteError(WriteError::Deserializer(e))),
-                    },
-
-                    Some(Err(e)) => {
-                        match e.kind() {
-                            io::ErrorKind::TimedOut => Ok(None), // timeout use to check for input on stdin
-                            io::ErrorKind::WouldBlock => Ok(None), // trigger on Linux when there is no stream input
                             _ => Err(WriteError::Io(e))?,
+                        };
+                    }
+                    Ok(None) => return Err(ConnectError::Disconnected),
+                    Err(_) => {
+                        return if heartbeat.is_dead() {
+                            Err(ConnectError::PeerTimeout(heartbeat.silence()))
+                        } else {
+                            Ok(None)
+                        };
+                    }
+                };
+
+                heartbeat.received();
+                *disconnect_loop_detecter = 0;
+
+                if let Some(capture) = &mut self.capture {
+                    capture.record(&frame);
+                }
+
+                let frame = match frame::decode_frame(&frame, tracked_sequence) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => return Ok(None),
+                    Err(e) => return Err(ConnectError::WriteError(WriteError::Deserializer(e))),
+                };
+
+                match frame {
+                    Frame::Audio(packet) => frame::play_audio_packet(
+                        &mut self.stream_config,
+                        &mut self.process_cache,
+                        packet,
+                    ),
+                    Frame::Message(Payload::Connect(connect)) => {
+                        match handshake::negotiate(
+                            &connect,
+                            &self.stream_config.audio_params.target_format,
+                        ) {
+                            // the legacy handshake is done with CHECK_1 and CHECK_2
+                            Ok(None) => Ok(None),
+                            Ok(Some(reply)) => {
+                                if handshake::has_feature(&reply, handshake::FEATURE_HEARTBEAT) {
+                                    heartbeat.enable_ping();
+                                }
+                                *control_enabled =
+                                    handshake::has_feature(&reply, handshake::FEATURE_CONTROL);
+                                send_message(framed, handshake::reply(reply)).await?;
+                                Ok(Some(StreamerMsg::PeerFeatures {
+                                    control: *control_enabled,
+                                }))
+                            }
+                            Err(e) => {
+                                // best effort, the session is refused anyway
+                                let _ = send_message(framed, handshake::refusal(&e)).await;
+                                Err(e)
+                            }
                         }
                     }
-                    None => Err(ConnectError::Disconnected),
+                    Frame::Message(Payload::Ping(ping)) => {
+                        send_message(framed, Heartbeat::pong(&ping)).await?;
+                        Ok(None)
+                    }
+                    Frame::Message(Payload::Pong(pong)) => Ok(heartbeat
+                        .rtt(&pong)
+                        .map(|rtt| StreamerMsg::RoundTrip { rtt })),
+                    Frame::Message(Payload::Telemetry(telemetry)) => {
+                        Ok(Some(StreamerMsg::Telemetry(telemetry.into())))
+                    }
+                    Frame::Message(_) => {
+                        debug!("ignoring unexpected message");
+                        Ok(None)
+                    }
                 }
             }
         }
     }
 }
+
+async fn send_message(
+    framed: &mut Framed<TcpStream, LengthDelimitedCodec>,
+    message: MessageWrapper,
+) -> Result<(), ConnectError> {
+    // don't wait forever on a peer that doesn't read
+    match tokio::time::timeout(MAX_WAIT_TIME, framed.send(message.encode_to_vec().into())).await {
+        Ok(res) => res.map_err(|e| ConnectError::WriteError(WriteError::Io(e))),
+        Err(_) => {
+            warn!("timeout while sending message to the peer");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use crate::config::MicSource;
+
+use super::message::TelemetryMessage;
+
+/// State of the phone, as reported by the app
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhoneTelemetry {
+    pub device_name: String,
+    /// Battery level in percent
+    pub battery_level: Option<u8>,
+    pub charging: bool,
+    pub mic_source: Option<MicSource>,
+    pub app_version: String,
+    pub muted: bool,
+}
+
+impl PhoneTelemetry {
+    /// Below this level, the user is warned that the phone may shut down
+    pub const LOW_BATTERY_LEVEL: u8 = 15;
+
+    /// The warning is only useful when the phone is used as a mic for a while,
+    /// a short session is likely over before the phone shuts down
+    pub const LOW_BATTERY_MIN_SESSION: Duration = Duration::from_secs(10 * 60);
+
+    pub fn is_battery_low(&self) -> bool {
+        !self.charging
+            && self
+                .battery_level
+                .is_some_and(|level| level <= Self::LOW_BATTERY_LEVEL)
+    }
+}
+
+impl From<TelemetryMessage> for PhoneTelemetry {
+    fn from(message: TelemetryMessage) -> Self {
+        Self {
+            device_name: message.device_name,
+            battery_level: u8::try_from(message.battery_level)
+                .ok()
+                .filter(|level| *level <= 100),
+            charging: message.charging,
+            mic_source: MicSource::from_android_source(message.audio_source),
+            app_version: message.app_version,
+            muted: message.muted,
+        }
+    }
+}
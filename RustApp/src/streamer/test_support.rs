@@ -0,0 +1,57 @@
+//! Streamers driven without a phone or hardware, for the unit and integration tests.
+
+use rtrb::RingBuffer;
+
+use crate::{
+    audio::{AudioPacketFormat, AudioProcessParams},
+    config::Config,
+};
+
+use super::AudioStream;
+#[cfg(feature = "usb")]
+pub use super::usb::fake::FakeBackend;
+#[cfg(feature = "usb")]
+use super::{StreamerMsg, StreamerTrait, usb_streamer::UsbStreamer};
+
+/// Stream to the default output format, nobody reads the audio
+pub fn stream_config() -> AudioStream {
+    let config = Config::default();
+    let format = AudioPacketFormat {
+        sample_rate: config.sample_rate,
+        audio_format: config.audio_format.clone(),
+        channel_count: config.channel_count.clone(),
+    };
+    let (producer, _consumer) = RingBuffer::new(1 << 16);
+    AudioStream::new(producer, AudioProcessParams::new(format, config), false)
+}
+
+/// Usb streamer on the in-memory bus of a [`FakeBackend`]
+#[cfg(feature = "usb")]
+pub struct FakeUsbStreamer(UsbStreamer<FakeBackend>);
+
+#[cfg(feature = "usb")]
+impl FakeUsbStreamer {
+    /// Switch the phone plugged in `backend` to accessory mode and open it
+    pub async fn new(
+        backend: FakeBackend,
+        serial: Option<String>,
+        stream_config: AudioStream,
+    ) -> Result<Self, String> {
+        super::usb_streamer::new_with(backend, serial, stream_config)
+            .await
+            .map(Self)
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn next(&mut self) -> Result<Option<StreamerMsg>, String> {
+        self.0.next().await.map_err(|e| e.to_string())
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), String> {
+        self.0.reconnect().await.map_err(|e| e.to_string())
+    }
+
+    pub fn status(&self) -> StreamerMsg {
+        self.0.status()
+    }
+}
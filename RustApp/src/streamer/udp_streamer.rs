@@ -1,54 +1,93 @@
-use std::{io, net::IpAddr, time::Duration};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
 
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
 use prost::Message;
-use tokio::net::UdpSocket;
 use tokio_util::{codec::LengthDelimitedCodec, udp::UdpFramed};
 
 use crate::{
     audio::process::ProcessCache,
-    config::ConnectionMode,
+    config::{ConnectionMode, Subnet},
     streamer::{
-        AudioPacketMessage, CHECK_2, WriteError,
-        message::{MessageWrapper, message_wrapper::Payload},
+        CHECK_2, PhoneControl, WriteError,
+        frame::{self, Frame},
+        handshake,
+        heartbeat::Heartbeat,
+        message::{ConnectMessage, MessageWrapper, message_wrapper::Payload},
+        socket,
     },
 };
 
-use super::{AudioStream, ConnectError, StreamerMsg, StreamerTrait};
+use super::{AudioStream, ConnectError, StreamerMsg, StreamerTrait, capture::Capture};
 
 const MAX_WAIT_TIME: Duration = Duration::from_millis(1500);
 
 const DISCONNECT_LOOP_DETECTER_MAX: u32 = 1000;
 
 pub struct UdpStreamer {
+    /// Address of the main adapter
     ip: IpAddr,
     pub port: u16,
     stream_config: AudioStream,
-    framed: UdpFramed<LengthDelimitedCodec>,
-    is_listening: bool,
+    /// One socket per adapter, all on the same port
+    framed: Vec<UdpFramed<LengthDelimitedCodec>>,
+    /// Index of the socket that received the last accepted datagram, replies are sent through it
+    socket: usize,
+    /// Datagrams from other addresses are ignored
+    allowed_subnets: Vec<Subnet>,
+    /// The session is locked to this peer, datagrams from other addresses are ignored
+    peer: Option<SocketAddr>,
+    /// Last locked peer, allowed to resume its session without a new handshake
+    last_peer: Option<SocketAddr>,
+    /// Number of datagrams ignored during the current session
+    rejected_packets: u64,
+    last_rejected_report: Option<Instant>,
+    /// The peer accepts control messages, sent back to its address
+    control_enabled: bool,
+    /// Returned by the next call, when a session starts with a message to report
+    pending_message: Option<StreamerMsg>,
     tracked_sequence: u32,
     process_cache: ProcessCache,
+    capture: Option<Capture>,
 }
 
 pub async fn new(
-    ip: IpAddr,
+    ips: Vec<IpAddr>,
     port: u16,
+    allowed_subnets: Vec<Subnet>,
     stream_config: AudioStream,
 ) -> Result<UdpStreamer, ConnectError> {
-    let socket = UdpSocket::bind((ip, port))
-        .await
-        .map_err(|e| ConnectError::CantBindPort(port, e))?;
+    let sockets =
+        socket::bind_udp_all(&ips, port).map_err(|e| ConnectError::CantBindPort(port, e))?;
 
-    let addr = socket.local_addr().map_err(ConnectError::NoLocalAddress)?;
+    let addr = sockets
+        .first()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))
+        .and_then(|socket| socket.local_addr())
+        .map_err(ConnectError::NoLocalAddress)?;
 
     let streamer = UdpStreamer {
-        ip,
+        ip: addr.ip(),
         port: addr.port(),
+        allowed_subnets,
         stream_config,
         tracked_sequence: 0,
-        is_listening: true,
-        framed: UdpFramed::new(socket, LengthDelimitedCodec::new()),
+        peer: None,
+        last_peer: None,
+        rejected_packets: 0,
+        last_rejected_report: None,
+        control_enabled: false,
+        pending_message: None,
+        framed: sockets
+            .into_iter()
+            .map(|socket| UdpFramed::new(socket, LengthDelimitedCodec::new()))
+            .collect(),
+        socket: 0,
         process_cache: ProcessCache::new(),
+        capture: None,
     };
 
     Ok(streamer)
@@ -60,100 +99,126 @@ impl StreamerTrait for UdpStreamer {
         self.process_cache.clear();
     }
 
+    fn capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        // the sockets are kept, the next handshake will start a new session
+        self.release_peer();
+        self.last_peer = None;
+        self.control_enabled = false;
+        self.pending_message = None;
+        self.process_cache.clear();
+        Ok(())
+    }
+
+    async fn send_control(&mut self, control: &PhoneControl) -> Result<(), ConnectError> {
+        match self.peer {
+            None => Err(ConnectError::NotConnected),
+            Some(_) if !self.control_enabled => Err(ConnectError::ControlUnsupported),
+            Some(peer) => self
+                .send_message(control.to_message(), peer)
+                .await
+                .map_err(|e| ConnectError::WriteError(WriteError::Io(e))),
+        }
+    }
+
     fn status(&self) -> StreamerMsg {
-        if self.is_listening {
-            StreamerMsg::Listening {
+        match self.peer {
+            None => StreamerMsg::Listening {
                 ip: Some(self.ip),
                 port: Some(self.port),
-            }
-        } else {
-            StreamerMsg::Connected {
+            },
+            Some(peer) => StreamerMsg::Connected {
                 ip: Some(self.ip),
                 port: Some(self.port),
+                peer: Some(peer),
                 mode: ConnectionMode::Udp,
-            }
+            },
         }
     }
 
     async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
-        match tokio::time::timeout(
-            Duration::from_secs(if self.is_listening {
+        if let Some(message) = self.pending_message.take() {
+            return Ok(Some(message));
+        }
+
+        let received = tokio::time::timeout(
+            Duration::from_secs(if self.peer.is_none() {
                 Duration::MAX.as_secs()
             } else {
                 1
             }),
-            self.framed.next(),
+            futures::future::select_all(self.framed.iter_mut().map(|framed| framed.next())),
         )
         .await
-        {
-            Ok(res) => match res {
+        .map(|(res, index, _)| (res, index));
+
+        match received {
+            Ok((res, index)) => match res {
                 Some(Ok((frame, addr))) => {
-                    match MessageWrapper::decode(frame) {
-                        Ok(packet) => {
-                            match packet.payload {
-                                Some(payload) => {
-                                    let message = match payload {
-                                        Payload::AudioPacket(packet) => {
-                                            if packet.sequence_number < self.tracked_sequence {
-                                                // drop packet
-                                                info!(
-                                                    "dropped packet: old sequence number {} < {}",
-                                                    packet.sequence_number, self.tracked_sequence
-                                                );
-                                            }
-                                            self.tracked_sequence = packet.sequence_number;
-
-                                            let packet = packet.audio_packet.unwrap();
-                                            let buffer_size = packet.buffer.len();
-                                            let sample_rate = packet.sample_rate;
-
-                                            match self.stream_config.process_audio_packet(
-                                                packet,
-                                                &mut self.process_cache,
-                                            ) {
-                                                Ok(Some(buffer)) => {
-                                                    debug!(
-                                                        "From {:?}, received {} bytes",
-                                                        addr, buffer_size
-                                                    );
-                                                    Some(StreamerMsg::UpdateAudioWave {
-                                                        data: AudioPacketMessage::to_wave_data(
-                                                            &buffer,
-                                                            sample_rate,
-                                                        ),
-                                                    })
-                                                }
-                                                _ => None,
-                                            }
-                                        }
-                                        Payload::Connect(_) => {
-                                            self.framed
-                                                .get_ref()
-                                                .send_to(CHECK_2.as_bytes(), &addr)
-                                                .await
-                                                .map_err(|e| {
-                                                    ConnectError::HandShakeFailed("writing", e)
-                                                })?;
-
-                                            None
-                                        }
-                                    };
-
-                                    if self.is_listening {
-                                        self.is_listening = false;
-                                        Ok(Some(StreamerMsg::Connected {
-                                            ip: Some(self.ip),
-                                            port: Some(self.port),
-                                            mode: ConnectionMode::Udp,
-                                        }))
-                                    } else {
-                                        Ok(message)
-                                    }
-                                }
-                                None => todo!(),
-                            }
+                    if self.peer.is_some_and(|peer| peer != addr)
+                        || !socket::is_allowed(&self.allowed_subnets, addr.ip())
+                    {
+                        return Ok(self.reject(addr));
+                    }
+                    self.socket = index;
+
+                    if let Some(capture) = &mut self.capture {
+                        capture.record(&frame);
+                    }
+
+                    let frame = match frame::decode_frame(&frame, &mut self.tracked_sequence) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => return Ok(None),
+                        Err(e) => {
+                            return Err(ConnectError::WriteError(WriteError::Deserializer(e)));
+                        }
+                    };
+
+                    // a new session starts with a handshake, except for the
+                    // previous peer resuming after a timeout
+                    if self.peer.is_none()
+                        && !matches!(frame, Frame::Message(Payload::Connect(_)))
+                        && self.last_peer != Some(addr)
+                    {
+                        return Ok(self.reject(addr));
+                    }
+
+                    let message = match frame {
+                        Frame::Audio(packet) => frame::play_audio_packet(
+                            &mut self.stream_config,
+                            &mut self.process_cache,
+                            packet,
+                        )?,
+                        Frame::Message(Payload::Connect(connect)) => {
+                            Some(self.handshake(connect, addr).await?)
                         }
-                        Err(e) => Err(ConnectError::WriteError(WriteError::Deserializer(e))),
+                        Frame::Message(Payload::Ping(ping)) => {
+                            self.send_message(Heartbeat::pong(&ping), addr)
+                                .await
+                                .map_err(WriteError::Io)?;
+
+                            None
+                        }
+                        // no ping are sent over udp
+                        Frame::Message(Payload::Pong(_)) => None,
+                        Frame::Message(Payload::Telemetry(telemetry)) => {
+                            Some(StreamerMsg::Telemetry(telemetry.into()))
+                        }
+                        // only sent by the pc
+                        Frame::Message(_) => None,
+                    };
+
+                    if self.peer.is_none() {
+                        info!("udp session locked to {addr}");
+                        self.peer = Some(addr);
+                        self.last_peer = Some(addr);
+                        self.pending_message = message;
+                        Ok(Some(self.status()))
+                    } else {
+                        Ok(message)
                     }
                 }
 
@@ -164,13 +229,10 @@ impl StreamerTrait for UdpStreamer {
                         _ => Err(WriteError::Io(e))?,
                     }
                 }
-                None => {
-                    todo!()
-                }
+                None => Err(ConnectError::Disconnected),
             },
             Err(_) => {
-                self.is_listening = true;
-                self.tracked_sequence = 0;
+                self.release_peer();
                 Ok(Some(StreamerMsg::Listening {
                     ip: Some(self.ip),
                     port: Some(self.port),
@@ -179,3 +241,81 @@ impl StreamerTrait for UdpStreamer {
         }
     }
 }
+
+impl UdpStreamer {
+    async fn handshake(
+        &mut self,
+        connect: ConnectMessage,
+        addr: SocketAddr,
+    ) -> Result<StreamerMsg, ConnectError> {
+        let target_format = &self.stream_config.audio_params.target_format;
+
+        let res = match handshake::negotiate(&connect, target_format) {
+            Ok(None) => {
+                self.control_enabled = false;
+                self.framed[self.socket]
+                    .get_ref()
+                    .send_to(CHECK_2.as_bytes(), &addr)
+                    .await
+                    .map(|_| ())
+            }
+            Ok(Some(reply)) => {
+                self.control_enabled = handshake::has_feature(&reply, handshake::FEATURE_CONTROL);
+                self.send_message(handshake::reply(reply), addr).await
+            }
+            Err(e) => {
+                // best effort, the session is refused anyway
+                let _ = self.send_message(handshake::refusal(&e), addr).await;
+                return Err(e);
+            }
+        };
+
+        res.map_err(|e| ConnectError::HandShakeFailed("writing", e))?;
+
+        Ok(StreamerMsg::PeerFeatures {
+            control: self.control_enabled,
+        })
+    }
+
+    async fn send_message(&mut self, message: MessageWrapper, addr: SocketAddr) -> io::Result<()> {
+        self.framed[self.socket]
+            .send((message.encode_to_vec().into(), addr))
+            .await
+    }
+
+    /// Count the ignored datagram, and report the count to the user at most once per second
+    fn reject(&mut self, addr: SocketAddr) -> Option<StreamerMsg> {
+        if self.rejected_packets == 0 {
+            warn!("ignoring datagrams from {addr}: not the session peer, or not allowed");
+        }
+        self.rejected_packets += 1;
+
+        if self
+            .last_rejected_report
+            .is_some_and(|last| last.elapsed() < Duration::from_secs(1))
+        {
+            return None;
+        }
+        self.last_rejected_report = Some(Instant::now());
+
+        Some(StreamerMsg::Rejected {
+            from: addr,
+            count: self.rejected_packets,
+        })
+    }
+
+    fn release_peer(&mut self) {
+        if let Some(peer) = self.peer.take() {
+            info!("udp session released from {peer}");
+        }
+        if self.rejected_packets > 0 {
+            info!(
+                "{} datagrams from other senders were ignored",
+                self.rejected_packets
+            );
+        }
+        self.rejected_packets = 0;
+        self.last_rejected_report = None;
+        self.tracked_sequence = 0;
+    }
+}
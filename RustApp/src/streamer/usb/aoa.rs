@@ -4,26 +4,24 @@ use std::{
     time::Duration,
 };
 
-use nusb::{
-    Device, DeviceInfo, Interface,
-    descriptors::TransferType,
-    transfer::{ControlIn, ControlOut, ControlType, Direction, Recipient},
-};
+use nusb::{Device, DeviceInfo, descriptors::TransferType, transfer::Direction};
+
+use super::backend::UsbInterface;
 
-const USB_AOA_VID: u16 = 0x18D1;
-const USB_AOA_PID_MIN: u16 = 0x2D00;
-const USB_AOA_PID_MAX: u16 = 0x2D05;
+pub const USB_AOA_VID: u16 = 0x18D1;
+pub const USB_AOA_PID_MIN: u16 = 0x2D00;
+pub const USB_AOA_PID_MAX: u16 = 0x2D05;
 
-const ACCESSORY_STRING_MANUFACTURER: u16 = 0x00;
-const ACCESSORY_STRING_MODEL: u16 = 0x01;
-const ACCESSORY_STRING_DESCRIPTION: u16 = 0x02;
-const ACCESSORY_STRING_VERSION: u16 = 0x03;
-const ACCESSORY_STRING_URI: u16 = 0x04;
-const ACCESSORY_STRING_SERIAL: u16 = 0x05;
+pub const ACCESSORY_STRING_MANUFACTURER: u16 = 0x00;
+pub const ACCESSORY_STRING_MODEL: u16 = 0x01;
+pub const ACCESSORY_STRING_DESCRIPTION: u16 = 0x02;
+pub const ACCESSORY_STRING_VERSION: u16 = 0x03;
+pub const ACCESSORY_STRING_URI: u16 = 0x04;
+pub const ACCESSORY_STRING_SERIAL: u16 = 0x05;
 
-const REQUEST_GET_PROTOCOL: u8 = 0x33;
-const REQUEST_SEND_STRING: u8 = 0x34;
-const REQUEST_START: u8 = 0x35;
+pub const REQUEST_GET_PROTOCOL: u8 = 0x33;
+pub const REQUEST_SEND_STRING: u8 = 0x34;
+pub const REQUEST_START: u8 = 0x35;
 
 #[derive(Clone, Debug, Default)]
 pub struct AccessoryStrings {
@@ -42,11 +40,14 @@ pub trait AccessoryDeviceInfoExt {
 
 impl AccessoryDeviceInfoExt for DeviceInfo {
     fn in_accessory_mode(&self) -> bool {
-        self.vendor_id() == USB_AOA_VID
-            && (USB_AOA_PID_MIN..=USB_AOA_PID_MAX).contains(&self.product_id())
+        is_accessory(self.vendor_id(), self.product_id())
     }
 }
 
+pub fn is_accessory(vendor_id: u16, product_id: u16) -> bool {
+    vendor_id == USB_AOA_VID && (USB_AOA_PID_MIN..=USB_AOA_PID_MAX).contains(&product_id)
+}
+
 pub trait AccessoryDeviceExt {
     // Get bulk in and out endpoints for the accessory interface
     fn get_bulk_endpoints(&self) -> Option<(u8, u8)>;
@@ -100,18 +101,12 @@ pub trait AccessoryInterfaceExt {
     async fn start_accessory(&mut self, accessory: &AccessoryStrings) -> Result<u16, Error>;
 }
 
-impl AccessoryInterfaceExt for Interface {
+impl<T: UsbInterface> AccessoryInterfaceExt for T {
     async fn get_protocol(&mut self, timeout: Duration) -> Result<u16, Error> {
-        let req = ControlIn {
-            control_type: ControlType::Vendor,
-            recipient: Recipient::Device,
-            request: REQUEST_GET_PROTOCOL,
-            value: 0,
-            index: 0,
-            length: 2, // 2 byte response
-        };
-
-        let res = self.control_in(req, timeout).await?;
+        // 2 byte response
+        let res = self
+            .vendor_in(REQUEST_GET_PROTOCOL, 0, 0, 2, timeout)
+            .await?;
 
         if res.len() != 2 {
             return Err(Error::new(
@@ -132,16 +127,8 @@ impl AccessoryInterfaceExt for Interface {
         let c_str = CString::new(text)?;
         let data = c_str.as_bytes_with_nul();
 
-        let req = ControlOut {
-            control_type: ControlType::Vendor,
-            recipient: Recipient::Device,
-            request: REQUEST_SEND_STRING,
-            value: 0,
-            index,
-            data,
-        };
-
-        self.control_out(req, timeout).await?;
+        self.vendor_out(REQUEST_SEND_STRING, 0, index, data, timeout)
+            .await?;
 
         Ok(())
     }
@@ -176,16 +163,7 @@ impl AccessoryInterfaceExt for Interface {
     }
 
     async fn send_start(&mut self, timeout: Duration) -> Result<(), Error> {
-        let req = ControlOut {
-            control_type: ControlType::Vendor,
-            recipient: Recipient::Device,
-            request: REQUEST_START,
-            value: 0,
-            index: 0,
-            data: &[],
-        };
-
-        self.control_out(req, timeout).await?;
+        self.vendor_out(REQUEST_START, 0, 0, &[], timeout).await?;
 
         Ok(())
     }
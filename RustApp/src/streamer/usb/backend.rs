@@ -0,0 +1,195 @@
+use std::{future::Future, io, time::Duration};
+
+use futures::{StreamExt, stream::BoxStream};
+use nusb::{
+    DeviceInfo,
+    io::{EndpointRead, EndpointWrite},
+    transfer::{Bulk, ControlIn, ControlOut, ControlType, In, Out, Recipient},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{aoa::AccessoryDeviceExt, device::UsbDevice};
+
+const TRANSFER_BUFFER_SIZE: usize = 1024;
+
+#[derive(Clone)]
+pub enum BackendHotplug<I> {
+    Connected(I),
+    Disconnected,
+}
+
+/// Access to the usb bus, implemented with nusb, and by an in-memory device in tests
+pub trait UsbBackend: Send + Sync + 'static {
+    /// Plugged device, before it is opened
+    type Info: Clone + Send + Sync + 'static;
+    type Device: UsbDeviceHandle;
+
+    fn describe(info: &Self::Info) -> UsbDevice;
+
+    fn list_devices(&self) -> impl Future<Output = io::Result<Vec<Self::Info>>> + Send;
+
+    fn watch_devices(&self) -> io::Result<BoxStream<'static, BackendHotplug<Self::Info>>>;
+
+    fn open(&self, info: &Self::Info) -> impl Future<Output = io::Result<Self::Device>> + Send;
+}
+
+pub trait UsbDeviceHandle: Send + Sync + 'static {
+    type Interface: UsbInterface;
+
+    fn claim_interface(
+        &self,
+        number: u8,
+    ) -> impl Future<Output = io::Result<Self::Interface>> + Send;
+
+    /// Bulk in and out endpoints of the first interface
+    fn bulk_endpoints(&self) -> Option<(u8, u8)>;
+}
+
+pub trait UsbInterface: Send + Sync + 'static {
+    type Reader: AsyncRead + Unpin + Send + 'static;
+    type Writer: BulkWriter;
+
+    /// Vendor request to the device, the only control transfers used by aoa
+    fn vendor_in(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+        timeout: Duration,
+    ) -> impl Future<Output = io::Result<Vec<u8>>> + Send;
+
+    fn vendor_out(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+
+    fn open_bulk(
+        &self,
+        endpoint_in: u8,
+        endpoint_out: u8,
+    ) -> io::Result<(Self::Reader, Self::Writer)>;
+}
+
+pub trait BulkWriter: AsyncWrite + Unpin + Send + 'static {
+    /// Flush and end the transfer, so the phone gets the data right away
+    fn flush_end(&mut self) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+pub type Interface<B> = <<B as UsbBackend>::Device as UsbDeviceHandle>::Interface;
+pub type Reader<B> = <Interface<B> as UsbInterface>::Reader;
+pub type Writer<B> = <Interface<B> as UsbInterface>::Writer;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NusbBackend;
+
+impl UsbBackend for NusbBackend {
+    type Info = DeviceInfo;
+    type Device = nusb::Device;
+
+    fn describe(info: &DeviceInfo) -> UsbDevice {
+        UsbDevice::from(info)
+    }
+
+    async fn list_devices(&self) -> io::Result<Vec<DeviceInfo>> {
+        Ok(nusb::list_devices().await?.collect())
+    }
+
+    fn watch_devices(&self) -> io::Result<BoxStream<'static, BackendHotplug<DeviceInfo>>> {
+        Ok(nusb::watch_devices()?
+            .map(|event| match event {
+                nusb::hotplug::HotplugEvent::Connected(info) => BackendHotplug::Connected(info),
+                nusb::hotplug::HotplugEvent::Disconnected(_) => BackendHotplug::Disconnected,
+            })
+            .boxed())
+    }
+
+    async fn open(&self, info: &DeviceInfo) -> io::Result<nusb::Device> {
+        Ok(info.open().await?)
+    }
+}
+
+impl UsbDeviceHandle for nusb::Device {
+    type Interface = nusb::Interface;
+
+    async fn claim_interface(&self, number: u8) -> io::Result<nusb::Interface> {
+        Ok(nusb::Device::claim_interface(self, number).await?)
+    }
+
+    fn bulk_endpoints(&self) -> Option<(u8, u8)> {
+        self.get_bulk_endpoints()
+    }
+}
+
+impl UsbInterface for nusb::Interface {
+    type Reader = EndpointRead<Bulk>;
+    type Writer = EndpointWrite<Bulk>;
+
+    async fn vendor_in(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        let req = ControlIn {
+            control_type: ControlType::Vendor,
+            recipient: Recipient::Device,
+            request,
+            value,
+            index,
+            length,
+        };
+
+        Ok(self.control_in(req, timeout).await?)
+    }
+
+    async fn vendor_out(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> io::Result<()> {
+        let req = ControlOut {
+            control_type: ControlType::Vendor,
+            recipient: Recipient::Device,
+            request,
+            value,
+            index,
+            data,
+        };
+
+        Ok(self.control_out(req, timeout).await?)
+    }
+
+    fn open_bulk(
+        &self,
+        endpoint_in: u8,
+        endpoint_out: u8,
+    ) -> io::Result<(EndpointRead<Bulk>, EndpointWrite<Bulk>)> {
+        let reader = self
+            .endpoint::<Bulk, In>(endpoint_in)?
+            .reader(TRANSFER_BUFFER_SIZE)
+            .with_num_transfers(8);
+
+        let writer = self
+            .endpoint::<Bulk, Out>(endpoint_out)?
+            .writer(TRANSFER_BUFFER_SIZE)
+            .with_num_transfers(8);
+
+        Ok((reader, writer))
+    }
+}
+
+impl BulkWriter for EndpointWrite<Bulk> {
+    async fn flush_end(&mut self) -> io::Result<()> {
+        self.flush_end_async().await
+    }
+}
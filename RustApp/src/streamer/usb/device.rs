@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use nusb::DeviceInfo;
+
+use super::aoa::AccessoryDeviceInfoExt;
+
+/// Vendor ids of Android phone makers, other usb peripherals are left alone
+const ANDROID_VENDOR_IDS: &[u16] = &[
+    0x18D1, // Google
+    0x04E8, // Samsung
+    0x22B8, // Motorola
+    0x1004, // LG
+    0x0BB4, // HTC
+    0x0FCE, // Sony
+    0x12D1, // Huawei
+    0x2717, // Xiaomi
+    0x2A70, // OnePlus
+    0x22D9, // Oppo, Realme
+    0x2D95, // Vivo
+    0x19D2, // ZTE
+    0x17EF, // Lenovo
+    0x0B05, // Asus
+    0x2E04, // HMD (Nokia)
+    0x2AE5, // Fairphone
+    0x2A45, // Meizu
+    0x04DD, // Sharp
+    0x0482, // Kyocera
+];
+
+/// Usb device that may be an Android phone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbDevice {
+    pub serial: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub in_accessory_mode: bool,
+}
+
+impl UsbDevice {
+    /// From a known Android vendor, in accessory mode, or with the chosen serial
+    pub fn is_candidate(&self, serial: Option<&str>) -> bool {
+        ANDROID_VENDOR_IDS.contains(&self.vendor_id)
+            || self.in_accessory_mode
+            || serial.is_some_and(|serial| self.serial.as_deref() == Some(serial))
+    }
+
+    /// `other` is this device re-enumerated in accessory mode
+    pub fn is_accessory(&self, other: &UsbDevice) -> bool {
+        other.in_accessory_mode && (self.serial.is_none() || self.serial == other.serial)
+    }
+
+    pub fn matches(&self, other: &UsbDevice) -> bool {
+        match &self.serial {
+            Some(_) => self.serial == other.serial,
+            None => other.vendor_id == self.vendor_id && other.product_id == self.product_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UsbHotplug {
+    /// A possible Android device was plugged
+    Plugged(UsbDevice),
+    Unplugged,
+}
+
+impl From<&DeviceInfo> for UsbDevice {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            serial: info.serial_number().map(str::to_string),
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            manufacturer: info.manufacturer_string().map(str::to_string),
+            product: info.product_string().map(str::to_string),
+            in_accessory_mode: info.in_accessory_mode(),
+        }
+    }
+}
+
+impl Display for UsbDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.manufacturer, &self.product) {
+            (Some(manufacturer), Some(product)) => write!(f, "{manufacturer} {product}")?,
+            (None, Some(name)) | (Some(name), None) => write!(f, "{name}")?,
+            (None, None) => write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?,
+        }
+
+        if let Some(serial) = &self.serial {
+            write!(f, " ({serial})")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! In-memory usb bus with an Android phone speaking the aoa protocol,
+//! to drive the usb streamer without hardware.
+
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    StreamExt,
+    channel::mpsc::{UnboundedSender, unbounded},
+    stream::BoxStream,
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf, ReadHalf, WriteHalf};
+
+use super::{
+    aoa::{REQUEST_GET_PROTOCOL, REQUEST_SEND_STRING, REQUEST_START, USB_AOA_PID_MIN, USB_AOA_VID},
+    backend::{BackendHotplug, BulkWriter, UsbBackend, UsbDeviceHandle, UsbInterface},
+    device::UsbDevice,
+};
+
+const PHONE_VID: u16 = 0x18D1;
+const PHONE_PID: u16 = 0x4EE7;
+/// Accessory with adb
+const ACCESSORY_PID: u16 = USB_AOA_PID_MIN + 1;
+const AOA_PROTOCOL: u16 = 2;
+
+const ENDPOINT_IN: u8 = 0x81;
+const ENDPOINT_OUT: u8 = 0x01;
+
+const LINK_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Bus {
+    phone_plugged: bool,
+    in_accessory_mode: bool,
+    /// Accessory strings received with `SEND_STRING`, by index
+    strings: HashMap<u16, String>,
+    /// Devices that received a control request
+    touched: Vec<UsbDevice>,
+    watchers: Vec<UnboundedSender<BackendHotplug<UsbDevice>>>,
+    /// Phone side of the accessory bulk endpoints
+    phone_link: Option<DuplexStream>,
+    transfers_failed: Arc<AtomicBool>,
+}
+
+impl Bus {
+    fn notify(&mut self, event: BackendHotplug<UsbDevice>) {
+        self.watchers
+            .retain(|watcher| watcher.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+/// A phone plugged next to an unrelated keyboard
+#[derive(Clone)]
+pub struct FakeBackend {
+    serial: String,
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl FakeBackend {
+    pub fn new(serial: &str) -> Self {
+        Self {
+            serial: serial.to_string(),
+            bus: Arc::new(Mutex::new(Bus {
+                phone_plugged: true,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn keyboard() -> UsbDevice {
+        UsbDevice {
+            serial: None,
+            vendor_id: 0x046D,
+            product_id: 0xC31C,
+            manufacturer: Some("Logitech".into()),
+            product: Some("USB Keyboard".into()),
+            in_accessory_mode: false,
+        }
+    }
+
+    fn phone(&self, in_accessory_mode: bool) -> UsbDevice {
+        UsbDevice {
+            serial: Some(self.serial.clone()),
+            vendor_id: if in_accessory_mode {
+                USB_AOA_VID
+            } else {
+                PHONE_VID
+            },
+            product_id: if in_accessory_mode {
+                ACCESSORY_PID
+            } else {
+                PHONE_PID
+            },
+            manufacturer: Some("Google".into()),
+            product: Some("Pixel".into()),
+            in_accessory_mode,
+        }
+    }
+
+    pub fn accessory_string(&self, index: u16) -> Option<String> {
+        self.bus.lock().unwrap().strings.get(&index).cloned()
+    }
+
+    pub fn touched(&self) -> Vec<UsbDevice> {
+        self.bus.lock().unwrap().touched.clone()
+    }
+
+    /// Phone side of the bulk endpoints, once the accessory is opened
+    pub fn take_phone_link(&self) -> Option<DuplexStream> {
+        self.bus.lock().unwrap().phone_link.take()
+    }
+
+    /// Bulk transfers fail from now on, a pending read fails once the phone link is dropped
+    pub fn unplug(&self) {
+        let mut bus = self.bus.lock().unwrap();
+        bus.phone_plugged = false;
+        bus.in_accessory_mode = false;
+        bus.transfers_failed.store(true, Ordering::Relaxed);
+        bus.phone_link = None;
+        bus.notify(BackendHotplug::Disconnected);
+    }
+
+    /// The phone comes back in normal mode
+    pub fn plug(&self) {
+        let phone = self.phone(false);
+        let mut bus = self.bus.lock().unwrap();
+        bus.phone_plugged = true;
+        bus.notify(BackendHotplug::Connected(phone));
+    }
+}
+
+impl UsbBackend for FakeBackend {
+    type Info = UsbDevice;
+    type Device = FakeDevice;
+
+    fn describe(info: &UsbDevice) -> UsbDevice {
+        info.clone()
+    }
+
+    async fn list_devices(&self) -> io::Result<Vec<UsbDevice>> {
+        let bus = self.bus.lock().unwrap();
+        let mut devices = vec![Self::keyboard()];
+        if bus.phone_plugged {
+            devices.push(self.phone(bus.in_accessory_mode));
+        }
+        Ok(devices)
+    }
+
+    fn watch_devices(&self) -> io::Result<BoxStream<'static, BackendHotplug<UsbDevice>>> {
+        let (sender, receiver) = unbounded();
+        self.bus.lock().unwrap().watchers.push(sender);
+        Ok(receiver.boxed())
+    }
+
+    async fn open(&self, info: &UsbDevice) -> io::Result<FakeDevice> {
+        let bus = self.bus.lock().unwrap();
+        let is_phone = info.serial.as_ref() == Some(&self.serial);
+
+        if is_phone && (!bus.phone_plugged || info.in_accessory_mode != bus.in_accessory_mode) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "device is gone"));
+        }
+
+        Ok(FakeDevice {
+            backend: self.clone(),
+            device: info.clone(),
+            is_phone,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct FakeDevice {
+    backend: FakeBackend,
+    device: UsbDevice,
+    is_phone: bool,
+}
+
+impl FakeDevice {
+    fn touch(&self) -> std::sync::MutexGuard<'_, Bus> {
+        let mut bus = self.backend.bus.lock().unwrap();
+        bus.touched.push(self.device.clone());
+        bus
+    }
+}
+
+fn stall() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "request stalled")
+}
+
+impl UsbDeviceHandle for FakeDevice {
+    type Interface = FakeDevice;
+
+    async fn claim_interface(&self, _number: u8) -> io::Result<FakeDevice> {
+        Ok(self.clone())
+    }
+
+    fn bulk_endpoints(&self) -> Option<(u8, u8)> {
+        self.device
+            .in_accessory_mode
+            .then_some((ENDPOINT_IN, ENDPOINT_OUT))
+    }
+}
+
+impl UsbInterface for FakeDevice {
+    type Reader = FakeReader;
+    type Writer = FakeWriter;
+
+    async fn vendor_in(
+        &self,
+        request: u8,
+        _value: u16,
+        _index: u16,
+        _length: u16,
+        _timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        let _bus = self.touch();
+
+        if self.is_phone && request == REQUEST_GET_PROTOCOL {
+            Ok(AOA_PROTOCOL.to_le_bytes().to_vec())
+        } else {
+            Err(stall())
+        }
+    }
+
+    async fn vendor_out(
+        &self,
+        request: u8,
+        _value: u16,
+        index: u16,
+        data: &[u8],
+        _timeout: Duration,
+    ) -> io::Result<()> {
+        let mut bus = self.touch();
+
+        if !self.is_phone {
+            return Err(stall());
+        }
+
+        match request {
+            REQUEST_SEND_STRING => {
+                let text = data.strip_suffix(&[0]).unwrap_or(data);
+                bus.strings
+                    .insert(index, String::from_utf8_lossy(text).to_string());
+                Ok(())
+            }
+            REQUEST_START => {
+                // the phone re-enumerates in accessory mode
+                bus.in_accessory_mode = true;
+                bus.notify(BackendHotplug::Disconnected);
+                bus.notify(BackendHotplug::Connected(self.backend.phone(true)));
+                Ok(())
+            }
+            _ => Err(stall()),
+        }
+    }
+
+    fn open_bulk(&self, endpoint_in: u8, endpoint_out: u8) -> io::Result<(FakeReader, FakeWriter)> {
+        if self.bulk_endpoints() != Some((endpoint_in, endpoint_out)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no such endpoints",
+            ));
+        }
+
+        let (pc, phone) = tokio::io::duplex(LINK_BUFFER_SIZE);
+        let (reader, writer) = tokio::io::split(pc);
+        let failed = Arc::new(AtomicBool::new(false));
+
+        let mut bus = self.backend.bus.lock().unwrap();
+        bus.phone_link = Some(phone);
+        bus.transfers_failed = failed.clone();
+
+        Ok((
+            FakeReader {
+                inner: reader,
+                failed: failed.clone(),
+            },
+            FakeWriter {
+                inner: writer,
+                failed,
+            },
+        ))
+    }
+}
+
+fn transfer_failed() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "transfer failed")
+}
+
+pub struct FakeReader {
+    inner: ReadHalf<DuplexStream>,
+    failed: Arc<AtomicBool>,
+}
+
+impl AsyncRead for FakeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Poll::Ready(Err(transfer_failed()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+pub struct FakeWriter {
+    inner: WriteHalf<DuplexStream>,
+    failed: Arc<AtomicBool>,
+}
+
+impl AsyncWrite for FakeWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Poll::Ready(Err(transfer_failed()));
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl BulkWriter for FakeWriter {
+    async fn flush_end(&mut self) -> io::Result<()> {
+        self.flush().await
+    }
+}
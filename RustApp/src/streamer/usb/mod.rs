@@ -1 +1,5 @@
 pub mod aoa;
+pub mod backend;
+pub mod device;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fake;
@@ -1,7 +1,9 @@
-use std::time::Duration;
+use std::{
+    pin::pin,
+    time::{Duration, Instant},
+};
 
-use futures::StreamExt;
-use nusb::io::{EndpointRead, EndpointWrite};
+use futures::{Stream, StreamExt, stream};
 use prost::Message;
 use tokio::io::AsyncWriteExt;
 use tokio_util::codec::{FramedRead, LengthDelimitedCodec};
@@ -11,59 +13,70 @@ use crate::{
     audio::process::ProcessCache,
     config::ConnectionMode,
     streamer::{
-        CHECK_2, WriteError,
-        message::{MessageWrapper, message_wrapper::Payload},
-        usb::aoa::{
-            AccessoryDeviceExt, AccessoryDeviceInfoExt, AccessoryInterfaceExt, AccessoryStrings,
+        CHECK_2, PhoneControl, WriteError,
+        frame::{self, Frame},
+        handshake,
+        heartbeat::Heartbeat,
+        message::{ConnectMessage, MessageWrapper, message_wrapper::Payload},
+        usb::{
+            aoa::{AccessoryInterfaceExt, AccessoryStrings},
+            backend::{
+                BackendHotplug, BulkWriter, NusbBackend, Reader, UsbBackend, UsbDeviceHandle,
+                UsbInterface, Writer,
+            },
+            device::{UsbDevice, UsbHotplug},
         },
     },
 };
 
-use super::{AudioPacketMessage, ConnectError, StreamerMsg, StreamerTrait};
+use super::{ConnectError, StreamerMsg, StreamerTrait, capture::Capture};
+
+const SEND_TIMEOUT: Duration = Duration::from_millis(500);
 
-const TRANSFER_BUFFER_SIZE: usize = 1024;
+/// The device re-enumerates after switching to accessory mode
+const ACCESSORY_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+const ACCESSORY_POLL_INTERVAL: Duration = Duration::from_millis(300);
 
-pub struct UsbStreamer {
+pub struct UsbStreamer<B: UsbBackend = NusbBackend> {
+    backend: B,
+    /// Serial of the device chosen by the user
+    serial: Option<String>,
     stream_config: AudioStream,
-    reader: FramedRead<EndpointRead<nusb::transfer::Bulk>, LengthDelimitedCodec>,
-    writer: EndpointWrite<nusb::transfer::Bulk>,
+    reader: FramedRead<Reader<B>, LengthDelimitedCodec>,
+    writer: Writer<B>,
     is_listening: bool,
     tracked_sequence: u32,
     process_cache: ProcessCache,
+    heartbeat: Heartbeat,
+    /// The phone accepts control messages
+    control_enabled: bool,
+    /// Returned by the next call, when a session starts with a message to report
+    pending_message: Option<StreamerMsg>,
+    capture: Option<Capture>,
 }
 
 // switch a USB device to accessory mode
-async fn switch_to_accessory(device_info: &nusb::DeviceInfo) -> Result<(), ConnectError> {
+async fn switch_to_accessory<B: UsbBackend>(
+    backend: &B,
+    device_info: &B::Info,
+    device: &UsbDevice,
+) -> Result<(), ConnectError> {
     info!(
-        "Checking USB device {} (address=0x{:X}, vid=0x{:X}, pid=0x{:X})",
-        device_info.product_string().unwrap_or("unknown"),
-        device_info.device_address(),
-        device_info.vendor_id(),
-        device_info.product_id()
+        "Checking USB device {device} (vid=0x{:X}, pid=0x{:X})",
+        device.vendor_id, device.product_id
     );
 
-    // first check if already in accessory mode
-    if device_info.in_accessory_mode() {
-        info!(
-            "USB device 0x{:X} is already in accessory mode",
-            device_info.device_address()
-        );
-
-        // NOTE: we run the handshake here regardless of whether it is already in AOA mode
-        // return Ok(());
-    }
-
-    // otherwise open device and send AOA control signal
-    let device = device_info
-        .open()
+    // open device and send AOA control signal
+    let handle = backend
+        .open(device_info)
         .await
-        .map_err(|e| ConnectError::CantOpenUsbHandle(e.into()))?;
+        .map_err(ConnectError::CantOpenUsbHandle)?;
 
     // claim the interface
-    let mut iface = device
+    let mut iface = handle
         .claim_interface(0)
         .await
-        .map_err(|e| ConnectError::CantClaimUsbInterface(e.into()))?;
+        .map_err(ConnectError::CantClaimUsbInterface)?;
 
     let strings = AccessoryStrings {
         manufacturer: "AndroidMic".to_string(),
@@ -83,106 +96,298 @@ async fn switch_to_accessory(device_info: &nusb::DeviceInfo) -> Result<(), Conne
         .await
         .map_err(ConnectError::CantSwitchUsbAOAMode)?;
 
-    info!(
-        "USB device {} (address=0x{:X}) switched to accessory mode",
-        device_info.product_string().unwrap_or("unknown"),
-        device_info.device_address()
-    );
+    info!("USB device {device} switched to accessory mode");
 
-    drop(device); // disconnect the device
+    drop(handle); // disconnect the device
     Ok(())
 }
 
-pub async fn new(stream_config: AudioStream) -> Result<UsbStreamer, ConnectError> {
-    // first try to switch any connected device to accessory mode
-    for device in nusb::list_devices()
+type AccessoryEndpoints<B> = (FramedRead<Reader<B>, LengthDelimitedCodec>, Writer<B>);
+
+/// Usb devices that may be Android phones, for the user to choose from
+pub async fn list_devices() -> Result<Vec<UsbDevice>, String> {
+    Ok(candidate_devices(&NusbBackend, None)
         .await
-        .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
-    {
-        if let Err(error) = switch_to_accessory(&device).await {
-            warn!(
-                "Cannot switch USB device 0x{:X} to accessory mode: {}",
-                device.device_address(),
-                error
-            )
-        } else {
-            break;
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(_, device)| device)
+        .collect())
+}
+
+/// Plug and unplug events of possible Android devices
+pub fn hotplug() -> impl Stream<Item = UsbHotplug> {
+    let watch = match NusbBackend.watch_devices() {
+        Ok(watch) => Some(watch),
+        Err(e) => {
+            warn!("can't watch usb devices: {e}");
+            None
         }
+    };
+
+    stream::iter(watch)
+        .flatten()
+        .filter_map(|event| async move {
+            match event {
+                BackendHotplug::Connected(info) => {
+                    let device = NusbBackend::describe(&info);
+                    device
+                        .is_candidate(None)
+                        .then_some(UsbHotplug::Plugged(device))
+                }
+                BackendHotplug::Disconnected => Some(UsbHotplug::Unplugged),
+            }
+        })
+}
+
+async fn candidate_devices<B: UsbBackend>(
+    backend: &B,
+    serial: Option<&str>,
+) -> Result<Vec<(B::Info, UsbDevice)>, ConnectError> {
+    Ok(backend
+        .list_devices()
+        .await
+        .map_err(ConnectError::NoUsbDevice)?
+        .into_iter()
+        .map(|info| {
+            let device = B::describe(&info);
+            (info, device)
+        })
+        .filter(|(_, device)| device.is_candidate(serial))
+        .collect())
+}
+
+/// Find the device chosen by the user, or the only candidate
+async fn select_device<B: UsbBackend>(
+    backend: &B,
+    serial: Option<&str>,
+) -> Result<(B::Info, UsbDevice), ConnectError> {
+    let mut candidates = candidate_devices(backend, serial).await?;
+
+    match serial {
+        Some(serial) => candidates
+            .into_iter()
+            .find(|(_, device)| device.serial.as_deref() == Some(serial))
+            .ok_or_else(|| ConnectError::UsbDeviceNotFound(serial.to_string())),
+        None => match candidates.len() {
+            0 => Err(ConnectError::NoUsbDevice(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "no Android device plugged",
+            ))),
+            1 => Ok(candidates.remove(0)),
+            count => Err(ConnectError::UsbDeviceNotChosen(count)),
+        },
     }
+}
 
-    let (device_info, endpoints, reader, writer) = {
-        let device_info = match nusb::list_devices()
-            .await
-            .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
-            .find(|d| d.in_accessory_mode())
-        {
-            Some(info) => info,
-            None => {
-                return Err(ConnectError::NoUsbDevice(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "No USB device found in accessory mode.\nIf this is your first connection attempt, the device has been switched to accessory mode.\nPlease click Connect again to establish the connection.",
-                )));
+async fn find_accessory<B: UsbBackend>(
+    backend: &B,
+    device: &UsbDevice,
+) -> Result<Option<B::Info>, ConnectError> {
+    Ok(backend
+        .list_devices()
+        .await
+        .map_err(ConnectError::NoUsbDevice)?
+        .into_iter()
+        .find(|info| device.is_accessory(&B::describe(info))))
+}
+
+/// Wait for the device to re-enumerate in accessory mode.
+/// `watch` is created before the switch, to not miss its plug event.
+async fn wait_for_accessory<B: UsbBackend>(
+    backend: &B,
+    device: &UsbDevice,
+    watch: Option<impl Stream<Item = BackendHotplug<B::Info>>>,
+) -> Result<Option<B::Info>, ConnectError> {
+    if let Some(info) = find_accessory(backend, device).await? {
+        return Ok(Some(info));
+    }
+
+    let Some(watch) = watch else {
+        // hot-plug events are not available, poll instead
+        let deadline = Instant::now() + ACCESSORY_WAIT_TIMEOUT;
+        while Instant::now() < deadline {
+            tokio::time::sleep(ACCESSORY_POLL_INTERVAL).await;
+            if let Some(info) = find_accessory(backend, device).await? {
+                return Ok(Some(info));
             }
-        };
+        }
+        return Ok(None);
+    };
 
-        let device = device_info
-            .open()
-            .await
-            .map_err(|e| ConnectError::CantOpenUsbHandle(e.into()))?;
+    let mut watch = pin!(watch);
+    let plugged = async {
+        while let Some(event) = watch.next().await {
+            if let BackendHotplug::Connected(info) = event
+                && device.is_accessory(&B::describe(&info))
+            {
+                return Some(info);
+            }
+        }
+        None
+    };
 
-        let iface = device
-            .claim_interface(0)
-            .await
-            .map_err(|e| ConnectError::CantClaimUsbInterface(e.into()))?;
-
-        let endpoints = device.get_bulk_endpoints().ok_or_else(|| {
-            ConnectError::CantLoadUsbConfig(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Accessory interface does not have required bulk endpoints",
-            ))
-        })?;
-
-        let reader = iface
-            .endpoint::<nusb::transfer::Bulk, nusb::transfer::In>(endpoints.0)
-            .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
-            .reader(TRANSFER_BUFFER_SIZE)
-            .with_num_transfers(8);
-
-        let writer = iface
-            .endpoint::<nusb::transfer::Bulk, nusb::transfer::Out>(endpoints.1)
-            .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
-            .writer(TRANSFER_BUFFER_SIZE)
-            .with_num_transfers(8);
-
-        (device_info, endpoints, reader, writer)
+    Ok(tokio::time::timeout(ACCESSORY_WAIT_TIMEOUT, plugged)
+        .await
+        .ok()
+        .flatten())
+}
+
+/// Wait for the cable to be plugged back
+async fn wait_for_device<B: UsbBackend>(
+    backend: &B,
+    serial: Option<&str>,
+) -> Result<(), ConnectError> {
+    let mut watch = backend.watch_devices().map_err(ConnectError::NoUsbDevice)?;
+
+    // the device may have been plugged before the watch started
+    if !candidate_devices(backend, serial).await?.is_empty() {
+        return Ok(());
+    }
+
+    info!("waiting for the usb device to be plugged");
+    while let Some(event) = watch.next().await {
+        if let BackendHotplug::Connected(info) = event
+            && B::describe(&info).is_candidate(serial)
+        {
+            return Ok(());
+        }
+    }
+
+    Err(ConnectError::Disconnected)
+}
+
+/// Switch the chosen device to accessory mode, then open its bulk endpoints
+async fn open_accessory<B: UsbBackend>(
+    backend: &B,
+    serial: Option<&str>,
+) -> Result<AccessoryEndpoints<B>, ConnectError> {
+    let (device_info, device) = select_device(backend, serial).await?;
+
+    let mut watch = None;
+
+    if !device.in_accessory_mode {
+        watch = backend
+            .watch_devices()
+            .inspect_err(|e| warn!("can't watch usb devices: {e}"))
+            .ok();
+        switch_to_accessory(backend, &device_info, &device).await?;
+    }
+
+    let device_info = match wait_for_accessory(backend, &device, watch).await? {
+        Some(info) => info,
+        None => {
+            return Err(ConnectError::NoUsbDevice(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "The USB device didn't come back in accessory mode.\nAccept the prompt on the phone if there is one, waiting for the device to reconnect.",
+            )));
+        }
     };
 
+    let handle = backend
+        .open(&device_info)
+        .await
+        .map_err(ConnectError::CantOpenUsbHandle)?;
+
+    let iface = handle
+        .claim_interface(0)
+        .await
+        .map_err(ConnectError::CantClaimUsbInterface)?;
+
+    let endpoints = handle.bulk_endpoints().ok_or_else(|| {
+        ConnectError::CantLoadUsbConfig(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Accessory interface does not have required bulk endpoints",
+        ))
+    })?;
+
+    let (reader, writer) = iface
+        .open_bulk(endpoints.0, endpoints.1)
+        .map_err(ConnectError::CantLoadUsbConfig)?;
+
     info!(
-        "Connected to USB device {} (address=0x{:X}, in=0x{:X} out=0x{:X}))",
-        device_info.product_string().unwrap_or("unknown"),
-        device_info.device_address(),
+        "Connected to USB device {} (in=0x{:X} out=0x{:X}))",
+        B::describe(&device_info),
         endpoints.0,
         endpoints.1
     );
 
+    Ok((FramedRead::new(reader, LengthDelimitedCodec::new()), writer))
+}
+
+pub async fn new(
+    serial: Option<String>,
+    stream_config: AudioStream,
+) -> Result<UsbStreamer, ConnectError> {
+    new_with(NusbBackend, serial, stream_config).await
+}
+
+pub async fn new_with<B: UsbBackend>(
+    backend: B,
+    serial: Option<String>,
+    stream_config: AudioStream,
+) -> Result<UsbStreamer<B>, ConnectError> {
+    let (reader, writer) = open_accessory(&backend, serial.as_deref()).await?;
+
     let streamer = UsbStreamer {
+        backend,
+        serial,
         stream_config,
-        reader: FramedRead::new(reader, LengthDelimitedCodec::new()),
+        reader,
         writer,
         is_listening: true,
         tracked_sequence: 0,
         process_cache: ProcessCache::new(),
+        capture: None,
+        heartbeat: Heartbeat::new(),
+        control_enabled: false,
+        pending_message: None,
     };
 
     Ok(streamer)
 }
 
-impl StreamerTrait for UsbStreamer {
+impl<B: UsbBackend> StreamerTrait for UsbStreamer<B> {
     fn reconfigure_stream(&mut self, stream_config: AudioStream) {
         self.stream_config = stream_config;
         self.process_cache.clear();
     }
 
+    fn capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectError> {
+        // the device re-enumerates when the cable is plugged back, so redo the aoa handshake
+        let serial = self.serial.as_deref();
+        let (reader, writer) = match open_accessory(&self.backend, serial).await {
+            Err(ConnectError::NoUsbDevice(..) | ConnectError::UsbDeviceNotFound(..)) => {
+                wait_for_device(&self.backend, serial).await?;
+                open_accessory(&self.backend, serial).await?
+            }
+            res => res?,
+        };
+
+        self.reader = reader;
+        self.writer = writer;
+        self.is_listening = true;
+        self.tracked_sequence = 0;
+        self.heartbeat = Heartbeat::new();
+        self.control_enabled = false;
+        self.pending_message = None;
+        self.process_cache.clear();
+        Ok(())
+    }
+
+    async fn send_control(&mut self, control: &PhoneControl) -> Result<(), ConnectError> {
+        if self.is_listening {
+            Err(ConnectError::NotConnected)
+        } else if !self.control_enabled {
+            Err(ConnectError::ControlUnsupported)
+        } else {
+            self.send_message(control.to_message()).await
+        }
+    }
+
     fn status(&self) -> StreamerMsg {
         if self.is_listening {
             StreamerMsg::Listening {
@@ -193,12 +398,23 @@ impl StreamerTrait for UsbStreamer {
             StreamerMsg::Connected {
                 ip: None,
                 port: None,
+                peer: None,
                 mode: ConnectionMode::Usb,
             }
         }
     }
 
     async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
+        if let Some(message) = self.pending_message.take() {
+            return Ok(Some(message));
+        }
+
+        if !self.is_listening
+            && let Some(ping) = self.heartbeat.next_ping()
+        {
+            self.send_message(ping).await?;
+        }
+
         match tokio::time::timeout(
             Duration::from_secs(if self.is_listening {
                 Duration::MAX.as_secs()
@@ -211,72 +427,54 @@ impl StreamerTrait for UsbStreamer {
         {
             Ok(res) => match res {
                 Some(Ok(frame)) => {
-                    match MessageWrapper::decode(frame) {
-                        Ok(packet) => {
-                            match packet.payload {
-                                Some(payload) => {
-                                    let message = match payload {
-                                        Payload::AudioPacket(packet) => {
-                                            if packet.sequence_number < self.tracked_sequence {
-                                                // drop packet
-                                                info!(
-                                                    "dropped packet: old sequence number {} < {}",
-                                                    packet.sequence_number, self.tracked_sequence
-                                                );
-                                            }
-                                            self.tracked_sequence = packet.sequence_number;
-
-                                            let packet = packet.audio_packet.unwrap();
-                                            let buffer_size = packet.buffer.len();
-                                            let sample_rate = packet.sample_rate;
-
-                                            match self.stream_config.process_audio_packet(
-                                                packet,
-                                                &mut self.process_cache,
-                                            ) {
-                                                Ok(Some(buffer)) => {
-                                                    debug!("received {} bytes", buffer_size);
-                                                    Some(StreamerMsg::UpdateAudioWave {
-                                                        data: AudioPacketMessage::to_wave_data(
-                                                            &buffer,
-                                                            sample_rate,
-                                                        ),
-                                                    })
-                                                }
-                                                _ => None,
-                                            }
-                                        }
-                                        Payload::Connect(_) => {
-                                            info!("Received connect message from device");
-                                            self.writer
-                                                .write_all(CHECK_2.as_bytes())
-                                                .await
-                                                .map_err(|e| {
-                                                    ConnectError::HandShakeFailed("writing", e)
-                                                })?;
-                                            self.writer.flush_end_async().await.map_err(|e| {
-                                                ConnectError::HandShakeFailed("flushing", e)
-                                            })?;
-
-                                            None
-                                        }
-                                    };
-
-                                    if self.is_listening {
-                                        self.is_listening = false;
-                                        Ok(Some(StreamerMsg::Connected {
-                                            ip: None,
-                                            port: None,
-                                            mode: ConnectionMode::Usb,
-                                        }))
-                                    } else {
-                                        Ok(message)
-                                    }
-                                }
-                                None => todo!(),
-                            }
+                    self.heartbeat.received();
+                    if let Some(capture) = &mut self.capture {
+                        capture.record(&frame);
+                    }
+                    let frame = match frame::decode_frame(&frame, &mut self.tracked_sequence) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => return Ok(None),
+                        Err(e) => {
+                            return Err(ConnectError::WriteError(WriteError::Deserializer(e)));
+                        }
+                    };
+
+                    let message = match frame {
+                        Frame::Audio(packet) => frame::play_audio_packet(
+                            &mut self.stream_config,
+                            &mut self.process_cache,
+                            packet,
+                        )?,
+                        Frame::Message(Payload::Connect(connect)) => {
+                            info!("Received connect message from device");
+                            Some(self.handshake(connect).await?)
+                        }
+                        Frame::Message(Payload::Ping(ping)) => {
+                            self.send_message(Heartbeat::pong(&ping)).await?;
+                            None
+                        }
+                        Frame::Message(Payload::Pong(pong)) => self
+                            .heartbeat
+                            .rtt(&pong)
+                            .map(|rtt| StreamerMsg::RoundTrip { rtt }),
+                        Frame::Message(Payload::Telemetry(telemetry)) => {
+                            Some(StreamerMsg::Telemetry(telemetry.into()))
                         }
-                        Err(e) => Err(ConnectError::WriteError(WriteError::Deserializer(e))),
+                        // only sent by the pc
+                        Frame::Message(_) => None,
+                    };
+
+                    if self.is_listening {
+                        self.is_listening = false;
+                        self.pending_message = message;
+                        Ok(Some(StreamerMsg::Connected {
+                            ip: None,
+                            port: None,
+                            peer: None,
+                            mode: ConnectionMode::Usb,
+                        }))
+                    } else {
+                        Ok(message)
                     }
                 }
 
@@ -284,21 +482,195 @@ impl StreamerTrait for UsbStreamer {
                     match e.kind() {
                         std::io::ErrorKind::TimedOut => Ok(None), // timeout use to check for input on stdin
                         std::io::ErrorKind::WouldBlock => Ok(None), // trigger on Linux when there is no stream input
-                        _ => Err(WriteError::Io(e))?,
+                        // the transfer fails when the cable is unplugged: report it, then reattach
+                        _ if !self.is_listening => {
+                            warn!("usb transfer failed: {e}");
+                            self.is_listening = true;
+                            self.tracked_sequence = 0;
+                            Ok(Some(StreamerMsg::Listening {
+                                ip: None,
+                                port: None,
+                            }))
+                        }
+                        _ => Err(ConnectError::Disconnected),
                     }
                 }
-                None => {
-                    todo!()
-                }
+                None => Err(ConnectError::Disconnected),
             },
+            // the reconnect policy reopens the accessory, like tcp and udp listen again
             Err(_) => {
-                self.is_listening = true;
-                self.tracked_sequence = 0;
-                Ok(Some(StreamerMsg::Listening {
-                    ip: None,
-                    port: None,
-                }))
+                if self.heartbeat.is_dead() {
+                    Err(ConnectError::PeerTimeout(self.heartbeat.silence()))
+                } else {
+                    Ok(None)
+                }
             }
         }
     }
 }
+
+impl<B: UsbBackend> UsbStreamer<B> {
+    async fn handshake(&mut self, connect: ConnectMessage) -> Result<StreamerMsg, ConnectError> {
+        match handshake::negotiate(&connect, &self.stream_config.audio_params.target_format) {
+            Ok(None) => {
+                self.control_enabled = false;
+                self.writer
+                    .write_all(CHECK_2.as_bytes())
+                    .await
+                    .map_err(|e| ConnectError::HandShakeFailed("writing", e))?;
+                self.writer
+                    .flush_end()
+                    .await
+                    .map_err(|e| ConnectError::HandShakeFailed("flushing", e))?;
+            }
+            Ok(Some(reply)) => {
+                if handshake::has_feature(&reply, handshake::FEATURE_HEARTBEAT) {
+                    self.heartbeat.enable_ping();
+                }
+                self.control_enabled = handshake::has_feature(&reply, handshake::FEATURE_CONTROL);
+                self.send_message(handshake::reply(reply)).await?;
+            }
+            Err(e) => {
+                // best effort, the session is refused anyway
+                let _ = self.send_message(handshake::refusal(&e)).await;
+                return Err(e);
+            }
+        }
+
+        Ok(StreamerMsg::PeerFeatures {
+            control: self.control_enabled,
+        })
+    }
+
+    /// Send a length delimited message to the device
+    async fn send_message(&mut self, message: MessageWrapper) -> Result<(), ConnectError> {
+        let buf = message.encode_to_vec();
+
+        let write = async {
+            self.writer
+                .write_all(&(buf.len() as u32).to_be_bytes())
+                .await?;
+            self.writer.write_all(&buf).await?;
+            self.writer.flush_end().await
+        };
+
+        // older apps never read from the accessory after the handshake
+        match tokio::time::timeout(SEND_TIMEOUT, write).await {
+            Ok(res) => res.map_err(|e| ConnectError::WriteError(WriteError::Io(e))),
+            Err(_) => {
+                warn!("timeout while sending message to the device");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, DuplexStream};
+
+    use super::*;
+    use crate::streamer::{
+        handshake::PROTOCOL_VERSION,
+        test_support::stream_config,
+        usb::{aoa::ACCESSORY_STRING_MANUFACTURER, fake::FakeBackend},
+    };
+
+    async fn send_connect(phone: &mut DuplexStream) {
+        let buf = MessageWrapper {
+            payload: Some(Payload::Connect(ConnectMessage {
+                protocol_version: PROTOCOL_VERSION,
+                client_name: "test".into(),
+                ..Default::default()
+            })),
+        }
+        .encode_to_vec();
+        phone
+            .write_all(&(buf.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        phone.write_all(&buf).await.unwrap();
+    }
+
+    async fn read_message(phone: &mut DuplexStream) -> MessageWrapper {
+        let len = phone.read_u32().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        phone.read_exact(&mut buf).await.unwrap();
+        MessageWrapper::decode(buf.as_slice()).unwrap()
+    }
+
+    /// Open the accessory and go through the handshake
+    async fn connect(backend: &FakeBackend) -> (UsbStreamer<FakeBackend>, DuplexStream) {
+        let mut streamer = new_with(backend.clone(), None, stream_config())
+            .await
+            .unwrap();
+        assert!(matches!(streamer.status(), StreamerMsg::Listening { .. }));
+
+        let mut phone = backend.take_phone_link().unwrap();
+        send_connect(&mut phone).await;
+        assert!(matches!(
+            streamer.next().await,
+            Ok(Some(StreamerMsg::Connected { .. }))
+        ));
+        assert!(matches!(
+            read_message(&mut phone).await.payload,
+            Some(Payload::ConnectReply(reply)) if reply.error.is_empty()
+        ));
+        assert!(matches!(
+            streamer.next().await,
+            Ok(Some(StreamerMsg::PeerFeatures { control: false }))
+        ));
+
+        (streamer, phone)
+    }
+
+    #[tokio::test]
+    async fn switches_the_phone_to_accessory_mode() {
+        let backend = FakeBackend::new("PHONE1");
+        let _ = connect(&backend).await;
+
+        assert_eq!(
+            backend
+                .accessory_string(ACCESSORY_STRING_MANUFACTURER)
+                .as_deref(),
+            Some("AndroidMic")
+        );
+        let touched = backend.touched();
+        assert!(!touched.is_empty());
+        assert!(!touched.contains(&FakeBackend::keyboard()));
+    }
+
+    #[tokio::test]
+    async fn reattaches_when_the_cable_is_plugged_back() {
+        let backend = FakeBackend::new("PHONE1");
+        let (mut streamer, phone) = connect(&backend).await;
+
+        backend.unplug();
+        drop(phone);
+        assert!(matches!(
+            streamer.next().await,
+            Ok(Some(StreamerMsg::Listening { .. }))
+        ));
+        assert!(matches!(
+            streamer.next().await,
+            Err(ConnectError::Disconnected)
+        ));
+
+        let plug = {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                backend.plug();
+            })
+        };
+        streamer.reconnect().await.unwrap();
+        plug.await.unwrap();
+
+        let mut phone = backend.take_phone_link().unwrap();
+        send_connect(&mut phone).await;
+        assert!(matches!(
+            streamer.next().await,
+            Ok(Some(StreamerMsg::Connected { .. }))
+        ));
+    }
+}
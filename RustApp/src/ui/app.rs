@@ -1,22 +1,29 @@
 use std::{
     fmt::{Debug, Display},
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
+use chrono::Local;
 use cpal::{
     Device, Host,
     traits::{DeviceTrait, HostTrait, StreamTrait},
 };
 use local_ip_address::list_afinet_netifas;
-use notify_rust::Notification;
 use rtrb::RingBuffer;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Sender, UnboundedSender};
 
 use cosmic::{
     Application, ApplicationExt, Element,
     app::{Core, Settings, Task},
     executor,
-    iced::{Size, Subscription, futures::StreamExt, window},
+    iced::{
+        Size, Subscription,
+        futures::{SinkExt, Stream, StreamExt},
+        stream, window,
+    },
     iced_widget::scrollable::{self, AbsoluteOffset},
     theme,
     widget::markdown,
@@ -27,20 +34,33 @@ use cpal::HostId;
 
 use super::{
     message::{AppMsg, ConfigMsg},
+    notification::Notifier,
     view::{main_window, settings_window},
     wave::AudioWave,
 };
 
-#[cfg(not(target_os = "linux"))]
 use super::tray::{SystemTray, SystemTrayMsg, SystemTrayStream};
 
+#[cfg(feature = "adb")]
+use crate::streamer::AdbDevice;
+#[cfg(feature = "usb")]
+use crate::streamer::{UsbDevice, UsbHotplug};
 use crate::{
-    audio::{AudioPacketFormat, AudioProcessParams},
+    audio::{
+        AudioPacketFormat, AudioProcessParams,
+        player::{OutputStreamEvent, OutputStreamEvents},
+    },
     config::{
-        AppTheme, AudioFormat, ChannelCount, Config, ConnectionMode, NetworkAdapter, SampleRate,
+        AppTheme, AudioFormat, ChannelCount, Config, ConnectionMode, MicSource, NetworkAdapter,
+        NotificationEvent, SampleRate, Subnet,
+    },
+    fl,
+    profile::Profile,
+    single_instance,
+    streamer::{
+        self, CAPTURE_EXTENSION, ConnectOption, DEFAULT_PC_PORT, PhoneControl, PhoneTelemetry,
+        ReconnectPolicy, StreamerCommand, StreamerMsg,
     },
-    fl, single_instance,
-    streamer::{self, ConnectOption, DEFAULT_PC_PORT, StreamerCommand, StreamerMsg},
     ui::view::{SCROLLABLE_ID, about_window},
     utils::APP_ID,
     window_icon,
@@ -99,16 +119,136 @@ impl AudioDevice {
     }
 }
 
-fn get_audio_devices(audio_host: &Host) -> Vec<AudioDevice> {
-    audio_host
-        .output_devices()
-        .unwrap()
-        .filter_map(|device| AudioDevice::new(device).ok())
+#[cfg(feature = "usb")]
+fn refresh_usb_devices() -> Task<AppMsg> {
+    Task::perform(streamer::list_usb_devices(), |devices| {
+        cosmic::Action::App(AppMsg::UsbDevices(devices))
+    })
+}
+
+#[cfg(feature = "adb")]
+fn refresh_adb_devices() -> Task<AppMsg> {
+    Task::perform(streamer::list_adb_devices(), |devices| {
+        cosmic::Action::App(AppMsg::AdbDevices(devices))
+    })
+}
+
+pub fn get_network_adapters() -> Vec<NetworkAdapter> {
+    let network_adapters = match list_afinet_netifas() {
+        Ok(network_adapters) => network_adapters,
+        Err(e) => {
+            error!("can't list network adapters: {e}");
+            return Vec::new();
+        }
+    };
+
+    network_adapters
+        .into_iter()
+        // a link local IPv6 address can't be used without its scope
+        .filter(|(_, ip)| match ip {
+            IpAddr::V4(ip) => !ip.is_loopback(),
+            IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unicast_link_local(),
+        })
+        .map(|(name, ip)| NetworkAdapter { name, ip })
         .collect()
 }
 
+pub fn get_audio_devices(audio_host: &Host) -> Vec<AudioDevice> {
+    match audio_host.output_devices() {
+        Ok(devices) => devices
+            .filter_map(|device| AudioDevice::new(device).ok())
+            .collect(),
+        Err(e) => {
+            error!("can't list output devices: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Look for added or removed output devices, away from the ui thread
+/// because listing them can take a while. The list is only sent when it changed.
+fn watch_audio_devices(host_id: cpal::HostId) -> impl Stream<Item = Vec<AudioDevice>> {
+    stream::channel(1, move |mut sender| async move {
+        let list = move || {
+            let audio_host = cpal::host_from_id(host_id).unwrap_or(cpal::default_host());
+            get_audio_devices(&audio_host)
+        };
+        let ids = |devices: &[AudioDevice]| {
+            devices
+                .iter()
+                .map(|device| device.id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // the app already listed the devices of this host
+        let Ok(devices) = tokio::task::spawn_blocking(list).await else {
+            return;
+        };
+        let mut previous = ids(&devices);
+
+        loop {
+            tokio::time::sleep(AUDIO_DEVICES_CHECK_INTERVAL).await;
+
+            let Ok(devices) = tokio::task::spawn_blocking(list).await else {
+                break;
+            };
+            let current = ids(&devices);
+            if current != previous {
+                previous = current;
+                if sender.send(devices).await.is_err() {
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Case insensitive match, where `*` match any sequence of characters.
+/// Without `*`, the pattern only need to be contained in the name.
+fn device_matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+
+    if !pattern.contains('*') {
+        return name.contains(&pattern);
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut rest = name.as_str();
+
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            match rest.strip_prefix(part) {
+                Some(r) => rest = r,
+                None => return false,
+            }
+        } else if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        } else {
+            match rest.find(part) {
+                Some(pos) => rest = &rest[pos + part.len()..],
+                None => return false,
+            }
+        }
+    }
+
+    rest.is_empty()
+}
+
+/// Interval used to look for added or removed output devices
+const AUDIO_DEVICES_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
 const SHARED_BUF_SIZE_S: f32 = 1.; // 0.15s
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputDeviceState {
+    Ok,
+    /// The output device disappeared and no other device is available
+    Lost,
+    /// Playing on another device until a preferred one comes back
+    Fallback,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnectionState {
     Default,
@@ -132,22 +272,57 @@ pub struct AppState {
     pub audio_host: Host,
     pub audio_devices: Vec<AudioDevice>,
     pub audio_device: Option<cpal::Device>,
+    /// Sample rates supported by the selected audio device
+    pub sample_rates: Vec<SampleRate>,
+    pub output_device_state: OutputDeviceState,
+    pub output_stream_sender: UnboundedSender<OutputStreamEvent>,
+    output_stream_events: OutputStreamEvents,
     pub audio_stream: Option<Stream>,
     pub audio_wave: AudioWave,
     pub connection_state: ConnectionState,
+    /// Round trip time with the phone, when the transport measures it
+    pub rtt: Option<Duration>,
+    /// Address of the connected phone, when the transport knows it
+    pub peer: Option<SocketAddr>,
+    /// Sender of the last datagram ignored by the udp listener, and the number ignored
+    pub rejected: Option<(SocketAddr, u64)>,
+    /// The phone accepts control messages
+    pub phone_control: bool,
+    /// State reported by the phone telemetry
+    pub phone_muted: bool,
+    pub phone_mic_source: MicSource,
+    /// Last state reported by the phone
+    pub telemetry: Option<PhoneTelemetry>,
+    connected_since: Option<Instant>,
+    low_battery_warned: bool,
     pub network_adapters: Vec<NetworkAdapter>,
+    #[cfg(feature = "usb")]
+    pub usb_devices: Vec<UsbDevice>,
+    #[cfg(feature = "adb")]
+    pub adb_devices: Vec<AdbDevice>,
+    #[cfg(feature = "adb")]
+    pub adb_pair_address: String,
+    #[cfg(feature = "adb")]
+    pub adb_pair_code: String,
+    /// A wireless pairing or connection is in progress
+    #[cfg(feature = "adb")]
+    pub adb_wireless_busy: bool,
     pub network_adapter: Option<NetworkAdapter>,
     pub port_input: String,
+    pub preferred_devices_input: String,
+    pub allowed_subnets_input: String,
+    pub replay_file_input: String,
+    pub profile_name_input: String,
+    pub profile_file_input: String,
     pub main_window: Option<CustomWindow>,
     pub settings_window: Option<CustomWindow>,
     pub about_window: Option<CustomWindow>,
     pub logs: Vec<markdown::Item>,
     log_path: String,
-    #[cfg(not(target_os = "linux"))]
     pub system_tray: Option<SystemTray>,
-    #[cfg(not(target_os = "linux"))]
     pub system_tray_stream: Option<SystemTrayStream>,
     has_shown_minimize_notification: bool,
+    notifier: Notifier,
     launched_automatically: bool,
 }
 
@@ -161,21 +336,27 @@ impl AppState {
     }
 
     fn update_audio_stream(&mut self) -> Task<AppMsg> {
-        if self.connection_state != ConnectionState::Connected {
+        if !matches!(
+            self.connection_state,
+            ConnectionState::Connected | ConnectionState::Listening
+        ) {
             return Task::none();
         }
         let (producer, consumer) = RingBuffer::<u8>::new(self.get_shared_buf_size());
         let config = self.config.data().clone();
+        let auto_play = self.connection_state == ConnectionState::Connected;
 
-        match self.create_audio_stream(consumer, true) {
+        match self.create_audio_stream(consumer, auto_play) {
             Ok(audio_config) => {
+                let task = self.log_negotiated_format(&audio_config);
+
                 self.send_command(StreamerCommand::ReconfigureStream {
                     buff: producer,
                     audio_params: AudioProcessParams::new(audio_config, config),
                     is_window_visible: self.main_window.is_some(),
                 });
 
-                Task::none()
+                task
             }
             Err(e) => {
                 error!("failed to start audio stream: {e}");
@@ -190,6 +371,114 @@ impl AppState {
         scrollable::scroll_to(SCROLLABLE_ID.clone(), AbsoluteOffset { x: 0., y: f32::MAX })
     }
 
+    fn current_device_id(&self) -> Option<String> {
+        self.audio_device
+            .as_ref()
+            .and_then(|device| device.id().ok())
+            .map(|id| id.to_string())
+    }
+
+    fn has_device_preference(&self) -> bool {
+        let config = self.config.data();
+        config.device_id.is_some() || !config.preferred_devices.is_empty()
+    }
+
+    /// Find the most preferred output device available: the one selected by the user,
+    /// then the first match in the preferred list.
+    fn find_preferred_device(&self, exclude: Option<&str>) -> Option<&AudioDevice> {
+        let config = self.config.data();
+        let devices = || {
+            self.audio_devices
+                .iter()
+                .filter(move |device| Some(device.id.as_str()) != exclude)
+        };
+
+        config
+            .device_id
+            .iter()
+            .filter_map(|id| devices().find(|device| &device.id == id))
+            .chain(config.preferred_devices.iter().filter_map(|pattern| {
+                devices().find(|device| {
+                    &device.id == pattern || device_matches_pattern(pattern, &device.name)
+                })
+            }))
+            .next()
+    }
+
+    /// Switch to the best available output device and rebuild the audio stream on it.
+    /// `exclude` is used to skip a device that is known to be dead.
+    fn select_best_audio_device(&mut self, exclude: Option<&str>) -> Task<AppMsg> {
+        let (device, state) = match self.find_preferred_device(exclude) {
+            Some(device) => (Some(device.clone()), OutputDeviceState::Ok),
+            None => {
+                let default = self
+                    .audio_host
+                    .default_output_device()
+                    .and_then(|device| AudioDevice::new(device).ok())
+                    .filter(|device| Some(device.id.as_str()) != exclude)
+                    .or_else(|| {
+                        self.audio_devices
+                            .iter()
+                            .find(|device| Some(device.id.as_str()) != exclude)
+                            .cloned()
+                    });
+
+                match default {
+                    Some(device) if self.has_device_preference() => {
+                        (Some(device), OutputDeviceState::Fallback)
+                    }
+                    Some(device) => (Some(device), OutputDeviceState::Ok),
+                    None => (None, OutputDeviceState::Lost),
+                }
+            }
+        };
+
+        let previous_id = self.current_device_id();
+        let state_changed = self.output_device_state != state;
+        self.output_device_state = state;
+
+        let Some(device) = device else {
+            self.audio_device = None;
+            self.audio_stream = None;
+            self.sample_rates.clear();
+
+            if state_changed {
+                warn!("no output device available");
+                self.notify(NotificationEvent::DeviceLost, &fl!("output_device_lost"));
+                return self.add_log(&fl!("output_device_lost"));
+            }
+            return Task::none();
+        };
+
+        if previous_id.as_deref() == Some(device.id.as_str()) && self.audio_stream.is_some() {
+            return Task::none();
+        }
+
+        info!("switching output device to {}", device.name);
+        self.sample_rates = SampleRate::supported_by(&device.device);
+        self.audio_device = Some(device.device.clone());
+
+        let log = self.add_log(format!("Output device switched to `{}`", device.name).as_str());
+        Task::batch(vec![log, self.update_audio_stream()])
+    }
+
+    /// Tell the user when the output device could not use the configured format
+    fn log_negotiated_format(&mut self, audio_config: &AudioPacketFormat) -> Task<AppMsg> {
+        let config = self.config.data();
+        let wanted = AudioPacketFormat {
+            sample_rate: config.sample_rate,
+            audio_format: config.audio_format.clone(),
+            channel_count: config.channel_count.clone(),
+        };
+
+        if *audio_config == wanted {
+            return Task::none();
+        }
+
+        info!("negotiated output format: {audio_config}");
+        self.add_log(format!("Output device uses `{audio_config}` instead of `{wanted}`").as_str())
+    }
+
     fn get_shared_buf_size(&self) -> usize {
         let size = ((self.config.data().sample_rate.to_number() as f32
             * self.config.data().channel_count.to_number() as f32
@@ -215,55 +504,197 @@ impl AppState {
 
         let connect_options = match config.connection_mode {
             ConnectionMode::Tcp => {
-                let Some(ip) = config.ip_or_default() else {
+                let ips = config.bind_addresses();
+                if ips.is_empty() {
                     let e = "no address ip found";
 
                     error!("failed to start audio stream: {e}");
                     return self.add_log(e);
-                };
+                }
 
                 ConnectOption::Tcp {
-                    ip,
+                    ips,
                     port: config.port,
+                    allowed_subnets: config.allowed_subnets.clone(),
                 }
             }
             ConnectionMode::Udp => {
-                let Some(ip) = config.ip_or_default() else {
+                let ips = config.bind_addresses();
+                if ips.is_empty() {
                     let e = "no address ip found";
 
                     error!("failed to start audio stream: {e}");
                     return self.add_log(e);
-                };
+                }
                 ConnectOption::Udp {
-                    ip,
+                    ips,
                     port: config.port,
+                    allowed_subnets: config.allowed_subnets.clone(),
                 }
             }
             #[cfg(feature = "adb")]
-            ConnectionMode::Adb => ConnectOption::Adb { port: config.port },
+            ConnectionMode::Adb => ConnectOption::Adb {
+                port: config.port,
+                serial: config.adb_device.clone(),
+                wireless: config
+                    .wireless_adb_devices
+                    .iter()
+                    .find(|device| device.address.is_some() && device.address == config.adb_device)
+                    .cloned(),
+            },
             #[cfg(feature = "usb")]
-            ConnectionMode::Usb => ConnectOption::Usb,
+            ConnectionMode::Usb => ConnectOption::Usb {
+                serial: config.usb_device.clone(),
+            },
+            ConnectionMode::File => {
+                let Some(path) = config.replay_file.clone() else {
+                    let e = "no audio file to replay";
+
+                    error!("failed to start audio stream: {e}");
+                    return self.add_log(e);
+                };
+
+                if path.extension().is_some_and(|ext| ext == CAPTURE_EXTENSION) {
+                    ConnectOption::Replay { path }
+                } else {
+                    ConnectOption::File {
+                        path,
+                        looping: config.replay_loop,
+                    }
+                }
+            }
         };
 
+        let mut tasks = vec![self.log_negotiated_format(&audio_config)];
+
+        let capture = (config.capture_traffic && config.connection_mode != ConnectionMode::File)
+            .then(|| {
+                let file = format!(
+                    "capture-{}.{CAPTURE_EXTENSION}",
+                    Local::now().format("%Y-%m-%d_%H-%M-%S")
+                );
+                Path::new(&self.log_path).with_file_name(file)
+            });
+        if let Some(capture) = &capture {
+            tasks.push(self.add_log(&format!("Capturing traffic to `{}`", capture.display())));
+        }
+
         self.connection_state = ConnectionState::WaitingOnStatus;
 
+        let reconnect_policy = ReconnectPolicy {
+            keep_listening: config.keep_listening,
+            max_retries: config.reconnect_max_retries,
+        };
+
         self.send_command(StreamerCommand::Connect {
             connect_options,
+            reconnect_policy,
+            capture,
             buff: producer,
             audio_params: AudioProcessParams::new(audio_config, config),
             is_window_visible: self.main_window.is_some(),
         });
 
-        Task::none()
+        Task::batch(tasks)
+    }
+
+    fn update_telemetry(&mut self, telemetry: PhoneTelemetry) -> Task<AppMsg> {
+        self.phone_muted = telemetry.muted;
+        if let Some(source) = telemetry.mic_source {
+            self.phone_mic_source = source;
+        }
+
+        if let Some(system_tray) = self.system_tray.as_mut() {
+            system_tray.update_menu_state(
+                false,
+                &format!(
+                    "{} - {}",
+                    fl!("state_connected"),
+                    telemetry_summary(&telemetry)
+                ),
+            );
+        }
+
+        let is_battery_low = telemetry.is_battery_low();
+        let battery_level = telemetry.battery_level.unwrap_or_default();
+        self.telemetry = Some(telemetry);
+
+        if !is_battery_low {
+            self.low_battery_warned = false;
+            return Task::none();
+        }
+        let is_long_session = self
+            .connected_since
+            .is_some_and(|since| since.elapsed() >= PhoneTelemetry::LOW_BATTERY_MIN_SESSION);
+        // the phone sends telemetry periodically, the warning comes once the session is long enough
+        if self.low_battery_warned || !is_long_session {
+            return Task::none();
+        }
+        self.low_battery_warned = true;
+
+        let minutes = self
+            .connected_since
+            .map(|since| since.elapsed().as_secs() / 60)
+            .unwrap_or_default();
+        let warning = fl!(
+            "phone_battery_low",
+            level = battery_level.to_string(),
+            minutes = minutes.to_string()
+        );
+        warn!("{warning}");
+
+        self.notify(NotificationEvent::LowBattery, &warning);
+
+        self.add_log(&warning)
+    }
+
+    /// Show a desktop notification, if the user wants one for this event
+    fn notify(&mut self, event: NotificationEvent, body: &str) {
+        let config = self.config.data();
+        if !config.notify_events.contains(&event)
+            || (config.notify_only_when_hidden && self.main_window.is_some())
+        {
+            return;
+        }
+        self.notifier.notify(event, body);
+    }
+
+    /// Addresses the phone can use to reach the listener
+    pub fn reachable_addresses(&self) -> Vec<&NetworkAdapter> {
+        let config = self.config.data();
+
+        self.network_adapters
+            .iter()
+            .filter(|adapter| {
+                config.bind_all_interfaces
+                    || config.extra_adapters.contains(&adapter.ip)
+                    || self
+                        .network_adapter
+                        .as_ref()
+                        .is_some_and(|selected| selected.ip == adapter.ip)
+            })
+            .filter(|adapter| {
+                config.allowed_subnets.is_empty()
+                    || config
+                        .allowed_subnets
+                        .iter()
+                        .any(|subnet| subnet.contains(adapter.ip))
+            })
+            .collect()
     }
 
     fn disconnect(&mut self) -> Task<AppMsg> {
         self.send_command(StreamerCommand::Stop);
         self.connection_state = ConnectionState::Default;
+        self.rtt = None;
+        self.peer = None;
+        self.rejected = None;
+        self.phone_control = false;
+        self.telemetry = None;
+        self.connected_since = None;
         self.audio_stream = None;
         self.audio_wave.clear();
 
-        #[cfg(not(target_os = "linux"))]
         if let Some(system_tray) = self.system_tray.as_mut() {
             system_tray.update_menu_state(true, &fl!("state_disconnected"));
         }
@@ -271,6 +702,33 @@ impl AppState {
         Task::none()
     }
 
+    fn switch_profile(&mut self, name: &str) -> Task<AppMsg> {
+        let mut result = Ok(Vec::new());
+        self.config.update(|c| result = c.switch_profile(name));
+        let warnings = match result {
+            Ok(warnings) => warnings,
+            Err(e) => return self.add_log(&e),
+        };
+
+        let config = self.config.data();
+        self.port_input = config.port.to_string();
+        self.allowed_subnets_input = config
+            .allowed_subnets
+            .iter()
+            .map(Subnet::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.profile_name_input = name.to_string();
+
+        info!("switched to profile {name}");
+        let mut tasks = vec![self.add_log(&format!("Switched to profile `{name}`"))];
+        for warning in warnings {
+            tasks.push(self.add_log(&warning));
+        }
+        tasks.push(self.update_audio_stream());
+        Task::batch(tasks)
+    }
+
     fn open_main_window(&mut self) -> Task<AppMsg> {
         let mut commands = Vec::new();
         let settings = window::Settings {
@@ -296,6 +754,8 @@ pub struct Flags {
     pub config: ConfigManager<Config>,
     pub config_path: String,
     pub log_path: String,
+    /// Problems found while loading the config, shown in the logs
+    pub config_warnings: Vec<String>,
     pub launched_automatically: bool,
 }
 
@@ -353,15 +813,7 @@ impl Application for AppState {
         };
 
         // initialize network adapter
-        let network_adapters = list_afinet_netifas()
-            .unwrap()
-            .iter()
-            .filter(|(_, ip)| ip.is_ipv4())
-            .map(|(name, ip)| NetworkAdapter {
-                name: name.clone(),
-                ip: *ip,
-            })
-            .collect::<Vec<_>>();
+        let network_adapters = get_network_adapters();
         let network_adapter = match &flags.config.data().ip_or_default() {
             Some(ip) => match network_adapters.iter().find(|adapter| adapter.ip == *ip) {
                 Some(adapter) => Some(adapter.clone()),
@@ -374,7 +826,6 @@ impl Application for AppState {
         };
 
         // initialize system tray
-        #[cfg(not(target_os = "linux"))]
         let (system_tray, system_tray_stream) = match SystemTray::new() {
             Ok((mut tray, stream)) => {
                 tray.update_menu_state(true, &fl!("state_disconnected"));
@@ -388,6 +839,13 @@ impl Application for AppState {
 
         let mut commands = Vec::new();
 
+        let (output_stream_sender, output_stream_events) = OutputStreamEvents::new();
+
+        let sample_rates = audio_device
+            .as_ref()
+            .map(SampleRate::supported_by)
+            .unwrap_or_default();
+
         let config = flags.config.data().clone();
         let mut app = Self {
             core,
@@ -395,25 +853,61 @@ impl Application for AppState {
             streamer: None,
             config: flags.config,
             audio_device,
+            sample_rates,
+            output_device_state: OutputDeviceState::Ok,
+            output_stream_sender,
+            output_stream_events,
             #[cfg(target_os = "linux")]
             available_hosts: cpal::available_hosts(),
             audio_host,
             audio_devices,
             audio_wave: AudioWave::new(),
             connection_state: ConnectionState::Default,
+            rtt: None,
+            peer: None,
+            rejected: None,
+            phone_control: false,
+            phone_muted: false,
+            phone_mic_source: MicSource::default(),
+            telemetry: None,
+            connected_since: None,
+            low_battery_warned: false,
             network_adapters,
             network_adapter,
+            #[cfg(feature = "usb")]
+            usb_devices: Vec::new(),
+            #[cfg(feature = "adb")]
+            adb_devices: Vec::new(),
+            #[cfg(feature = "adb")]
+            adb_pair_address: String::new(),
+            #[cfg(feature = "adb")]
+            adb_pair_code: String::new(),
+            #[cfg(feature = "adb")]
+            adb_wireless_busy: false,
             port_input: config.port.to_string(),
+            preferred_devices_input: config.preferred_devices.join(", "),
+            allowed_subnets_input: config
+                .allowed_subnets
+                .iter()
+                .map(Subnet::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            replay_file_input: config
+                .replay_file
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            profile_name_input: config.active_profile.clone().unwrap_or_default(),
+            profile_file_input: String::new(),
             main_window: None,
             settings_window: None,
             about_window: None,
             logs: Vec::new(),
             log_path: flags.log_path.clone(),
-            #[cfg(not(target_os = "linux"))]
             system_tray,
-            #[cfg(not(target_os = "linux"))]
             system_tray_stream,
             has_shown_minimize_notification: false,
+            notifier: Notifier::default(),
             launched_automatically: flags.launched_automatically,
         };
 
@@ -431,22 +925,38 @@ impl Application for AppState {
         commands.push(
             app.add_log(format!("log path: [{}]({LOG_PATH_WORKAROUND})", flags.log_path).as_str()),
         );
+        for warning in &flags.config_warnings {
+            commands.push(app.add_log(warning));
+        }
         info!("app version: {}", env!("CARGO_PKG_VERSION"));
         info!("config path: {}", flags.config_path);
         info!("log path: {}", flags.log_path);
 
-        #[cfg(not(target_os = "linux"))]
-        if !flags.launched_automatically || !app.config.data().start_minimized {
+        // without tray, the window is the only way back to the app
+        if !flags.launched_automatically
+            || !app.config.data().start_minimized
+            || app.system_tray.is_none()
+        {
             commands.push(app.open_main_window());
         }
 
-        #[cfg(target_os = "linux")]
-        commands.push(app.open_main_window());
+        #[cfg(feature = "adb")]
+        if app.config.data().connection_mode == ConnectionMode::Adb {
+            commands.push(refresh_adb_devices());
+        }
+
+        #[cfg(feature = "usb")]
+        if app.config.data().connection_mode == ConnectionMode::Usb {
+            commands.push(refresh_usb_devices());
+        }
 
         match single_instance::stream() {
             Ok(stream) => {
                 commands.push(cosmic::iced::task::Task::run(stream, |event| match event {
                     single_instance::IpcEvent::Show => cosmic::Action::App(AppMsg::ShowWindow),
+                    single_instance::IpcEvent::SwitchProfile(name) => {
+                        cosmic::Action::App(AppMsg::SwitchProfile(name))
+                    }
                 }));
             }
             Err(e) => {
@@ -465,6 +975,163 @@ impl Application for AppState {
                 self.config.update(|config| {
                     config.connection_mode = connection_mode;
                 });
+
+                #[cfg(feature = "adb")]
+                if connection_mode == ConnectionMode::Adb {
+                    return refresh_adb_devices();
+                }
+
+                #[cfg(feature = "usb")]
+                if connection_mode == ConnectionMode::Usb {
+                    return refresh_usb_devices();
+                }
+            }
+            #[cfg(feature = "usb")]
+            AppMsg::RefreshUsbDevices => {
+                return refresh_usb_devices();
+            }
+            #[cfg(feature = "usb")]
+            AppMsg::UsbDevices(devices) => match devices {
+                Ok(devices) => {
+                    self.usb_devices = devices;
+                }
+                Err(e) => {
+                    error!("can't list usb devices: {e}");
+                    self.usb_devices.clear();
+                    return self.add_log(&format!("Can't list usb devices: {e}"));
+                }
+            },
+            #[cfg(feature = "usb")]
+            AppMsg::UsbHotplug(event) => {
+                let auto_connect = match &event {
+                    UsbHotplug::Plugged(device) => {
+                        config.auto_connect
+                            && self.connection_state == ConnectionState::Default
+                            && (config.usb_device.is_none() || config.usb_device == device.serial)
+                    }
+                    UsbHotplug::Unplugged => false,
+                };
+
+                if auto_connect {
+                    info!("usb device plugged, connecting");
+                    return Task::batch(vec![refresh_usb_devices(), self.connect()]);
+                }
+                return refresh_usb_devices();
+            }
+            #[cfg(feature = "usb")]
+            AppMsg::UsbDevice(device) => {
+                let Some(serial) = device.serial.clone() else {
+                    return self.add_log(&format!(
+                        "Usb device `{device}` has no serial number and can't be remembered"
+                    ));
+                };
+                self.config.update(|c| c.usb_device = Some(serial));
+                return self.add_log(&format!("Selected usb device: {device}"));
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::RefreshAdbDevices => {
+                return refresh_adb_devices();
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbDevices(devices) => match devices {
+                Ok(devices) => {
+                    self.adb_devices = devices;
+                }
+                Err(e) => {
+                    error!("can't list adb devices: {e}");
+                    self.adb_devices.clear();
+                    return self.add_log(&format!("Can't list adb devices: {e}"));
+                }
+            },
+            #[cfg(feature = "adb")]
+            AppMsg::AdbDevice(device) => {
+                self.config
+                    .update(|c| c.adb_device = Some(device.serial.clone()));
+                return self.add_log(&format!("Selected adb device: {device}"));
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbPairAddress(address) => {
+                self.adb_pair_address = address;
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbPairCode(code) => {
+                self.adb_pair_code = code;
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbDiscoverPairing => {
+                return Task::perform(streamer::discover_pairing_services(), |services| {
+                    cosmic::Action::App(AppMsg::AdbPairingServices(services))
+                });
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbPairingServices(services) => match services {
+                Ok(services) => match services.first() {
+                    Some(service) => {
+                        self.adb_pair_address = service.address.clone();
+                        return self.add_log(&format!(
+                            "Found `{}` waiting to be paired on `{}`",
+                            service.name, service.address
+                        ));
+                    }
+                    None => return self.add_log(&fl!("adb_no_pairing_service")),
+                },
+                Err(e) => {
+                    error!("can't discover adb devices: {e}");
+                    return self.add_log(&format!("Can't discover adb devices: {e}"));
+                }
+            },
+            #[cfg(feature = "adb")]
+            AppMsg::AdbPair => {
+                self.adb_wireless_busy = true;
+                return Task::perform(
+                    streamer::pair_wireless(
+                        self.adb_pair_address.trim().to_string(),
+                        self.adb_pair_code.trim().to_string(),
+                    ),
+                    |result| cosmic::Action::App(AppMsg::AdbWirelessConnected(result)),
+                );
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbConnectWireless(device) => {
+                self.adb_wireless_busy = true;
+                return Task::perform(streamer::reconnect_wireless(device), |result| {
+                    cosmic::Action::App(AppMsg::AdbWirelessConnected(result))
+                });
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbWirelessConnected(result) => {
+                self.adb_wireless_busy = false;
+
+                match result {
+                    Ok((device, serial)) => {
+                        self.adb_pair_code.clear();
+                        self.config.update(|c| {
+                            match c
+                                .wireless_adb_devices
+                                .iter_mut()
+                                .find(|paired| paired.name == device.name)
+                            {
+                                Some(paired) => *paired = device.clone(),
+                                None => c.wireless_adb_devices.push(device.clone()),
+                            }
+                            c.adb_device = Some(serial);
+                        });
+
+                        return Task::batch(vec![
+                            self.add_log(&format!("Connected to wireless adb device `{device}`")),
+                            refresh_adb_devices(),
+                        ]);
+                    }
+                    Err(e) => {
+                        error!("wireless adb failed: {e}");
+                        return self.add_log(&format!("Wireless adb failed: {e}"));
+                    }
+                }
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbForgetWireless(device) => {
+                self.config
+                    .update(|c| c.wireless_adb_devices.retain(|paired| *paired != device));
             }
             AppMsg::RefreshAudioDevices => {
                 #[cfg(not(target_os = "linux"))]
@@ -477,74 +1144,168 @@ impl Application for AppState {
                 self.audio_devices = get_audio_devices(&audio_host);
             }
             AppMsg::RefreshNetworkAdapters => {
-                let network_adapters = list_afinet_netifas()
-                    .unwrap()
-                    .iter()
-                    .filter(|(_, ip)| ip.is_ipv4())
-                    .map(|(name, ip)| NetworkAdapter {
-                        name: name.clone(),
-                        ip: *ip,
-                    })
-                    .collect::<Vec<_>>();
-                self.network_adapters = network_adapters;
+                self.network_adapters = get_network_adapters();
             }
             AppMsg::Streamer(streamer_msg) => match streamer_msg {
                 StreamerMsg::Error(e) => {
+                    self.notify(NotificationEvent::Error, &e);
                     self.connection_state = ConnectionState::Default;
+                    self.rtt = None;
+                    self.peer = None;
+                    self.rejected = None;
+                    self.phone_control = false;
+                    self.telemetry = None;
+                    self.connected_since = None;
                     self.audio_stream = None;
                     self.audio_wave.clear();
                     return self.add_log(&e);
                 }
                 StreamerMsg::Listening { ip, port } => {
-                    if let Err(e) = self.audio_stream.as_ref().unwrap().stream.pause() {
+                    if let Some(audio_stream) = &self.audio_stream
+                        && let Err(e) = audio_stream.stream.pause()
+                    {
                         error!("{e}");
                     }
                     self.audio_wave.clear();
 
-                    #[cfg(not(target_os = "linux"))]
                     if let Some(system_tray) = self.system_tray.as_mut() {
                         system_tray.update_menu_state(false, &fl!("state_listening"));
                     }
 
+                    if self.connection_state == ConnectionState::Connected {
+                        self.notify(
+                            NotificationEvent::Disconnected,
+                            &fl!("notification_disconnected"),
+                        );
+                    }
+
                     self.connection_state = ConnectionState::Listening;
+                    self.rtt = None;
+                    self.peer = None;
+                    self.rejected = None;
+                    self.phone_control = false;
+                    self.telemetry = None;
+                    self.connected_since = None;
                     if let (Some(ip), Some(port)) = (ip, port) {
-                        info!("listening on {ip}:{port}");
-                        return self.add_log(format!("Listening on `{ip}:{port}`").as_str());
+                        let address = SocketAddr::new(ip, port);
+                        info!("listening on {address}");
+
+                        let mut log = format!("Listening on `{address}`");
+                        if ip.is_unspecified() || !self.config.data().extra_adapters.is_empty() {
+                            let reachable = self
+                                .reachable_addresses()
+                                .iter()
+                                .map(|adapter| format!("`{}`", SocketAddr::new(adapter.ip, port)))
+                                .collect::<Vec<_>>();
+                            log.push_str(&format!(", reachable on {}", reachable.join(", ")));
+                        }
+                        return self.add_log(&log);
                     }
                 }
-                StreamerMsg::Connected { ip, port, mode: _ } => {
-                    if let Err(e) = self.audio_stream.as_ref().unwrap().stream.play() {
+                StreamerMsg::Connected {
+                    ip,
+                    port,
+                    peer,
+                    mode: _,
+                } => {
+                    if let Some(audio_stream) = &self.audio_stream
+                        && let Err(e) = audio_stream.stream.play()
+                    {
                         error!("{e}");
                     }
 
-                    #[cfg(not(target_os = "linux"))]
                     if let Some(system_tray) = self.system_tray.as_mut() {
                         system_tray.update_menu_state(false, &fl!("state_connected"));
                     }
 
-                    if self.main_window.is_none() {
-                        let address = format!(
-                            "{}:{}",
-                            ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
-                            port.unwrap_or_default()
-                        );
-
-                        // show notification when app is minimized
-                        let _ = Notification::new()
-                            .summary("AndroidMic")
-                            .body(format!("Connected on {address}").as_str())
-                            .auto_icon()
-                            .show()
-                            .map_err(|e| {
-                                error!("failed to show notification: {e}");
-                            });
-                    }
+                    let address = SocketAddr::new(
+                        ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                        port.unwrap_or_default(),
+                    );
+                    self.notify(
+                        NotificationEvent::Connected,
+                        &fl!("notification_connected", address = address.to_string()),
+                    );
 
                     self.connection_state = ConnectionState::Connected;
+                    self.peer = peer;
+                    self.phone_control = false;
+                    self.phone_muted = false;
+                    self.phone_mic_source = MicSource::default();
+                    self.telemetry = None;
+                    self.connected_since = Some(Instant::now());
+                    self.low_battery_warned = false;
                     if let (Some(ip), Some(port)) = (ip, port) {
-                        info!("connected on {ip}:{port}");
-                        return self.add_log(format!("Connected on `{ip}:{port}`").as_str());
+                        let log = match peer {
+                            Some(peer) => {
+                                format!("Connected to `{peer}` on `{}`", SocketAddr::new(ip, port))
+                            }
+                            None => format!("Connected on `{}`", SocketAddr::new(ip, port)),
+                        };
+                        info!("{log}");
+                        return self.add_log(&log);
+                    }
+                }
+                StreamerMsg::Reconnecting {
+                    error,
+                    attempt,
+                    max_attempts,
+                    delay,
+                } => {
+                    if let Some(audio_stream) = &self.audio_stream
+                        && let Err(e) = audio_stream.stream.pause()
+                    {
+                        error!("{e}");
                     }
+                    self.audio_wave.clear();
+
+                    if let Some(system_tray) = self.system_tray.as_mut() {
+                        system_tray.update_menu_state(false, &fl!("state_reconnecting"));
+                    }
+
+                    if self.connection_state == ConnectionState::Connected {
+                        self.notify(
+                            NotificationEvent::Disconnected,
+                            &fl!("notification_disconnected"),
+                        );
+                    }
+
+                    self.connection_state = ConnectionState::Listening;
+                    self.rtt = None;
+                    self.peer = None;
+                    self.rejected = None;
+                    self.phone_control = false;
+                    self.telemetry = None;
+                    self.connected_since = None;
+                    return self.add_log(
+                        format!(
+                            "{error}, reconnecting in {:.1}s ({attempt}/{max_attempts})",
+                            delay.as_secs_f32()
+                        )
+                        .as_str(),
+                    );
+                }
+                StreamerMsg::PeerFeatures { control } => {
+                    self.phone_control = control;
+                }
+                StreamerMsg::Rejected { from, count } => {
+                    self.rejected = Some((from, count));
+                }
+                StreamerMsg::RoundTrip { rtt } => {
+                    self.rtt = Some(rtt);
+                }
+                StreamerMsg::BufferOverrun { dropped_bytes } => {
+                    debug!("buffer overrun, dropped {dropped_bytes} bytes");
+                    self.notify(
+                        NotificationEvent::BufferOverrun,
+                        &fl!("notification_buffer_overrun"),
+                    );
+                }
+                StreamerMsg::ControlFailed(e) => {
+                    return self.add_log(&e);
+                }
+                StreamerMsg::Telemetry(telemetry) => {
+                    return self.update_telemetry(telemetry);
                 }
                 StreamerMsg::UpdateAudioWave { data } => {
                     self.audio_wave.write_chunk(&data);
@@ -557,6 +1318,8 @@ impl Application for AppState {
                 }
             },
             AppMsg::Device(audio_device) => {
+                self.output_device_state = OutputDeviceState::Ok;
+                self.sample_rates = SampleRate::supported_by(&audio_device.device);
                 self.audio_device = Some(audio_device.device.clone());
                 self.config
                     .update(|c| c.device_id = Some(audio_device.id.clone()));
@@ -567,11 +1330,54 @@ impl Application for AppState {
                 self.audio_host = cpal::host_from_id(selected_host).unwrap_or(cpal::default_host());
                 self.audio_devices = get_audio_devices(&self.audio_host);
             }
+            AppMsg::AudioDevices(audio_devices) => {
+                self.audio_devices = audio_devices;
+
+                let current_id = self.current_device_id();
+                let is_present = current_id
+                    .as_ref()
+                    .is_some_and(|id| self.audio_devices.iter().any(|d| &d.id == id));
+
+                if !is_present {
+                    return self.select_best_audio_device(None);
+                }
+
+                // switch back when a preferred device comes back
+                if self.output_device_state == OutputDeviceState::Fallback
+                    && let Some(preferred) = self.find_preferred_device(None)
+                    && Some(&preferred.id) != current_id.as_ref()
+                {
+                    return self.select_best_audio_device(None);
+                }
+            }
+            AppMsg::OutputStream(event) => match event {
+                OutputStreamEvent::DeviceLost => {
+                    warn!("output device lost");
+                    self.notify(NotificationEvent::DeviceLost, &fl!("output_device_lost"));
+                    self.audio_devices = get_audio_devices(&self.audio_host);
+                    let lost_id = self.current_device_id();
+                    return self.select_best_audio_device(lost_id.as_deref());
+                }
+                OutputStreamEvent::Error(e) => {
+                    self.notify(NotificationEvent::Error, &e);
+                    return self.add_log(&e);
+                }
+            },
             AppMsg::Adapter(adapter) => {
                 self.config.update(|c| c.ip = Some(adapter.ip));
                 self.network_adapter = Some(adapter.clone());
                 return self.add_log(format!("Selected network adapter: {adapter}").as_str());
             }
+            AppMsg::StartAtLoginChanged(res) => {
+                let enabled = res.unwrap_or_else(|e| {
+                    error!("can't change start at login: {e}");
+                    false
+                });
+                self.config.update(|s| s.start_at_login = enabled);
+            }
+            AppMsg::SwitchProfile(name) => {
+                return self.switch_profile(&name);
+            }
             AppMsg::Connect => {
                 return self.connect();
             }
@@ -603,6 +1409,10 @@ impl Application for AppState {
                         .chain(set_window_title);
                 }
             },
+            AppMsg::PhoneControl(control) => {
+                // the controls follow the telemetry, sent by the phone when its state changes
+                self.send_command(StreamerCommand::Control(control));
+            }
             AppMsg::Config(msg) => match msg {
                 ConfigMsg::PortTextInput(text) => {
                     self.port_input = text;
@@ -631,6 +1441,125 @@ impl Application for AppState {
                     return self.add_log(format!("Changed port to {}", port).as_str());
                 }
 
+                ConfigMsg::PreferredDevicesInput(text) => {
+                    self.preferred_devices_input = text;
+                }
+                ConfigMsg::PreferredDevicesSave => {
+                    let preferred_devices: Vec<String> = self
+                        .preferred_devices_input
+                        .split(',')
+                        .map(|pattern| pattern.trim().to_string())
+                        .filter(|pattern| !pattern.is_empty())
+                        .collect();
+
+                    self.preferred_devices_input = preferred_devices.join(", ");
+                    self.config
+                        .update(|c| c.preferred_devices = preferred_devices);
+                }
+                ConfigMsg::BindAllInterfaces(bind_all_interfaces) => {
+                    self.config
+                        .update(|c| c.bind_all_interfaces = bind_all_interfaces);
+                }
+                ConfigMsg::ExtraAdapter(ip, listen) => {
+                    self.config.update(|c| {
+                        c.extra_adapters.retain(|extra| *extra != ip);
+                        if listen {
+                            c.extra_adapters.push(ip);
+                        }
+                    });
+                }
+                ConfigMsg::AllowedSubnetsInput(text) => {
+                    self.allowed_subnets_input = text;
+                }
+                ConfigMsg::AllowedSubnetsSave => {
+                    let allowed_subnets = match self
+                        .allowed_subnets_input
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|subnet| !subnet.is_empty())
+                        .map(Subnet::from_str)
+                        .collect::<Result<Vec<_>, _>>()
+                    {
+                        Ok(allowed_subnets) => allowed_subnets,
+                        Err(e) => return self.add_log(&e),
+                    };
+
+                    self.allowed_subnets_input = allowed_subnets
+                        .iter()
+                        .map(Subnet::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    self.config.update(|c| c.allowed_subnets = allowed_subnets);
+                }
+                ConfigMsg::ReplayFileInput(text) => {
+                    self.replay_file_input = text;
+                }
+                ConfigMsg::ReplayFileSave => {
+                    let path = self.replay_file_input.trim();
+                    let replay_file = (!path.is_empty()).then(|| PathBuf::from(path));
+                    self.config.update(|c| c.replay_file = replay_file);
+                }
+                ConfigMsg::ProfileNameInput(text) => {
+                    self.profile_name_input = text;
+                }
+                ConfigMsg::ProfileSave => {
+                    let name = self.profile_name_input.trim().to_string();
+                    if name.is_empty() {
+                        return self.add_log("The profile needs a name");
+                    }
+                    self.config.update(|c| {
+                        c.insert_profile(Profile::from_config(name.clone(), c));
+                        c.active_profile = Some(name.clone());
+                    });
+                    return self.add_log(&format!("Saved profile `{name}`"));
+                }
+                ConfigMsg::ProfileDelete => {
+                    let name = self.profile_name_input.trim().to_string();
+                    if config.profile(&name).is_none() {
+                        return self.add_log(&format!("No profile named `{name}`"));
+                    }
+                    self.config.update(|c| c.remove_profile(&name));
+                    self.profile_name_input.clear();
+                    return self.add_log(&format!("Deleted profile `{name}`"));
+                }
+                ConfigMsg::ProfileFileInput(text) => {
+                    self.profile_file_input = text;
+                }
+                ConfigMsg::ProfileImport => {
+                    let path = PathBuf::from(self.profile_file_input.trim());
+                    match Profile::import(&path) {
+                        Ok(profile) => {
+                            let name = profile.name.clone();
+                            self.config.update(|c| c.insert_profile(profile));
+                            return self.add_log(&format!("Imported profile `{name}`"));
+                        }
+                        Err(e) => {
+                            error!("can't import profile: {e:#}");
+                            return self.add_log(&format!("Can't import profile: {e:#}"));
+                        }
+                    }
+                }
+                ConfigMsg::ProfileExport => {
+                    let name = self.profile_name_input.trim().to_string();
+                    let Some(profile) = config.profile(&name) else {
+                        return self.add_log(&format!("No profile named `{name}`"));
+                    };
+                    let path = PathBuf::from(self.profile_file_input.trim());
+                    if let Err(e) = profile.export(&path) {
+                        error!("can't export profile: {e:#}");
+                        return self.add_log(&format!("Can't export profile: {e:#}"));
+                    }
+                    return self.add_log(&format!(
+                        "Exported profile `{name}` to `{}`",
+                        path.display()
+                    ));
+                }
+                ConfigMsg::CaptureTraffic(capture_traffic) => {
+                    self.config.update(|c| c.capture_traffic = capture_traffic);
+                }
+                ConfigMsg::ReplayLoop(replay_loop) => {
+                    self.config.update(|c| c.replay_loop = replay_loop);
+                }
                 ConfigMsg::SampleRate(sample_rate) => {
                     self.config.update(|s| s.sample_rate = sample_rate);
                     return self.update_audio_stream();
@@ -644,11 +1573,38 @@ impl Application for AppState {
                     return self.update_audio_stream();
                 }
                 ConfigMsg::StartAtLogin(start_at_login) => {
-                    crate::start_at_login::start_at_login(start_at_login, &mut self.config);
+                    // the flatpak portal may show a dialog, the ui keeps running meanwhile
+                    return Task::perform(
+                        crate::start_at_login::set_start_at_login(start_at_login),
+                        |res| {
+                            cosmic::Action::App(AppMsg::StartAtLoginChanged(
+                                res.map_err(|e| e.to_string()),
+                            ))
+                        },
+                    );
                 }
                 ConfigMsg::AutoConnect(auto_connect) => {
                     self.config.update(|s| s.auto_connect = auto_connect);
                 }
+                ConfigMsg::NotifyEvent(event, enabled) => {
+                    self.config.update(|s| {
+                        s.notify_events.retain(|e| *e != event);
+                        if enabled {
+                            s.notify_events.push(event);
+                        }
+                    });
+                }
+                ConfigMsg::NotifyOnlyWhenHidden(notify_only_when_hidden) => {
+                    self.config
+                        .update(|s| s.notify_only_when_hidden = notify_only_when_hidden);
+                }
+                ConfigMsg::KeepListening(keep_listening) => {
+                    self.config.update(|s| s.keep_listening = keep_listening);
+                }
+                ConfigMsg::ReconnectMaxRetries(max_retries) => {
+                    self.config
+                        .update(|s| s.reconnect_max_retries = max_retries as u32);
+                }
                 ConfigMsg::UseRecommendedFormat => {
                     if let Some(device) = &self.audio_device
                         && let Ok(format) = device.default_output_config()
@@ -670,6 +1626,14 @@ impl Application for AppState {
                         return self.update_audio_stream();
                     }
                 }
+                ConfigMsg::SendFormatToPhone => {
+                    let format = AudioPacketFormat {
+                        sample_rate: config.sample_rate,
+                        audio_format: config.audio_format.clone(),
+                        channel_count: config.channel_count.clone(),
+                    };
+                    self.send_command(StreamerCommand::Control(PhoneControl::ChangeFormat(format)));
+                }
                 ConfigMsg::ResetDenoiseSettings => {
                     self.config.update(|c| c.reset_denoise_settings());
                 }
@@ -790,14 +1754,10 @@ impl Application for AppState {
                 }
 
                 if !self.launched_automatically && !self.has_shown_minimize_notification {
-                    let _ = Notification::new()
-                        .summary("AndroidMic")
-                        .body(&fl!("minimized_to_tray"))
-                        .auto_icon()
-                        .show()
-                        .map_err(|e| {
-                            error!("failed to show notification: {e}");
-                        });
+                    self.notify(
+                        NotificationEvent::MinimizedToTray,
+                        &fl!("minimized_to_tray"),
+                    );
                     self.has_shown_minimize_notification = true;
                 }
 
@@ -823,7 +1783,6 @@ impl Application for AppState {
                     error!("{e}");
                 }
             }
-            #[cfg(not(target_os = "linux"))]
             AppMsg::SystemTray(tray_msg) => match tray_msg {
                 SystemTrayMsg::Show => {
                     if let Some(main_window) = &self.main_window {
@@ -894,9 +1853,29 @@ impl Application for AppState {
 
     fn subscription(&self) -> cosmic::iced::Subscription<Self::Message> {
         #[allow(unused_mut)]
-        let mut subscriptions = vec![Subscription::run(|| streamer::sub().map(AppMsg::Streamer))];
+        let mut subscriptions = vec![
+            Subscription::run(|| streamer::sub().map(AppMsg::Streamer)),
+            Subscription::run_with_id(
+                "output-stream-events",
+                self.output_stream_events
+                    .clone()
+                    .sub()
+                    .map(AppMsg::OutputStream),
+            ),
+            Subscription::run_with_id(
+                ("audio-devices", self.audio_host.id()),
+                watch_audio_devices(self.audio_host.id()).map(AppMsg::AudioDevices),
+            ),
+        ];
+
+        // a plugged phone refreshes the device list, and can trigger auto connect
+        #[cfg(feature = "usb")]
+        if self.config.data().connection_mode == ConnectionMode::Usb {
+            subscriptions.push(Subscription::run(|| {
+                streamer::usb_hotplug().map(AppMsg::UsbHotplug)
+            }));
+        }
 
-        #[cfg(not(target_os = "linux"))]
         if let Some(system_tray_stream) = &self.system_tray_stream {
             subscriptions.push(Subscription::run_with_id(
                 "system-tray",
@@ -921,10 +1900,10 @@ impl Application for AppState {
         if let Some(window) = &self.main_window
             && window.window_id == id
         {
-            #[cfg(not(target_os = "linux"))]
-            return Some(AppMsg::HideWindow);
-
-            #[cfg(target_os = "linux")]
+            // the tray may be missing, like on desktops without StatusNotifierItem support
+            if self.system_tray.is_some() {
+                return Some(AppMsg::HideWindow);
+            }
             return Some(AppMsg::Exit);
         }
 
@@ -941,3 +1920,63 @@ fn to_cosmic_theme(theme: &AppTheme) -> theme::Theme {
         AppTheme::System => theme::system_preference(),
     }
 }
+
+/// Device name and battery, shown in the tray tooltip and the main window
+pub fn telemetry_summary(telemetry: &PhoneTelemetry) -> String {
+    let name = telemetry.device_name.as_str();
+
+    match (telemetry.battery_level, telemetry.charging) {
+        (Some(level), true) => fl!(
+            "phone_summary_charging",
+            name = name,
+            level = level.to_string()
+        ),
+        (Some(level), false) => fl!("phone_summary", name = name, level = level.to_string()),
+        (None, _) => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_without_star_is_contained() {
+        assert!(device_matches_pattern(
+            "headset",
+            "USB Headset Analog Stereo"
+        ));
+        assert!(!device_matches_pattern(
+            "speakers",
+            "USB Headset Analog Stereo"
+        ));
+    }
+
+    #[test]
+    fn pattern_with_star_matches_whole_name() {
+        assert!(device_matches_pattern("usb*", "USB Headset Analog Stereo"));
+        assert!(device_matches_pattern(
+            "*stereo",
+            "USB Headset Analog Stereo"
+        ));
+        assert!(device_matches_pattern(
+            "usb*analog*",
+            "USB Headset Analog Stereo"
+        ));
+        assert!(device_matches_pattern("*", "Speakers"));
+
+        // anchored at both ends, unlike the substring match
+        assert!(!device_matches_pattern(
+            "headset*",
+            "USB Headset Analog Stereo"
+        ));
+        assert!(!device_matches_pattern(
+            "*headset",
+            "USB Headset Analog Stereo"
+        ));
+        assert!(!device_matches_pattern(
+            "usb*mono",
+            "USB Headset Analog Stereo"
+        ));
+    }
+}
@@ -73,3 +73,43 @@ macro_rules! tray_icon {
     }};
     ($name:literal) => {{ tray_icon!($name, 32, 32) }};
 }
+
+/// ARGB32 in network byte order, as expected by StatusNotifierItem
+#[cfg(target_os = "linux")]
+#[macro_export]
+macro_rules! tray_icon {
+    ($name:literal, $width:expr, $height:expr) => {{
+        let svg = include_bytes!(concat!("../../res/icons/", $name, ".svg"));
+        let opt = resvg::usvg::Options::default();
+        let tree = resvg::usvg::Tree::from_data(svg, &opt).ok();
+
+        tree.and_then(|tree| {
+            let viewbox = tree.size();
+            let mut pixmap = resvg::tiny_skia::Pixmap::new($width, $height)?;
+            resvg::render(
+                &tree,
+                resvg::tiny_skia::Transform::from_scale(
+                    $width as f32 / viewbox.width(),
+                    $height as f32 / viewbox.height(),
+                ),
+                &mut pixmap.as_mut(),
+            );
+
+            let data = pixmap
+                .pixels()
+                .iter()
+                .flat_map(|pixel| {
+                    let color = pixel.demultiply();
+                    [color.alpha(), color.red(), color.green(), color.blue()]
+                })
+                .collect();
+
+            Some(ksni::Icon {
+                width: $width as i32,
+                height: $height as i32,
+                data,
+            })
+        })
+    }};
+    ($name:literal) => {{ tray_icon!($name, 32, 32) }};
+}
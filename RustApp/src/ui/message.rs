@@ -1,12 +1,21 @@
+use std::net::IpAddr;
+
 use super::app::AudioDevice;
-#[cfg(not(target_os = "linux"))]
 use super::tray::SystemTrayMsg;
+#[cfg(feature = "usb")]
+use crate::streamer::{UsbDevice, UsbHotplug};
 use crate::{
+    audio::player::OutputStreamEvent,
     config::{
         AppTheme, AudioEffect, AudioFormat, ChannelCount, ConnectionMode, DenoiseKind,
-        NetworkAdapter, SampleRate,
+        NetworkAdapter, NotificationEvent, SampleRate,
     },
-    streamer::StreamerMsg,
+    streamer::{PhoneControl, StreamerMsg},
+};
+#[cfg(feature = "adb")]
+use crate::{
+    config::WirelessAdbDevice,
+    streamer::{AdbDevice, MdnsService},
 };
 
 #[derive(Debug, Clone)]
@@ -14,20 +23,57 @@ pub enum AppMsg {
     ChangeConnectionMode(ConnectionMode),
     Streamer(StreamerMsg),
     Device(AudioDevice),
+    /// The output devices changed
+    AudioDevices(Vec<AudioDevice>),
+    OutputStream(OutputStreamEvent),
+    PhoneControl(PhoneControl),
     #[cfg(target_os = "linux")]
     SelectedHost(HostId),
     Adapter(NetworkAdapter),
+    SwitchProfile(String),
     Connect,
     Stop,
     ToggleSettingsWindow,
     Config(ConfigMsg),
+    /// Whether the app starts at login, once the request is done
+    StartAtLoginChanged(Result<bool, String>),
     RefreshAudioDevices,
     RefreshNetworkAdapters,
+    #[cfg(feature = "usb")]
+    RefreshUsbDevices,
+    #[cfg(feature = "usb")]
+    UsbDevices(Result<Vec<UsbDevice>, String>),
+    #[cfg(feature = "usb")]
+    UsbDevice(UsbDevice),
+    #[cfg(feature = "usb")]
+    UsbHotplug(UsbHotplug),
+    #[cfg(feature = "adb")]
+    RefreshAdbDevices,
+    #[cfg(feature = "adb")]
+    AdbDevices(Result<Vec<AdbDevice>, String>),
+    #[cfg(feature = "adb")]
+    AdbDevice(AdbDevice),
+    #[cfg(feature = "adb")]
+    AdbPairAddress(String),
+    #[cfg(feature = "adb")]
+    AdbPairCode(String),
+    #[cfg(feature = "adb")]
+    AdbDiscoverPairing,
+    #[cfg(feature = "adb")]
+    AdbPairingServices(Result<Vec<MdnsService>, String>),
+    #[cfg(feature = "adb")]
+    AdbPair,
+    #[cfg(feature = "adb")]
+    AdbConnectWireless(WirelessAdbDevice),
+    /// The paired device and its adb serial
+    #[cfg(feature = "adb")]
+    AdbWirelessConnected(Result<(WirelessAdbDevice, String), String>),
+    #[cfg(feature = "adb")]
+    AdbForgetWireless(WirelessAdbDevice),
     HideWindow,
     ShowWindow,
     Menu(MenuMsg),
     LinkClicked(String),
-    #[cfg(not(target_os = "linux"))]
     SystemTray(SystemTrayMsg),
     Exit,
 }
@@ -38,10 +84,16 @@ pub enum ConfigMsg {
     ChannelCount(ChannelCount),
     AudioFormat(AudioFormat),
     UseRecommendedFormat,
+    SendFormatToPhone,
     ResetDenoiseSettings,
     StartAtLogin(bool),
     StartMinimized(bool),
     AutoConnect(bool),
+    NotifyEvent(NotificationEvent, bool),
+    NotifyOnlyWhenHidden(bool),
+    KeepListening(bool),
+    CaptureTraffic(bool),
+    ReconnectMaxRetries(i32),
     DeNoise(bool),
     DeNoiseKind(DenoiseKind),
     SpeexNoiseSuppress(i32),
@@ -57,6 +109,22 @@ pub enum ConfigMsg {
     ToggleAboutWindow,
     PortTextInput(String),
     PortSave,
+    PreferredDevicesInput(String),
+    PreferredDevicesSave,
+    BindAllInterfaces(bool),
+    /// Also listen on the adapter with this address, or stop
+    ExtraAdapter(IpAddr, bool),
+    AllowedSubnetsInput(String),
+    AllowedSubnetsSave,
+    ReplayFileInput(String),
+    ReplayFileSave,
+    ReplayLoop(bool),
+    ProfileNameInput(String),
+    ProfileSave,
+    ProfileDelete,
+    ProfileFileInput(String),
+    ProfileImport,
+    ProfileExport,
     PostAudioEffect(AudioEffect),
 }
 
@@ -1,8 +1,8 @@
 pub mod app;
 mod icon;
 mod message;
+mod notification;
 
-#[cfg(not(target_os = "linux"))]
 mod tray;
 mod view;
 mod wave;
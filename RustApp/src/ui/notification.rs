@@ -0,0 +1,91 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use notify_rust::Notification;
+
+use crate::config::NotificationEvent;
+
+pub fn show(body: &str) {
+    let _ = Notification::new()
+        .summary("AndroidMic")
+        .body(body)
+        .auto_icon()
+        .show()
+        .map_err(|e| {
+            error!("failed to show notification: {e}");
+        });
+}
+
+/// Show notifications for events, without flooding the desktop
+/// when a flaky connection repeats them.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    last_shown: HashMap<NotificationEvent, Instant>,
+}
+
+impl Notifier {
+    /// Minimum time between two notifications of the same event
+    fn interval(event: NotificationEvent) -> Duration {
+        match event {
+            NotificationEvent::Connected
+            | NotificationEvent::Disconnected
+            | NotificationEvent::MinimizedToTray => Duration::from_secs(10),
+            NotificationEvent::Error | NotificationEvent::DeviceLost => Duration::from_secs(30),
+            NotificationEvent::BufferOverrun | NotificationEvent::LowBattery => {
+                Duration::from_secs(60)
+            }
+        }
+    }
+
+    pub fn notify(&mut self, event: NotificationEvent, body: &str) {
+        if self.should_show(event, Instant::now()) {
+            show(body);
+        }
+    }
+
+    fn should_show(&mut self, event: NotificationEvent, now: Instant) -> bool {
+        if let Some(last) = self.last_shown.get(&event)
+            && now.duration_since(*last) < Self::interval(event)
+        {
+            debug!("notification rate limited: {event:?}");
+            return false;
+        }
+
+        self.last_shown.insert(event, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_events_are_rate_limited() {
+        let mut notifier = Notifier::default();
+        let start = Instant::now();
+
+        assert!(notifier.should_show(NotificationEvent::Connected, start));
+        assert!(
+            !notifier.should_show(NotificationEvent::Connected, start + Duration::from_secs(5))
+        );
+        // other events have their own limit
+        assert!(notifier.should_show(NotificationEvent::Disconnected, start));
+        assert!(notifier.should_show(
+            NotificationEvent::Connected,
+            start + Duration::from_secs(10)
+        ));
+
+        assert!(notifier.should_show(NotificationEvent::BufferOverrun, start));
+        assert!(!notifier.should_show(
+            NotificationEvent::BufferOverrun,
+            start + Duration::from_secs(59)
+        ));
+        assert!(notifier.should_show(
+            NotificationEvent::BufferOverrun,
+            start + Duration::from_secs(60)
+        ));
+    }
+}
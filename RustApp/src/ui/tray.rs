@@ -3,6 +3,7 @@ use futures::SinkExt;
 use std::sync::Arc;
 use tokio::sync::{Mutex, mpsc};
 
+#[cfg(not(target_os = "linux"))]
 use tray_icon::{
     TrayIcon, TrayIconBuilder, TrayIconEvent,
     menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
@@ -23,12 +24,14 @@ pub struct SystemTrayStream {
     receiver: Arc<Mutex<mpsc::UnboundedReceiver<SystemTrayMsg>>>,
 }
 
+#[cfg(not(target_os = "linux"))]
 pub struct SystemTray {
     tray_icon: TrayIcon,
     item_connect: MenuItem,
     item_disconnect: MenuItem,
 }
 
+#[cfg(not(target_os = "linux"))]
 impl SystemTray {
     pub fn new() -> anyhow::Result<(Self, SystemTrayStream)> {
         let item_show = MenuItem::new(fl!("tray_show_window"), true, None);
@@ -101,6 +104,111 @@ impl SystemTray {
     }
 }
 
+/// StatusNotifierItem exposed over D-Bus, shown by most Linux desktops
+#[cfg(target_os = "linux")]
+pub struct SystemTray {
+    handle: ksni::blocking::Handle<SniTray>,
+}
+
+#[cfg(target_os = "linux")]
+struct SniTray {
+    sender: mpsc::UnboundedSender<SystemTrayMsg>,
+    disconnected: bool,
+    status: String,
+}
+
+#[cfg(target_os = "linux")]
+impl SniTray {
+    fn item(label: String, enabled: bool, msg: SystemTrayMsg) -> ksni::MenuItem<Self> {
+        ksni::menu::StandardItem {
+            label,
+            enabled,
+            activate: Box::new(move |tray: &mut Self| {
+                let _ = tray.sender.send(msg.clone());
+            }),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ksni::Tray for SniTray {
+    fn id(&self) -> String {
+        crate::utils::APP_ID.into()
+    }
+
+    fn title(&self) -> String {
+        "AndroidMic".into()
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        tray_icon!("icon").into_iter().collect()
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip {
+            title: format!("AndroidMic - {}", self.status),
+            ..Default::default()
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        let _ = self.sender.send(SystemTrayMsg::Show);
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        vec![
+            Self::item(fl!("tray_show_window"), true, SystemTrayMsg::Show),
+            Self::item(
+                fl!("tray_connect"),
+                self.disconnected,
+                SystemTrayMsg::Connect,
+            ),
+            Self::item(
+                fl!("tray_disconnect"),
+                !self.disconnected,
+                SystemTrayMsg::Disconnect,
+            ),
+            ksni::MenuItem::Separator,
+            Self::item(fl!("tray_exit"), true, SystemTrayMsg::Exit),
+        ]
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl SystemTray {
+    pub fn new() -> anyhow::Result<(Self, SystemTrayStream)> {
+        use ksni::blocking::TrayMethods;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let tray = SniTray {
+            sender,
+            disconnected: true,
+            status: String::new(),
+        };
+
+        // fails when no StatusNotifierWatcher is running, like on GNOME without extension
+        let handle = tray.spawn()?;
+
+        Ok((
+            Self { handle },
+            SystemTrayStream {
+                receiver: Arc::new(Mutex::new(receiver)),
+            },
+        ))
+    }
+
+    pub fn update_menu_state(&mut self, disconnected: bool, status: &str) {
+        let status = status.to_string();
+        let _ = self.handle.update(move |tray| {
+            tray.disconnected = disconnected;
+            tray.status = status;
+        });
+    }
+}
+
 impl SystemTrayStream {
     pub fn sub(self) -> impl Stream<Item = SystemTrayMsg> {
         let receiver_arc = self.receiver.clone();
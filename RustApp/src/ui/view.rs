@@ -15,14 +15,18 @@ use cosmic::{
 use cpal::traits::DeviceTrait;
 
 use super::{
-    app::{AppState, ConnectionState},
+    app::{AppState, ConnectionState, OutputDeviceState, telemetry_summary},
     message::{AppMsg, ConfigMsg},
 };
+#[cfg(feature = "adb")]
+use crate::streamer::AdbDeviceState;
 use crate::{
     config::{
-        AppTheme, AudioEffect, AudioFormat, ChannelCount, ConnectionMode, DenoiseKind, SampleRate,
+        AppTheme, AudioEffect, AudioFormat, ChannelCount, ConnectionMode, DenoiseKind, MicSource,
+        NotificationEvent,
     },
     fl,
+    streamer::PhoneControl,
     ui::message::MenuMsg,
     utils::APP,
     widget_icon_button, widget_icon_handle,
@@ -50,11 +54,15 @@ pub fn main_window(app: &AppState) -> Element<'_, AppMsg> {
                 .height(Length::Fill)
                 .spacing(20)
                 .align_x(Horizontal::Center)
+                .push_maybe(profile(app))
                 .push_maybe(
                     (connection_mode == ConnectionMode::Tcp
                         || connection_mode == ConnectionMode::Udp)
                         .then(|| network_adapter(app)),
                 )
+                .push_maybe(adb_device(app, connection_mode))
+                .push_maybe(usb_device(app, connection_mode))
+                .push_maybe((connection_mode == ConnectionMode::File).then(|| replay_file(app)))
                 .push(audio(app))
                 .push(vertical_space())
                 .push(connection_type(app)),
@@ -164,16 +172,71 @@ fn audio(app: &AppState) -> Element<'_, AppMsg> {
                         .width(Length::Shrink),
                 ),
         )
+        .push_maybe(match app.output_device_state {
+            OutputDeviceState::Ok => None,
+            OutputDeviceState::Lost => Some(text::caption(fl!("output_device_lost"))),
+            OutputDeviceState::Fallback => Some(text::caption(fl!("output_device_fallback"))),
+        })
+        .push_maybe(
+            app.audio_stream.as_ref().map(|stream| {
+                text::caption(fl!("output_format", format = stream.config.to_string()))
+            }),
+        )
         .push(button::text(fl!("settings")).on_press(AppMsg::ToggleSettingsWindow))
         .into()
 }
 
 fn network_adapter(app: &AppState) -> Element<'_, AppMsg> {
     let selected = app.network_adapter.as_ref();
+    let config = app.config.data();
+
+    if config.bind_all_interfaces {
+        return column()
+            .spacing(20)
+            .align_x(Horizontal::Center)
+            .push(text::title4(fl!("network_adapter")))
+            .push(
+                row()
+                    .width(Length::Fill)
+                    .align_y(Vertical::Center)
+                    .push(text(fl!("bind_all_interfaces")))
+                    .push(horizontal_space())
+                    .push(
+                        toggler(true)
+                            .on_toggle(|v| AppMsg::Config(ConfigMsg::BindAllInterfaces(v))),
+                    ),
+            )
+            .push(text::caption(fl!("reachable_addresses")))
+            .push(
+                app.reachable_addresses()
+                    .into_iter()
+                    .fold(column().spacing(5), |column, adapter| {
+                        column.push(text::caption(adapter.to_string()))
+                    }),
+            )
+            .push(
+                widget_icon_button!("refresh24")
+                    .on_press(AppMsg::RefreshNetworkAdapters)
+                    .class(cosmic::theme::Button::Text)
+                    .width(Length::Shrink),
+            )
+            .into();
+    }
+
     column()
         .spacing(20)
         .align_x(Horizontal::Center)
         .push(text::title4(fl!("network_adapter")))
+        .push(
+            row()
+                .width(Length::Fill)
+                .align_y(Vertical::Center)
+                .push(text(fl!("bind_all_interfaces")))
+                .push(horizontal_space())
+                .push(
+                    toggler(false).on_toggle(|v| AppMsg::Config(ConfigMsg::BindAllInterfaces(v))),
+                ),
+        )
         .push(
             row()
                 .width(Length::Fill)
@@ -194,6 +257,256 @@ fn network_adapter(app: &AppState) -> Element<'_, AppMsg> {
                         .width(Length::Shrink),
                 ),
         )
+        .push(text::caption(fl!("extra_adapters")))
+        .push(
+            app.network_adapters
+                .iter()
+                .filter(|adapter| selected.is_none_or(|selected| selected.ip != adapter.ip))
+                .fold(column().spacing(5), |column, adapter| {
+                    let ip = adapter.ip;
+                    column.push(
+                        row()
+                            .width(Length::Fill)
+                            .align_y(Vertical::Center)
+                            .push(text::caption(adapter.to_string()))
+                            .push(horizontal_space())
+                            .push(toggler(config.extra_adapters.contains(&ip)).on_toggle(
+                                move |v| AppMsg::Config(ConfigMsg::ExtraAdapter(ip, v)),
+                            )),
+                    )
+                }),
+        )
+        .into()
+}
+
+#[cfg(feature = "usb")]
+fn usb_device(app: &AppState, connection_mode: ConnectionMode) -> Option<Element<'_, AppMsg>> {
+    if connection_mode != ConnectionMode::Usb {
+        return None;
+    }
+
+    let serial = app.config.data().usb_device.as_ref();
+    let selected = app
+        .usb_devices
+        .iter()
+        .find(|device| device.serial.as_ref() == serial);
+
+    let status = if app.usb_devices.is_empty() {
+        Some(fl!("usb_no_device"))
+    } else if serial.is_some() && selected.is_none() {
+        Some(fl!("usb_device_missing"))
+    } else if serial.is_none() && app.usb_devices.len() > 1 {
+        Some(fl!("usb_choose_device"))
+    } else {
+        None
+    };
+
+    Some(
+        column()
+            .spacing(20)
+            .align_x(Horizontal::Center)
+            .push(text::title4(fl!("usb_device")))
+            .push(
+                row()
+                    .width(Length::Fill)
+                    .spacing(5)
+                    .push(
+                        pick_list(
+                            app.usb_devices.clone(),
+                            selected.cloned(),
+                            AppMsg::UsbDevice,
+                        )
+                        .placeholder(serial.map_or(fl!("none"), Clone::clone))
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        widget_icon_button!("refresh24")
+                            .on_press(AppMsg::RefreshUsbDevices)
+                            .class(cosmic::theme::Button::Text)
+                            .width(Length::Shrink),
+                    ),
+            )
+            .push_maybe(status.map(text::caption))
+            .into(),
+    )
+}
+
+#[cfg(not(feature = "usb"))]
+fn usb_device(_app: &AppState, _connection_mode: ConnectionMode) -> Option<Element<'_, AppMsg>> {
+    None
+}
+
+#[cfg(feature = "adb")]
+fn adb_device(app: &AppState, connection_mode: ConnectionMode) -> Option<Element<'_, AppMsg>> {
+    if connection_mode != ConnectionMode::Adb {
+        return None;
+    }
+
+    let serial = app.config.data().adb_device.as_ref();
+    let selected = app
+        .adb_devices
+        .iter()
+        .find(|device| Some(&device.serial) == serial);
+
+    let status = if app.adb_devices.is_empty() {
+        Some(fl!("adb_no_device"))
+    } else if selected.is_some_and(|device| device.state == AdbDeviceState::Unauthorized) {
+        Some(fl!("adb_unauthorized"))
+    } else if serial.is_some() && selected.is_none() {
+        Some(fl!("adb_device_missing"))
+    } else {
+        None
+    };
+
+    Some(
+        column()
+            .spacing(20)
+            .align_x(Horizontal::Center)
+            .push(text::title4(fl!("adb_device")))
+            .push(
+                row()
+                    .width(Length::Fill)
+                    .spacing(5)
+                    .push(
+                        pick_list(
+                            app.adb_devices.clone(),
+                            selected.cloned(),
+                            AppMsg::AdbDevice,
+                        )
+                        .placeholder(serial.map_or(fl!("none"), Clone::clone))
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        widget_icon_button!("refresh24")
+                            .on_press(AppMsg::RefreshAdbDevices)
+                            .class(cosmic::theme::Button::Text)
+                            .width(Length::Shrink),
+                    ),
+            )
+            .push_maybe(status.map(text::caption))
+            .push(wireless_adb(app))
+            .into(),
+    )
+}
+
+#[cfg(feature = "adb")]
+fn wireless_adb(app: &AppState) -> Element<'_, AppMsg> {
+    let busy = app.adb_wireless_busy;
+    let can_pair = !busy && !app.adb_pair_address.is_empty() && !app.adb_pair_code.is_empty();
+
+    column()
+        .spacing(10)
+        .push(text::heading(fl!("adb_wireless")))
+        .extend(app.config.data().wireless_adb_devices.iter().map(|device| {
+            row()
+                .align_y(Vertical::Center)
+                .spacing(5)
+                .push(text::caption(device.to_string()))
+                .push(horizontal_space())
+                .push(
+                    button::text(fl!("adb_wireless_connect")).on_press_maybe(
+                        (!busy).then(|| AppMsg::AdbConnectWireless(device.clone())),
+                    ),
+                )
+                .push(
+                    button::text(fl!("adb_wireless_forget"))
+                        .on_press(AppMsg::AdbForgetWireless(device.clone())),
+                )
+                .into()
+        }))
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(5)
+                .push(
+                    text_input(&fl!("adb_pair_address"), &app.adb_pair_address)
+                        .on_input(AppMsg::AdbPairAddress)
+                        .width(Length::Fill),
+                )
+                .push(
+                    button::text(fl!("adb_discover"))
+                        .on_press_maybe((!busy).then_some(AppMsg::AdbDiscoverPairing)),
+                ),
+        )
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(5)
+                .push(
+                    text_input(&fl!("adb_pair_code"), &app.adb_pair_code)
+                        .on_input(AppMsg::AdbPairCode)
+                        .width(Length::Fill),
+                )
+                .push(
+                    button::text(fl!("adb_pair"))
+                        .on_press_maybe(can_pair.then_some(AppMsg::AdbPair)),
+                ),
+        )
+        .into()
+}
+
+#[cfg(not(feature = "adb"))]
+fn adb_device(_app: &AppState, _connection_mode: ConnectionMode) -> Option<Element<'_, AppMsg>> {
+    None
+}
+
+fn profile(app: &AppState) -> Option<Element<'_, AppMsg>> {
+    let config = app.config.data();
+
+    if config.profiles.is_empty() {
+        return None;
+    }
+
+    let names: Vec<String> = config
+        .profiles
+        .iter()
+        .map(|profile| profile.name.clone())
+        .collect();
+
+    Some(
+        column()
+            .spacing(20)
+            .align_x(Horizontal::Center)
+            .push(text::title4(fl!("profile")))
+            .push(
+                pick_list(names, config.active_profile.clone(), AppMsg::SwitchProfile)
+                    .placeholder(fl!("none"))
+                    .width(Length::Fill),
+            )
+            .into(),
+    )
+}
+
+fn replay_file(app: &AppState) -> Element<'_, AppMsg> {
+    column()
+        .spacing(20)
+        .align_x(Horizontal::Center)
+        .push(text::title4(fl!("replay_file")))
+        .push(
+            row()
+                .width(Length::Fill)
+                .align_y(Vertical::Center)
+                .spacing(5)
+                .push(
+                    text_input(&fl!("replay_file_placeholder"), &app.replay_file_input)
+                        .on_input(|text| AppMsg::Config(ConfigMsg::ReplayFileInput(text)))
+                        .width(Length::Fill),
+                )
+                .push(
+                    button::text(fl!("save")).on_press(AppMsg::Config(ConfigMsg::ReplayFileSave)),
+                ),
+        )
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .push(text(fl!("replay_loop")))
+                .push(horizontal_space())
+                .push(
+                    toggler(app.config.data().replay_loop).on_toggle(|replay_loop| {
+                        AppMsg::Config(ConfigMsg::ReplayLoop(replay_loop))
+                    }),
+                ),
+        )
         .into()
 }
 
@@ -249,9 +562,86 @@ fn connection_type(app: &AppState) -> Element<'_, AppMsg> {
                     {
                         Option::<Element<AppMsg>>::None
                     }
-                }),
+                })
+                .push(radio(
+                    text(fl!("connection_file")),
+                    &ConnectionMode::File,
+                    Some(connection_mode),
+                    |mode| AppMsg::ChangeConnectionMode(*mode),
+                )),
         )
         .push(connect_button(app))
+        .push_maybe(
+            app.peer
+                .map(|peer| text::caption(fl!("connected_peer", peer = peer.to_string()))),
+        )
+        .push_maybe(app.rejected.map(|(from, count)| {
+            text::caption(fl!(
+                "rejected_datagrams",
+                count = count.to_string(),
+                sender = from.to_string()
+            ))
+        }))
+        .push_maybe(
+            app.rtt
+                .map(|rtt| text::caption(fl!("rtt", rtt = rtt.as_millis().to_string()))),
+        )
+        .push_maybe(
+            (app.connection_state == ConnectionState::Connected
+                && (app.phone_control || app.telemetry.is_some()))
+            .then(|| phone_control(app)),
+        )
+        .into()
+}
+
+fn phone_control(app: &AppState) -> Element<'_, AppMsg> {
+    column()
+        .spacing(10)
+        .align_x(Horizontal::Center)
+        .push(text::title4(fl!("phone")))
+        .push_maybe(app.telemetry.as_ref().map(|telemetry| {
+            column()
+                .align_x(Horizontal::Center)
+                .push(text::caption(telemetry_summary(telemetry)))
+                .push_maybe((!telemetry.app_version.is_empty()).then(|| {
+                    text::caption(fl!(
+                        "phone_app_version",
+                        version = telemetry.app_version.as_str()
+                    ))
+                }))
+        }))
+        // only phones announcing the control feature accept commands
+        .push_maybe(app.phone_control.then(|| {
+            column()
+                .spacing(10)
+                .align_x(Horizontal::Center)
+                .push(
+                    row()
+                        .align_y(Vertical::Center)
+                        .push(text(fl!("phone_mute")))
+                        .push(horizontal_space())
+                        .push(
+                            toggler(app.phone_muted)
+                                .on_toggle(|muted| AppMsg::PhoneControl(PhoneControl::Mute(muted))),
+                        ),
+                )
+                .push(
+                    row()
+                        .align_y(Vertical::Center)
+                        .spacing(5)
+                        .push(text(fl!("phone_mic_source")))
+                        .push(horizontal_space())
+                        .push(pick_list(
+                            MicSource::VALUES,
+                            Some(&app.phone_mic_source),
+                            |source| AppMsg::PhoneControl(PhoneControl::MicSource(source)),
+                        )),
+                )
+                .push(
+                    button::destructive(fl!("phone_stop_streaming"))
+                        .on_press(AppMsg::PhoneControl(PhoneControl::StopStreaming)),
+                )
+        }))
         .into()
 }
 
@@ -281,7 +671,7 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                             .push(text(fl!("sample_rate")))
                             .push(horizontal_space())
                             .push(pick_list(
-                                SampleRate::VALUES,
+                                app.sample_rates.clone(),
                                 Some(&config.sample_rate),
                                 ConfigMsg::SampleRate,
                             )),
@@ -316,24 +706,117 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                                     .on_press(ConfigMsg::UseRecommendedFormat),
                             )
                             .push(horizontal_space()),
+                    )
+                    .add_maybe(
+                        (app.connection_state == ConnectionState::Connected && app.phone_control)
+                            .then(|| {
+                                row()
+                                    .push(horizontal_space())
+                                    .push(
+                                        button::text(fl!("send_format_to_phone"))
+                                            .on_press(ConfigMsg::SendFormatToPhone),
+                                    )
+                                    .push(horizontal_space())
+                            }),
                     ),
             )
             .push(
-                settings::section().title(fl!("title_connection")).add(
-                    row()
-                        .width(Length::Fill)
-                        .align_y(Vertical::Center)
+                settings::section().title(fl!("title_output_device")).add(
+                    column()
                         .spacing(5)
-                        .push(text(fl!("port")))
-                        .push(horizontal_space())
+                        .push(text(fl!("preferred_devices")))
                         .push(
-                            text_input("", &app.port_input)
-                                .on_input(ConfigMsg::PortTextInput)
-                                .width(Length::Fixed(150.0)),
-                        )
-                        .push(button::text(fl!("save")).on_press(ConfigMsg::PortSave)),
+                            row()
+                                .width(Length::Fill)
+                                .align_y(Vertical::Center)
+                                .spacing(5)
+                                .push(
+                                    text_input(
+                                        &fl!("preferred_devices_placeholder"),
+                                        &app.preferred_devices_input,
+                                    )
+                                    .on_input(ConfigMsg::PreferredDevicesInput)
+                                    .width(Length::Fill),
+                                )
+                                .push(
+                                    button::text(fl!("save"))
+                                        .on_press(ConfigMsg::PreferredDevicesSave),
+                                ),
+                        ),
                 ),
             )
+            .push(
+                settings::section()
+                    .title(fl!("title_connection"))
+                    .add(
+                        row()
+                            .width(Length::Fill)
+                            .align_y(Vertical::Center)
+                            .spacing(5)
+                            .push(text(fl!("port")))
+                            .push(horizontal_space())
+                            .push(
+                                text_input("", &app.port_input)
+                                    .on_input(ConfigMsg::PortTextInput)
+                                    .width(Length::Fixed(150.0)),
+                            )
+                            .push(button::text(fl!("save")).on_press(ConfigMsg::PortSave)),
+                    )
+                    .add(
+                        column().spacing(5).push(text(fl!("allowed_subnets"))).push(
+                            row()
+                                .width(Length::Fill)
+                                .align_y(Vertical::Center)
+                                .spacing(5)
+                                .push(
+                                    text_input(
+                                        &fl!("allowed_subnets_placeholder"),
+                                        &app.allowed_subnets_input,
+                                    )
+                                    .on_input(ConfigMsg::AllowedSubnetsInput)
+                                    .width(Length::Fill),
+                                )
+                                .push(
+                                    button::text(fl!("save"))
+                                        .on_press(ConfigMsg::AllowedSubnetsSave),
+                                ),
+                        ),
+                    )
+                    .add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(fl!("keep_listening")))
+                            .push(horizontal_space())
+                            .push(
+                                toggler(config.keep_listening).on_toggle(ConfigMsg::KeepListening),
+                            ),
+                    )
+                    .add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .spacing(10)
+                            .push(text(fl!("reconnect_max_retries")))
+                            .push(text(format!("{}", config.reconnect_max_retries)))
+                            .push(
+                                widget::slider(
+                                    0..=20,
+                                    config.reconnect_max_retries as i32,
+                                    ConfigMsg::ReconnectMaxRetries,
+                                )
+                                .step(1),
+                            ),
+                    )
+                    .add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(fl!("capture_traffic")))
+                            .push(horizontal_space())
+                            .push(
+                                toggler(config.capture_traffic)
+                                    .on_toggle(ConfigMsg::CaptureTraffic),
+                            ),
+                    ),
+            )
             .push(
                 settings::section()
                     .title(fl!("denoise"))
@@ -496,10 +979,84 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                         )),
                 ),
             )
+            .push(
+                settings::section()
+                    .title(fl!("title_profiles"))
+                    .add(
+                        column().spacing(5).push(text(fl!("profile_name"))).push(
+                            row()
+                                .width(Length::Fill)
+                                .align_y(Vertical::Center)
+                                .spacing(5)
+                                .push(
+                                    text_input(
+                                        &fl!("profile_name_placeholder"),
+                                        &app.profile_name_input,
+                                    )
+                                    .on_input(ConfigMsg::ProfileNameInput)
+                                    .width(Length::Fill),
+                                )
+                                .push(button::text(fl!("save")).on_press(ConfigMsg::ProfileSave))
+                                .push(
+                                    button::destructive(fl!("delete"))
+                                        .on_press(ConfigMsg::ProfileDelete),
+                                ),
+                        ),
+                    )
+                    .add(
+                        column().spacing(5).push(text(fl!("profile_file"))).push(
+                            row()
+                                .width(Length::Fill)
+                                .align_y(Vertical::Center)
+                                .spacing(5)
+                                .push(
+                                    text_input(
+                                        &fl!("profile_file_placeholder"),
+                                        &app.profile_file_input,
+                                    )
+                                    .on_input(ConfigMsg::ProfileFileInput)
+                                    .width(Length::Fill),
+                                )
+                                .push(
+                                    button::text(fl!("profile_import"))
+                                        .on_press(ConfigMsg::ProfileImport),
+                                )
+                                .push(
+                                    button::text(fl!("profile_export"))
+                                        .on_press(ConfigMsg::ProfileExport),
+                                ),
+                        ),
+                    ),
+            )
+            .push(
+                NotificationEvent::VALUES.iter().fold(
+                    settings::section().title(fl!("title_notifications")).add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(fl!("notify_only_when_hidden")))
+                            .push(horizontal_space())
+                            .push(
+                                toggler(config.notify_only_when_hidden)
+                                    .on_toggle(ConfigMsg::NotifyOnlyWhenHidden),
+                            ),
+                    ),
+                    |section, &event| {
+                        section.add(
+                            row()
+                                .align_y(Vertical::Center)
+                                .push(text(event.to_string()))
+                                .push(horizontal_space())
+                                .push(toggler(config.notify_events.contains(&event)).on_toggle(
+                                    move |enabled| ConfigMsg::NotifyEvent(event, enabled),
+                                )),
+                        )
+                    },
+                ),
+            )
             .push(
                 settings::section()
                     .title(fl!("title_app"))
-                    .add_maybe(if cfg!(target_os = "windows") {
+                    .add_maybe(if cfg!(any(target_os = "windows", target_os = "linux")) {
                         Some(
                             row()
                                 .align_y(Vertical::Center)
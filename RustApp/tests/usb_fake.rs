@@ -0,0 +1,86 @@
+//! The usb streamer against the in-memory phone of the fake backend, through the public api
+
+use android_mic::streamer::{
+    PROTOCOL_VERSION, StreamerMsg,
+    message::{ConnectMessage, MessageWrapper, TelemetryMessage, message_wrapper::Payload},
+    test_support::{FakeBackend, FakeUsbStreamer, stream_config},
+};
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+async fn send(phone: &mut DuplexStream, payload: Payload) {
+    let buf = MessageWrapper {
+        payload: Some(payload),
+    }
+    .encode_to_vec();
+    phone
+        .write_all(&(buf.len() as u32).to_be_bytes())
+        .await
+        .unwrap();
+    phone.write_all(&buf).await.unwrap();
+}
+
+async fn read_message(phone: &mut DuplexStream) -> MessageWrapper {
+    let len = phone.read_u32().await.unwrap();
+    let mut buf = vec![0; len as usize];
+    phone.read_exact(&mut buf).await.unwrap();
+    MessageWrapper::decode(buf.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn phone_connects_and_sends_telemetry() {
+    let backend = FakeBackend::new("PHONE1");
+    let mut streamer = FakeUsbStreamer::new(backend.clone(), None, stream_config())
+        .await
+        .unwrap();
+    assert!(matches!(streamer.status(), StreamerMsg::Listening { .. }));
+
+    let mut phone = backend.take_phone_link().unwrap();
+    send(
+        &mut phone,
+        Payload::Connect(ConnectMessage {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: "integration test".into(),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    assert!(matches!(
+        streamer.next().await,
+        Ok(Some(StreamerMsg::Connected { .. }))
+    ));
+    assert!(matches!(
+        read_message(&mut phone).await.payload,
+        Some(Payload::ConnectReply(reply)) if reply.error.is_empty()
+    ));
+    assert!(matches!(
+        streamer.next().await,
+        Ok(Some(StreamerMsg::PeerFeatures { .. }))
+    ));
+
+    send(
+        &mut phone,
+        Payload::Telemetry(TelemetryMessage {
+            device_name: "Pixel".into(),
+            battery_level: 80,
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    match streamer.next().await {
+        Ok(Some(StreamerMsg::Telemetry(telemetry))) => {
+            assert_eq!(telemetry.device_name, "Pixel");
+            assert_eq!(telemetry.battery_level, Some(80));
+        }
+        other => panic!("expected telemetry, got {other:?}"),
+    }
+
+    backend.unplug();
+    drop(phone);
+    assert!(matches!(
+        streamer.next().await,
+        Ok(Some(StreamerMsg::Listening { .. }))
+    ));
+}